@@ -0,0 +1,222 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+
+const MANIFEST_PATH: &str = "/assets.toml";
+// Used when the resources folder doesn't have the manifest, so the missing files still get listed
+const DEFAULT_MANIFEST: &str = include_str!("../assets/assets.toml");
+
+/// Every file the game loads, by name
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    images: HashMap<String, String>,
+    #[serde(default)]
+    sounds: HashMap<String, String>,
+    #[serde(default)]
+    fonts: HashMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageHandle(usize);
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoundHandle(usize);
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontHandle(usize);
+
+/// Images, sounds and fonts, loaded once when the game starts.
+/// Anything that can't be found is replaced by a placeholder, the first entry of each list.
+pub struct Assets {
+    images: Vec<ggez::graphics::Image>,
+    image_names: HashMap<String, ImageHandle>,
+    sounds: Vec<ggez::audio::SoundData>,
+    sound_names: HashMap<String, SoundHandle>,
+    fonts: Vec<ggez::graphics::Font>,
+    font_names: HashMap<String, FontHandle>,
+}
+
+impl Assets {
+    pub fn load(ctx: &mut ggez::Context) -> ggez::GameResult<Self> {
+        let resources = ggez::filesystem::resources_dir(ctx).to_path_buf();
+        let mut missing: Vec<String> = Vec::new(); // what went wrong, with the path the file was expected at
+
+        let mut content = String::new();
+        let read = ggez::filesystem::open(ctx, MANIFEST_PATH).and_then(|mut file| {
+            file.read_to_string(&mut content)
+                .map_err(ggez::GameError::from)
+        });
+        if let Err(e) = read {
+            missing.push(format!(
+                "'{}' ({}), expected at '{}'",
+                MANIFEST_PATH,
+                e,
+                resources
+                    .join(MANIFEST_PATH.trim_start_matches('/'))
+                    .display()
+            ));
+            content = DEFAULT_MANIFEST.to_string();
+        }
+        let manifest: Manifest = match toml::from_str(&content) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                missing.push(format!("'{}' could not be parsed: {}", MANIFEST_PATH, e));
+                toml::from_str(DEFAULT_MANIFEST).unwrap()
+            }
+        };
+        let mut report = |kind: &str, path: &str, e: ggez::GameError| {
+            missing.push(format!(
+                "{} '{}' ({}), expected at '{}'",
+                kind,
+                path,
+                e,
+                resources.join(path.trim_start_matches('/')).display()
+            ))
+        };
+
+        // A magenta and black checker, hard to miss
+        let mut images = vec![ggez::graphics::Image::from_rgba8(
+            ctx,
+            2,
+            2,
+            &[
+                255, 0, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 0, 255, 255,
+            ],
+        )?];
+        let mut image_names = HashMap::new();
+        for (name, path) in manifest.images.iter() {
+            match ggez::graphics::Image::new(ctx, path) {
+                Ok(image) => {
+                    println!("Loading: '{}'", path);
+                    image_names.insert(name.clone(), ImageHandle(images.len()));
+                    images.push(image);
+                }
+                Err(e) => {
+                    report("Image", path, e);
+                    image_names.insert(name.clone(), ImageHandle(0));
+                }
+            }
+        }
+
+        let mut sounds = vec![silence()];
+        let mut sound_names = HashMap::new();
+        for (name, path) in manifest.sounds.iter() {
+            match ggez::audio::SoundData::new(ctx, path) {
+                Ok(sound) => {
+                    println!("Loading: '{}'", path);
+                    sound_names.insert(name.clone(), SoundHandle(sounds.len()));
+                    sounds.push(sound);
+                }
+                Err(e) => {
+                    report("Sound", path, e);
+                    sound_names.insert(name.clone(), SoundHandle(0));
+                }
+            }
+        }
+
+        let mut fonts = vec![ggez::graphics::Font::default()];
+        let mut font_names = HashMap::new();
+        for (name, path) in manifest.fonts.iter() {
+            match ggez::graphics::Font::new(ctx, path) {
+                Ok(font) => {
+                    println!("Loading: '{}'", path);
+                    font_names.insert(name.clone(), FontHandle(fonts.len()));
+                    fonts.push(font);
+                }
+                Err(e) => {
+                    report("Font", path, e);
+                    font_names.insert(name.clone(), FontHandle(0));
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            eprintln!(
+                "[WARNING] {} asset(s) missing, using placeholders. \
+                 Copy the content of the assets folder in '{}':",
+                missing.len(),
+                resources.display()
+            );
+            for line in missing.iter() {
+                eprintln!("  - {}", line);
+            }
+        }
+
+        Ok(Assets {
+            images,
+            image_names,
+            sounds,
+            sound_names,
+            fonts,
+            font_names,
+        })
+    }
+
+    pub fn image(&self, name: &str) -> ImageHandle {
+        match self.image_names.get(name) {
+            Some(handle) => *handle,
+            None => {
+                eprintln!("[WARNING] No image named '{}' in '{}'", name, MANIFEST_PATH);
+                ImageHandle(0)
+            }
+        }
+    }
+    pub fn sound(&self, name: &str) -> SoundHandle {
+        match self.sound_names.get(name) {
+            Some(handle) => *handle,
+            None => {
+                eprintln!("[WARNING] No sound named '{}' in '{}'", name, MANIFEST_PATH);
+                SoundHandle(0)
+            }
+        }
+    }
+    pub fn font(&self, name: &str) -> FontHandle {
+        match self.font_names.get(name) {
+            Some(handle) => *handle,
+            None => {
+                eprintln!("[WARNING] No font named '{}' in '{}'", name, MANIFEST_PATH);
+                FontHandle(0)
+            }
+        }
+    }
+
+    pub fn get_image(&self, handle: ImageHandle) -> ggez::graphics::Image {
+        self.images[handle.0].clone()
+    }
+    pub fn get_font(&self, handle: FontHandle) -> ggez::graphics::Font {
+        self.fonts[handle.0]
+    }
+    /// Same as source, for sounds coming from somewhere on the map
+    pub fn spatial_source(
+        &self,
+        ctx: &mut ggez::Context,
+        handle: SoundHandle,
+    ) -> ggez::audio::SpatialSource {
+        ggez::audio::SpatialSource::from_data(ctx, self.sounds[handle.0].clone())
+            .expect("Could not create an audio source")
+    }
+    /// A new source playing the sound, they don't read the file again
+    pub fn source(&self, ctx: &mut ggez::Context, handle: SoundHandle) -> ggez::audio::Source {
+        ggez::audio::Source::from_data(ctx, self.sounds[handle.0].clone())
+            .expect("Could not create an audio source")
+    }
+}
+
+/// A valid wav file with a few silent samples
+fn silence() -> ggez::audio::SoundData {
+    let samples: u32 = 64;
+    let mut wav: Vec<u8> = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + samples * 2).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // size of the format chunk
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&44100u32.to_le_bytes());
+    wav.extend_from_slice(&(44100u32 * 2).to_le_bytes()); // bytes per second
+    wav.extend_from_slice(&2u16.to_le_bytes()); // bytes per sample
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(samples * 2).to_le_bytes());
+    wav.resize(wav.len() + samples as usize * 2, 0);
+    ggez::audio::SoundData::from_bytes(&wav)
+}
@@ -0,0 +1,255 @@
+use ggez::audio::SoundSource;
+use glam::Vec2;
+use std::collections::HashMap;
+
+use crate::assets;
+
+const HEARING_DISTANCE: f32 = 900.; // in pixels, further sounds are silent
+const LOW_HP_THRESHOLD: f32 = 0.25; // of the max hp, the warning loops under it
+const MUSIC_VOLUME: f32 = 0.3;
+const LOW_HP_VOLUME: f32 = 0.6;
+
+/// Sounds played by the game, with how many of them can overlap
+const POOLS: [(&str, Channel, usize); 3] = [
+    ("pistol", Channel::Sfx, 6),
+    ("little_pistol", Channel::Sfx, 16), // the minigun shoots faster than the sound lasts
+    ("spawn", Channel::Ui, 1),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    Sfx,
+    Music,
+    Ui,
+}
+
+/// Between 0 and 1, set in the settings menu
+#[derive(Clone)]
+pub struct Volumes {
+    pub master: f32,
+    pub sfx: f32,
+    pub music: f32,
+    pub ui: f32,
+}
+
+/// Copies of the same sound, so a new one doesn't cut the previous ones
+struct Pool {
+    channel: Channel,
+    sources: Vec<ggez::audio::SpatialSource>,
+    next: usize,
+}
+
+pub struct AudioManager {
+    pub volumes: Volumes,
+    pub muted: bool, // the sound effects only, while a replay catches up
+    pools: HashMap<&'static str, Pool>,
+    listener: Vec2,
+    music: Option<(String, ggez::audio::Source)>,
+    low_hp: ggez::audio::Source, // loops while the player is about to die
+}
+
+impl Default for Volumes {
+    fn default() -> Self {
+        Volumes {
+            master: 1.,
+            sfx: 1.,
+            music: 1.,
+            ui: 1.,
+        }
+    }
+}
+
+impl Volumes {
+    pub fn of(&self, channel: Channel) -> f32 {
+        self.master
+            * match channel {
+                Channel::Sfx => self.sfx,
+                Channel::Music => self.music,
+                Channel::Ui => self.ui,
+            }
+    }
+}
+
+impl Pool {
+    /// A source that isn't playing, or the one that started the longest time ago
+    fn free_source(&mut self) -> &mut ggez::audio::SpatialSource {
+        let index = match self.sources.iter().position(|source| source.stopped()) {
+            Some(index) => index,
+            None => {
+                let index = self.next;
+                self.next = (self.next + 1) % self.sources.len();
+                index
+            }
+        };
+        &mut self.sources[index]
+    }
+}
+
+/// The volume and pan of a sound heard from the listener, None when it's too far
+fn spatialize(listener: Vec2, pos: Vec2) -> Option<(f32, f32)> {
+    let offset = pos - listener;
+    let falloff = 1. - offset.length() / HEARING_DISTANCE;
+    if falloff <= 0. {
+        return None;
+    }
+    let pan = (offset.x / HEARING_DISTANCE).clamp(-1., 1.);
+    Some((falloff * falloff, pan))
+}
+
+impl AudioManager {
+    pub fn new(ctx: &mut ggez::Context, assets: &assets::Assets) -> Self {
+        let mut pools = HashMap::new();
+        for (name, channel, size) in POOLS.iter() {
+            let handle = assets.sound(name);
+            let sources = (0..*size)
+                .map(|_| {
+                    let mut source = assets.spatial_source(ctx, handle);
+                    source.set_ears([-1., 0., 0.], [1., 0., 0.]);
+                    source
+                })
+                .collect();
+            pools.insert(
+                *name,
+                Pool {
+                    channel: *channel,
+                    sources,
+                    next: 0,
+                },
+            );
+        }
+        let mut low_hp = assets.source(ctx, assets.sound("low_hp"));
+        low_hp.set_repeat(true);
+        AudioManager {
+            volumes: Volumes::default(),
+            muted: false,
+            pools,
+            listener: Vec2::ZERO,
+            music: None,
+            low_hp,
+        }
+    }
+    /// Where the player hears from
+    pub fn set_listener(&mut self, pos: Vec2) {
+        self.listener = pos;
+    }
+
+    /// Plays the sound as if it was on the player
+    pub fn play(&mut self, ctx: &mut ggez::Context, name: &str) {
+        self.play_with_volume(ctx, name, 1., 0.);
+    }
+    /// Quieter with the distance, and on the side it comes from
+    pub fn play_at(&mut self, ctx: &mut ggez::Context, name: &str, pos: Vec2) {
+        if let Some((volume, pan)) = spatialize(self.listener, pos) {
+            self.play_with_volume(ctx, name, volume, pan);
+        }
+    }
+    fn play_with_volume(&mut self, ctx: &mut ggez::Context, name: &str, volume: f32, pan: f32) {
+        let pool = match self.pools.get_mut(name) {
+            Some(pool) => pool,
+            None => {
+                eprintln!("[WARNING] No sound pool for '{}'", name);
+                return;
+            }
+        };
+        let volume = volume * self.volumes.of(pool.channel);
+        if volume <= 0. || self.muted {
+            return;
+        }
+        let source = pool.free_source();
+        // The ears are one unit away on each side, rodio lowers what's further away from them.
+        // The emitter stays between them so it only pans, the falloff is done by the volume.
+        source.set_position([pan, 0., 0.]);
+        source.set_volume(volume);
+        if let Err(e) = source.play(ctx) {
+            eprintln!("Could not play '{}': {}", name, e);
+        }
+    }
+
+    /// Loops the music, unless it's already the one playing
+    pub fn play_music(&mut self, ctx: &mut ggez::Context, assets: &assets::Assets, name: &str) {
+        if let Some((current, _)) = &self.music {
+            if current == name {
+                return;
+            }
+        }
+        if let Some((_, music)) = &mut self.music {
+            music.stop(ctx).unwrap_or_default();
+        }
+        let mut music = assets.source(ctx, assets.sound(name));
+        music.set_repeat(true);
+        music.set_volume(MUSIC_VOLUME * self.volumes.of(Channel::Music));
+        if let Err(e) = music.play(ctx) {
+            eprintln!("Could not play '{}': {}", name, e);
+        }
+        self.music = Some((name.to_string(), music));
+    }
+
+    /// Follows the volume settings and starts or stops the low hp warning
+    pub fn update(&mut self, ctx: &mut ggez::Context, hp_ratio: f32) {
+        if let Some((_, music)) = &mut self.music {
+            music.set_volume(MUSIC_VOLUME * self.volumes.of(Channel::Music));
+        }
+
+        let low = hp_ratio > 0. && hp_ratio < LOW_HP_THRESHOLD;
+        self.low_hp
+            .set_volume(LOW_HP_VOLUME * self.volumes.of(Channel::Ui));
+        if low && self.low_hp.stopped() {
+            if let Err(e) = self.low_hp.play(ctx) {
+                eprintln!("Could not play the low hp warning: {}", e);
+            }
+        } else if !low && !self.low_hp.stopped() {
+            self.low_hp.stop(ctx).unwrap_or_default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_master_volume_scales_every_channel() {
+        let volumes = Volumes {
+            master: 0.5,
+            sfx: 0.8,
+            music: 0.,
+            ui: 1.,
+        };
+        assert_eq!(volumes.of(Channel::Sfx), 0.4);
+        assert_eq!(volumes.of(Channel::Music), 0.);
+        assert_eq!(volumes.of(Channel::Ui), 0.5);
+    }
+
+    #[test]
+    fn sounds_on_the_listener_are_full_and_centered() {
+        assert_eq!(
+            spatialize(Vec2::new(10., 10.), Vec2::new(10., 10.)),
+            Some((1., 0.))
+        );
+    }
+
+    #[test]
+    fn sounds_fade_with_the_distance() {
+        let listener = Vec2::ZERO;
+        let (near, _) = spatialize(listener, Vec2::new(0., 100.)).unwrap();
+        let (far, _) = spatialize(listener, Vec2::new(0., 500.)).unwrap();
+        assert!(near > far);
+        assert_eq!(spatialize(listener, Vec2::new(0., HEARING_DISTANCE)), None);
+        assert_eq!(
+            spatialize(listener, Vec2::new(0., HEARING_DISTANCE * 2.)),
+            None
+        );
+    }
+
+    #[test]
+    fn sounds_pan_to_the_side_they_come_from() {
+        let listener = Vec2::ZERO;
+        let (_, left) = spatialize(listener, Vec2::new(-300., 0.)).unwrap();
+        let (_, right) = spatialize(listener, Vec2::new(300., 0.)).unwrap();
+        let (_, above) = spatialize(listener, Vec2::new(0., -300.)).unwrap();
+        assert!(left < 0.);
+        assert!(right > 0.);
+        assert_eq!(left, -right);
+        assert_eq!(above, 0.);
+    }
+}
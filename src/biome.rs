@@ -0,0 +1,136 @@
+use rand::Rng;
+
+use crate::{mapgen, monster};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Biome {
+    MossyCaves,
+    FloodedCaverns,
+    CrystalMines,
+    MagmaDepths,
+    CrabNest,
+}
+
+impl Biome {
+    pub fn from_depth(depth: u32) -> Self {
+        match depth {
+            0..=4 => Biome::MossyCaves,
+            5..=11 => Biome::FloodedCaverns,
+            12..=19 => Biome::CrystalMines,
+            20..=29 => Biome::MagmaDepths,
+            _ => Biome::CrabNest,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Biome::MossyCaves => "Mossy caves",
+            Biome::FloodedCaverns => "Flooded caverns",
+            Biome::CrystalMines => "Crystal mines",
+            Biome::MagmaDepths => "Magma depths",
+            Biome::CrabNest => "Rusty crab nest",
+        }
+    }
+    /// Liquid of the pools, unless the generation config forces one
+    pub fn liquid(&self) -> mapgen::Liquid {
+        match self {
+            Biome::MossyCaves => mapgen::Liquid::None,
+            Biome::FloodedCaverns | Biome::CrystalMines | Biome::CrabNest => mapgen::Liquid::Water,
+            Biome::MagmaDepths => mapgen::Liquid::Lava,
+        }
+    }
+    /// Textures replacing the default ones, by material
+    pub fn textures(&self) -> Vec<(i32, &'static str)> {
+        match self {
+            Biome::MossyCaves => vec![(-1, "ground_moss"), (4, "wall_moss")],
+            Biome::FloodedCaverns => Vec::new(),
+            Biome::CrystalMines => vec![(4, "wall_crystal")],
+            Biome::MagmaDepths => vec![(-1, "ground_ash"), (4, "wall_basalt")],
+            Biome::CrabNest => vec![(-1, "ground_sand"), (4, "wall_rust")],
+        }
+    }
+    /// Multiplied with the light of every tile
+    pub fn tint(&self) -> (f32, f32, f32) {
+        match self {
+            Biome::MossyCaves => (0.95, 1., 0.9),
+            Biome::FloodedCaverns => (0.8, 0.9, 1.),
+            Biome::CrystalMines => (0.9, 0.95, 1.),
+            Biome::MagmaDepths => (1., 0.8, 0.7),
+            Biome::CrabNest => (1., 0.9, 0.8),
+        }
+    }
+    pub fn ambient_light(&self) -> f32 {
+        match self {
+            Biome::MossyCaves => 0.15,
+            Biome::FloodedCaverns => 0.1,
+            Biome::CrystalMines => 0.25, // the crystals glow a bit
+            Biome::MagmaDepths => 0.2,
+            Biome::CrabNest => 0.08,
+        }
+    }
+    /// Multiplies the chance of a trap on each ground tile
+    pub fn hazard_mult(&self) -> f64 {
+        match self {
+            Biome::MossyCaves => 0.5,
+            Biome::FloodedCaverns => 1.,
+            Biome::CrystalMines => 1.5,
+            Biome::MagmaDepths => 2.,
+            Biome::CrabNest => 1.5,
+        }
+    }
+    /// Name of the music in the asset manifest
+    pub fn music(&self) -> &'static str {
+        match self {
+            Biome::MossyCaves => "music_mossy",
+            Biome::FloodedCaverns => "music_flooded",
+            Biome::CrystalMines => "music_crystal",
+            Biome::MagmaDepths => "music_magma",
+            Biome::CrabNest => "music_crab_nest",
+        }
+    }
+    pub fn max_monsters(&self) -> usize {
+        match self {
+            Biome::MossyCaves => 20,
+            Biome::FloodedCaverns => 25,
+            Biome::CrystalMines => 30,
+            Biome::MagmaDepths => 30,
+            Biome::CrabNest => 40,
+        }
+    }
+    /// Monsters living there, with their spawn weight
+    pub fn roster(&self) -> &'static [(monster::MonsterType, u32)] {
+        match self {
+            Biome::MossyCaves => &[
+                (monster::MonsterType::TestBot, 1),
+                (monster::MonsterType::MossCrawler, 3),
+            ],
+            Biome::FloodedCaverns => &[
+                (monster::MonsterType::TestBot, 1),
+                (monster::MonsterType::Drowned, 3),
+            ],
+            Biome::CrystalMines => &[
+                (monster::MonsterType::TestBot, 2),
+                (monster::MonsterType::CrystalGolem, 2),
+            ],
+            Biome::MagmaDepths => &[
+                (monster::MonsterType::MagmaImp, 3),
+                (monster::MonsterType::CrystalGolem, 1),
+            ],
+            Biome::CrabNest => &[
+                (monster::MonsterType::RustyCrab, 4),
+                (monster::MonsterType::MagmaImp, 1),
+            ],
+        }
+    }
+    pub fn pick_monster<R: Rng>(&self, rng: &mut R) -> monster::MonsterType {
+        let roster = self.roster();
+        let total: u32 = roster.iter().map(|(_, weight)| weight).sum();
+        let mut roll = rng.gen_range(0..total);
+        for (monster_type, weight) in roster.iter() {
+            if roll < *weight {
+                return *monster_type;
+            }
+            roll -= weight;
+        }
+        roster[0].0
+    }
+}
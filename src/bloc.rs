@@ -1,6 +1,10 @@
-use crate::{map, physics};
+use std::collections::hash_map::Entry;
+
+use crate::{clock, map, physics, status};
 use std;
 
+const STEP_TIMEOUT: u128 = 100; // ms without contact before an entity is considered gone
+
 pub enum Bloc {
     Air(Air),
     Wall(Wall),
@@ -8,6 +12,17 @@ pub enum Bloc {
     Lava(Lava),
     Spawn(Spawn),
     End(End),
+    Spikes(Spikes),
+    CollapsingFloor(CollapsingFloor),
+    GasVent(GasVent),
+    PressurePlate(PressurePlate),
+    Hole(Hole),
+    Door(Door),
+}
+
+pub enum BlocEvent {
+    Fall(i32),         // id of the entity that fell in a hole
+    Alert(glam::Vec2), // a pressure plate has been stepped on there
 }
 
 pub struct Air {
@@ -30,7 +45,7 @@ pub struct Lava {
     pub tile: map::Tile,
     pub damage: i32,
     pub damage_speed: i32,
-    pub id_time_list: std::collections::HashMap<i32, std::time::SystemTime>,
+    pub id_time_list: std::collections::HashMap<i32, clock::GameTime>,
 }
 
 pub struct Spawn {
@@ -43,6 +58,56 @@ pub struct End {
     pub tile: map::Tile,
 }
 
+pub struct Spikes {
+    pub id: i32,
+    pub tile: map::Tile,
+    pub hidden: bool,
+    pub damage: i32,
+    pub trigger_delay: u128,
+    pub id_time_list: std::collections::HashMap<i32, (clock::GameTime, clock::GameTime)>, // armed since, last seen
+}
+
+pub struct CollapsingFloor {
+    pub id: i32,
+    pub tile: map::Tile,
+    pub hidden: bool,
+    pub collapse_delay: u128,
+    pub stepped_on: Option<clock::GameTime>, // it crumbles from there
+}
+
+/// What is left of a collapsing floor, whoever walks in falls a level down
+pub struct Hole {
+    pub id: i32,
+    pub tile: map::Tile,
+}
+
+pub struct GasVent {
+    pub id: i32,
+    pub tile: map::Tile,
+    pub hidden: bool,
+    pub damage_speed: i32, // delay between two poison stacks
+    pub id_time_list: std::collections::HashMap<i32, clock::GameTime>,
+}
+
+pub struct PressurePlate {
+    pub id: i32,
+    pub tile: map::Tile,
+    pub hidden: bool,
+    pub cooldown: u128,
+    pub last_trigger: clock::GameTime,
+}
+
+pub struct Door {
+    pub id: i32,
+    pub tile: map::Tile,
+    pub open: bool,
+    pub key: Option<i32>, // id of the key that unlocks it, None once unlocked
+}
+
+fn millis_since(time: clock::GameTime) -> u128 {
+    time.elapsed().as_millis()
+}
+
 impl Air {
     pub fn new(id: i32, tile: map::Tile) -> Self {
         Air { id: id, tile: tile }
@@ -55,6 +120,15 @@ impl Wall {
     }
 }
 
+impl Hole {
+    pub fn new(id: i32, tile: map::Tile) -> Self {
+        Hole { id, tile }
+    }
+    pub fn step<E: physics::EntityTrait>(&self, entity: &E) -> BlocEvent {
+        BlocEvent::Fall(entity.id())
+    }
+}
+
 impl Water {
     pub fn new(id: i32, tile: map::Tile) -> Self {
         Self { id: id, tile: tile }
@@ -85,70 +159,210 @@ impl Lava {
     }
     pub fn damage<E: physics::EntityTrait>(&mut self, entity: &mut E) {
         self.update();
-        if !self.id_time_list.contains_key(&entity.id()) {
-            self.id_time_list
-                .insert(entity.id(), std::time::SystemTime::now());
+        if let Entry::Vacant(entry) = self.id_time_list.entry(entity.id()) {
+            entry.insert(clock::GameTime::now());
             entity.take_damage(self.damage);
+            entity.apply_status(status::StatusKind::Burning);
         }
     }
 
     pub fn update(&mut self) {
         for (id, time) in self.id_time_list.clone().iter() {
-            match time.elapsed() {
-                Ok(elapsed) => {
-                    if elapsed.as_millis() > self.damage_speed as u128 {
-                        self.id_time_list.remove(&id);
-                    }
-                }
-                Err(e) => {
-                    println!("There has been an error in the lava update function: {}", e);
-                }
+            if millis_since(*time) > self.damage_speed as u128 {
+                self.id_time_list.remove(id);
             }
         }
     }
 }
 
-impl physics::EntityTrait for Bloc {
-    fn get_hitbox(&self) -> ggez::graphics::Rect {
+impl Spikes {
+    pub fn new(id: i32, tile: map::Tile) -> Self {
+        Spikes {
+            id,
+            tile,
+            hidden: true,
+            damage: 15,
+            trigger_delay: 400,
+            id_time_list: std::collections::HashMap::new(),
+        }
+    }
+    /// The spikes come out a little while after you stepped on the plate, and keep hitting
+    pub fn step<E: physics::EntityTrait>(&mut self, entity: &mut E) {
+        // Forget the ones that walked away
+        self.id_time_list
+            .retain(|_, (_, last_seen)| millis_since(*last_seen) < STEP_TIMEOUT);
+        let now = clock::GameTime::now();
+        let mut armed_since = match self.id_time_list.get(&entity.id()) {
+            Some((armed_since, _)) => *armed_since,
+            None => now,
+        };
+        if millis_since(armed_since) > self.trigger_delay {
+            entity.take_damage(self.damage);
+            entity.apply_status(status::StatusKind::Bleed);
+            armed_since = now;
+        }
+        self.id_time_list.insert(entity.id(), (armed_since, now));
+    }
+}
+
+impl CollapsingFloor {
+    pub fn new(id: i32, tile: map::Tile) -> Self {
+        CollapsingFloor {
+            id,
+            tile,
+            hidden: true,
+            collapse_delay: 600,
+            stepped_on: None,
+        }
+    }
+    /// Starts to crumble under the first one to step on it
+    pub fn step(&mut self) {
+        self.stepped_on.get_or_insert_with(clock::GameTime::now);
+    }
+    /// Gave way, the map turns it into a hole
+    pub fn collapsed(&self) -> bool {
+        self.stepped_on
+            .is_some_and(|time| millis_since(time) > self.collapse_delay)
+    }
+}
+
+impl GasVent {
+    pub fn new(id: i32, tile: map::Tile) -> Self {
+        GasVent {
+            id,
+            tile,
+            hidden: true,
+            damage_speed: 1000,
+            id_time_list: std::collections::HashMap::new(),
+        }
+    }
+    pub fn step<E: physics::EntityTrait>(&mut self, entity: &mut E) {
+        let damage_speed = self.damage_speed as u128;
+        self.id_time_list
+            .retain(|_, time| millis_since(*time) <= damage_speed);
+        if let Entry::Vacant(entry) = self.id_time_list.entry(entity.id()) {
+            entry.insert(clock::GameTime::now());
+            entity.apply_status(status::StatusKind::Poison);
+        }
+    }
+}
+
+impl PressurePlate {
+    pub fn new(id: i32, tile: map::Tile) -> Self {
+        PressurePlate {
+            id,
+            tile,
+            hidden: true,
+            cooldown: 3000,
+            last_trigger: clock::GameTime::NEVER,
+        }
+    }
+    pub fn step(&mut self) -> Option<BlocEvent> {
+        if millis_since(self.last_trigger) > self.cooldown {
+            self.last_trigger = clock::GameTime::now();
+            Some(BlocEvent::Alert(glam::Vec2::from(
+                self.tile.hitbox.center(),
+            )))
+        } else {
+            None
+        }
+    }
+}
+
+impl Door {
+    pub fn new(id: i32, tile: map::Tile, open: bool, key: Option<i32>) -> Self {
+        Door {
+            id,
+            tile,
+            open,
+            key,
+        }
+    }
+    pub fn is_locked(&self) -> bool {
+        self.key.is_some()
+    }
+    /// Returns the material matching the new state, so the grid can follow
+    pub fn set_open(&mut self, open: bool) -> i32 {
+        self.open = open;
+        self.tile.transparent = open;
+        self.tile.material = if open {
+            26
+        } else if self.is_locked() {
+            28
+        } else {
+            27
+        };
+        self.tile.material
+    }
+}
+
+impl Bloc {
+    pub fn tile(&self) -> &map::Tile {
         match self {
-            Bloc::Air(a) => a.tile.hitbox,
-            Bloc::Wall(w) => w.tile.hitbox,
-            Bloc::Water(w) => w.tile.hitbox,
-            Bloc::Lava(l) => l.tile.hitbox,
-            Bloc::Spawn(s) => s.tile.hitbox,
-            Bloc::End(e) => e.tile.hitbox,
+            Bloc::Air(a) => &a.tile,
+            Bloc::Wall(w) => &w.tile,
+            Bloc::Water(w) => &w.tile,
+            Bloc::Lava(l) => &l.tile,
+            Bloc::Spawn(s) => &s.tile,
+            Bloc::End(e) => &e.tile,
+            Bloc::Spikes(s) => &s.tile,
+            Bloc::CollapsingFloor(c) => &c.tile,
+            Bloc::GasVent(g) => &g.tile,
+            Bloc::PressurePlate(p) => &p.tile,
+            Bloc::Hole(h) => &h.tile,
+            Bloc::Door(d) => &d.tile,
         }
     }
-    fn get_angle(&self) -> f32 {
+    pub fn tile_mut(&mut self) -> &mut map::Tile {
         match self {
-            Bloc::Air(a) => a.tile.angle,
-            Bloc::Wall(w) => w.tile.angle,
-            Bloc::Water(w) => w.tile.angle,
-            Bloc::Lava(l) => l.tile.angle,
-            Bloc::Spawn(s) => s.tile.angle,
-            Bloc::End(e) => e.tile.angle,
+            Bloc::Air(a) => &mut a.tile,
+            Bloc::Wall(w) => &mut w.tile,
+            Bloc::Water(w) => &mut w.tile,
+            Bloc::Lava(l) => &mut l.tile,
+            Bloc::Spawn(s) => &mut s.tile,
+            Bloc::End(e) => &mut e.tile,
+            Bloc::Spikes(s) => &mut s.tile,
+            Bloc::CollapsingFloor(c) => &mut c.tile,
+            Bloc::GasVent(g) => &mut g.tile,
+            Bloc::PressurePlate(p) => &mut p.tile,
+            Bloc::Hole(h) => &mut h.tile,
+            Bloc::Door(d) => &mut d.tile,
         }
     }
-    fn ray_cast_bypass(&self) -> bool {
+    /// Only traps can be hidden, and only until someone finds them
+    pub fn is_hidden(&self) -> bool {
         match self {
-            Bloc::Air(a) => a.tile.transparent,
-            Bloc::Wall(w) => w.tile.transparent,
-            Bloc::Water(w) => w.tile.transparent,
-            Bloc::Lava(l) => l.tile.transparent,
-            Bloc::Spawn(s) => s.tile.transparent,
-            Bloc::End(e) => e.tile.transparent,
+            Bloc::Spikes(s) => s.hidden,
+            Bloc::CollapsingFloor(c) => c.hidden,
+            Bloc::GasVent(g) => g.hidden,
+            Bloc::PressurePlate(p) => p.hidden,
+            _ => false,
         }
     }
-    fn rotated_hitbox(&self) -> Vec<glam::Vec2> {
+    pub fn reveal(&mut self) {
         match self {
-            Bloc::Air(a) => physics::rotate_square(a.tile.hitbox, a.tile.angle),
-            Bloc::Wall(w) => physics::rotate_square(w.tile.hitbox, w.tile.angle),
-            Bloc::Water(w) => physics::rotate_square(w.tile.hitbox, w.tile.angle),
-            Bloc::Lava(l) => physics::rotate_square(l.tile.hitbox, l.tile.angle),
-            Bloc::Spawn(s) => physics::rotate_square(s.tile.hitbox, s.tile.angle),
-            Bloc::End(e) => physics::rotate_square(e.tile.hitbox, e.tile.angle),
+            Bloc::Spikes(s) => s.hidden = false,
+            Bloc::CollapsingFloor(c) => c.hidden = false,
+            Bloc::GasVent(g) => g.hidden = false,
+            Bloc::PressurePlate(p) => p.hidden = false,
+            _ => {}
         }
     }
+}
+
+impl physics::EntityTrait for Bloc {
+    fn get_hitbox(&self) -> ggez::graphics::Rect {
+        self.tile().hitbox
+    }
+    fn get_angle(&self) -> f32 {
+        self.tile().angle
+    }
+    fn ray_cast_bypass(&self) -> bool {
+        self.tile().transparent
+    }
+    fn rotated_hitbox(&self) -> Vec<glam::Vec2> {
+        self.tile().get_rotated_hitbox()
+    }
     fn id(&self) -> i32 {
         match self {
             Bloc::Air(a) => a.id,
@@ -157,7 +371,69 @@ impl physics::EntityTrait for Bloc {
             Bloc::Lava(l) => l.id,
             Bloc::Spawn(s) => s.id,
             Bloc::End(e) => e.id,
+            Bloc::Spikes(s) => s.id,
+            Bloc::CollapsingFloor(c) => c.id,
+            Bloc::GasVent(g) => g.id,
+            Bloc::PressurePlate(p) => p.id,
+            Bloc::Hole(h) => h.id,
+            Bloc::Door(d) => d.id,
         }
     }
     fn take_damage(&mut self, _damage: i32) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile() -> map::Tile {
+        map::Tile::new(0., 0., 60., -1, true, 0.)
+    }
+    /// Anything with an id can walk on a trap
+    fn walker(id: i32) -> Bloc {
+        Bloc::Air(Air::new(id, tile()))
+    }
+    fn wait(millis: u128) {
+        clock::advance(millis as f32 / 1000.);
+    }
+
+    #[test]
+    fn a_collapsing_floor_gives_way_after_its_delay() {
+        let mut floor = CollapsingFloor::new(0, tile());
+        assert!(!floor.collapsed());
+        floor.step();
+        assert!(!floor.collapsed());
+        wait(floor.collapse_delay + 1);
+        floor.step(); // stepping on it again doesn't start it over
+        assert!(floor.collapsed());
+    }
+
+    #[test]
+    fn only_who_walks_in_the_hole_falls() {
+        let hole = Hole::new(0, tile());
+        assert!(matches!(hole.step(&walker(7)), BlocEvent::Fall(7)));
+    }
+
+    #[test]
+    fn stepping_on_a_trap_does_not_reveal_it() {
+        let mut walker = walker(1);
+        let mut spikes = Spikes::new(0, tile());
+        spikes.step(&mut walker);
+        let mut floor = CollapsingFloor::new(0, tile());
+        floor.step();
+        let mut vent = GasVent::new(0, tile());
+        vent.step(&mut walker);
+        let mut plate = PressurePlate::new(0, tile());
+        plate.step();
+        assert!(spikes.hidden && floor.hidden && vent.hidden && plate.hidden);
+    }
+
+    #[test]
+    fn spikes_forget_who_walked_away() {
+        let mut spikes = Spikes::new(0, tile());
+        spikes.step(&mut walker(1));
+        wait(STEP_TIMEOUT * 2);
+        spikes.step(&mut walker(2));
+        assert_eq!(spikes.id_time_list.keys().collect::<Vec<_>>(), vec![&2]);
+    }
+}
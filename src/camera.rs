@@ -1,53 +1,149 @@
 use ggez;
-use glam;
+use glam::Vec2;
+use rand::{Rng, SeedableRng};
 
-pub struct Camera {
-    pub camera: ggez::graphics::Rect,
-    pub scroll: glam::Vec2,
-}
+use crate::hearing;
 
-fn min(min: f32, nbr: f32) -> f32 {
-    if min < nbr {
-        min
-    } else {
-        nbr
-    }
-}
+const ZOOM_STEP: f32 = 1.1; // per notch of the mouse wheel
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 2.5;
+const TRAUMA_DECAY: f32 = 1.5; // per second
+const MAX_SHAKE: f32 = 20.; // in pixels, at full trauma
+const SHOT_TRAUMA: f32 = 0.04; // for a pistol, the louder the weapon the more it shakes
+const EXPLOSION_TRAUMA: f32 = 0.6;
+const DAMAGE_TRAUMA: f32 = 0.02; // per hp lost
 
-fn max(max: f32, nbr: f32) -> f32 {
-    if max > nbr {
-        max
-    } else {
-        nbr
-    }
+pub struct Camera {
+    pub center: Vec2, // world position in the middle of the screen
+    pub zoom: f32,
+    pub damping: f32,    // how fast it catches up, higher is snappier
+    pub dead_zone: Vec2, // half size, in pixels, the target moves in it without the camera following
+    pub look_ahead: f32, // in pixels, toward where the player aims
+    goal: Vec2,
+    trauma: f32, // between 0 and 1, the shake is its square
+    shake: Vec2,
+    rng: rand::rngs::StdRng, // seeded with the run, a replay shakes the same way
 }
 
 impl Camera {
-    pub fn new(width: f32, height: f32) -> Self {
+    pub fn new(seed: u64) -> Self {
         Camera {
-            camera: ggez::graphics::Rect::new(0.0, 0.0, width, height),
-            scroll: glam::Vec2::new(0., 0.),
+            center: Vec2::ZERO,
+            zoom: 1.,
+            damping: 8.,
+            dead_zone: Vec2::new(40., 30.),
+            look_ahead: 120.,
+            goal: Vec2::ZERO,
+            trauma: 0.,
+            shake: Vec2::ZERO,
+            rng: rand::rngs::StdRng::seed_from_u64(seed),
         }
     }
-    pub fn set_focus(
+    /// Starts the shake over, for a new run
+    pub fn reseed(&mut self, seed: u64) {
+        self.trauma = 0.;
+        self.shake = Vec2::ZERO;
+        self.rng = rand::rngs::StdRng::seed_from_u64(seed);
+    }
+    /// Jumps to the target, for when the player is moved somewhere else
+    pub fn snap(&mut self, target: Vec2) {
+        self.center = target;
+        self.goal = target;
+    }
+    /// Follows the target, ahead of it toward the angle.
+    /// Stays inside the bounds when there are some, maps smaller than the screen are centered.
+    pub fn update(
         &mut self,
-        target: (f32, f32),
-        window_size: (f32, f32),
-        map_size: (f32, f32),
-        tile_size: f32,
+        dt: f32,
+        target: Vec2,
+        angle: f32,
+        window_size: Vec2,
+        bounds: Option<ggez::graphics::Rect>,
     ) {
-        // println!("Camera focusing on x:{} y:{}.", target.0, target.1);
-        let mut x = -target.0 + (window_size.0 / 2.0);
-        let mut y = -target.1 + (window_size.1 / 2.0);
+        let desired = target + Vec2::new(angle.cos(), angle.sin()) * self.look_ahead;
+        let diff = desired - self.goal;
+        if diff.x.abs() > self.dead_zone.x {
+            self.goal.x += diff.x - diff.x.signum() * self.dead_zone.x;
+        }
+        if diff.y.abs() > self.dead_zone.y {
+            self.goal.y += diff.y - diff.y.signum() * self.dead_zone.y;
+        }
+        if let Some(bounds) = bounds {
+            self.goal = self.clamp(self.goal, window_size, bounds);
+        }
+        self.center += (self.goal - self.center) * (1. - (-self.damping * dt).exp());
+        if let Some(bounds) = bounds {
+            self.center = self.clamp(self.center, window_size, bounds);
+        }
 
-        x = min(0.0, x);
-        y = min(0.0, y);
+        self.trauma = (self.trauma - TRAUMA_DECAY * dt).max(0.);
+        self.shake = Vec2::new(self.rng.gen_range(-1. ..=1.), self.rng.gen_range(-1. ..=1.))
+            * self.trauma
+            * self.trauma
+            * MAX_SHAKE;
+    }
+    fn clamp(&self, pos: Vec2, window_size: Vec2, bounds: ggez::graphics::Rect) -> Vec2 {
+        let half = window_size / (2. * self.zoom);
+        let axis = |pos: f32, min: f32, size: f32, half: f32| {
+            if size <= half * 2. {
+                min + size / 2.
+            } else {
+                pos.clamp(min + half, min + size - half)
+            }
+        };
+        Vec2::new(
+            axis(pos.x, bounds.x, bounds.w, half.x),
+            axis(pos.y, bounds.y, bounds.h, half.y),
+        )
+    }
 
-        x = max((map_size.1 - self.camera.w) * -tile_size, x);
-        y = max((map_size.0 - self.camera.h) * -tile_size, y);
+    /// Positive notches zoom in
+    pub fn zoom_by(&mut self, notches: f32) {
+        self.zoom = (self.zoom * ZOOM_STEP.powf(notches)).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+    /// Between 0 and 1, adds up and wears off over time
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.);
+    }
+    /// Shots and explosions shake the screen
+    pub fn react(&mut self, noise: &hearing::Noise) {
+        match noise.kind {
+            hearing::NoiseKind::Gunshot => {
+                self.add_trauma(SHOT_TRAUMA * noise.radius / noise.kind.radius())
+            }
+            hearing::NoiseKind::Explosion => self.add_trauma(EXPLOSION_TRAUMA),
+            _ => (),
+        }
+    }
+    pub fn hurt(&mut self, damage: i32) {
+        self.add_trauma(DAMAGE_TRAUMA * damage as f32);
+    }
 
-        self.scroll = glam::Vec2::new(-x, -y);
-        self.camera.x = x;
-        self.camera.y = y;
+    /// Area of the world on screen
+    pub fn view(&self, window_size: Vec2) -> ggez::graphics::Rect {
+        let size = window_size / self.zoom;
+        ggez::graphics::Rect::new(
+            self.center.x - size.x / 2.,
+            self.center.y - size.y / 2.,
+            size.x,
+            size.y,
+        )
+    }
+    /// Added to the world positions when drawing, used with the screen coordinates below
+    pub fn draw_offset(&self, window_size: Vec2) -> Vec2 {
+        let view = self.view(window_size);
+        Vec2::new(-view.x, -view.y)
+    }
+    /// What to give to graphics::set_screen_coordinates so the zoom and the shake apply
+    pub fn screen_coordinates(&self, window_size: Vec2) -> ggez::graphics::Rect {
+        ggez::graphics::Rect::new(
+            -self.shake.x / self.zoom,
+            -self.shake.y / self.zoom,
+            window_size.x / self.zoom,
+            window_size.y / self.zoom,
+        )
+    }
+    pub fn screen_to_world(&self, pos: Vec2, window_size: Vec2) -> Vec2 {
+        (pos - window_size / 2. - self.shake) / self.zoom + self.center
     }
 }
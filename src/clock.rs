@@ -0,0 +1,36 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+thread_local! {
+    static NOW: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// A moment of the game. Unlike the system clock it stands still while the game is paused,
+/// and a replay goes through the same moments however fast it's played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameTime(Option<Duration>); // None is before the game started
+
+impl GameTime {
+    /// Long enough ago that any delay is over
+    pub const NEVER: GameTime = GameTime(None);
+
+    pub fn now() -> Self {
+        GameTime(Some(NOW.with(|now| now.get())))
+    }
+    pub fn elapsed(&self) -> Duration {
+        match self.0 {
+            Some(time) => NOW.with(|now| now.get()).saturating_sub(time),
+            None => Duration::MAX,
+        }
+    }
+}
+
+/// Called once per update of the game, with the same dt as the rest of it
+pub fn advance(dt: f32) {
+    NOW.with(|now| now.set(now.get() + Duration::from_secs_f32(dt.max(0.))));
+}
+
+/// Back to the start, for a new run
+pub fn reset() {
+    NOW.with(|now| now.set(Duration::ZERO));
+}
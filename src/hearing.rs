@@ -0,0 +1,106 @@
+use glam::Vec2;
+
+const WALL_DAMPING: f32 = 0.5; // what's left of the loudness after each wall in the way
+const STEPS_PER_TILE: f32 = 2.; // how finely the line to the listener is checked for walls
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseKind {
+    Gunshot,
+    Footstep,
+    Splash,
+    Explosion,
+}
+
+/// Something monsters can hear, until it's been handled at the end of the frame
+#[derive(Clone, Copy, Debug)]
+pub struct Noise {
+    pub kind: NoiseKind,
+    pub pos: Vec2,
+    pub radius: f32, // in tiles, how far it can be heard with nothing in the way
+}
+
+impl NoiseKind {
+    /// In tiles, scaled by the loudness of each noise
+    pub fn radius(&self) -> f32 {
+        match self {
+            NoiseKind::Gunshot => 10.,
+            NoiseKind::Footstep => 3.,
+            NoiseKind::Splash => 5.,
+            NoiseKind::Explosion => 20.,
+        }
+    }
+}
+
+impl Noise {
+    pub fn new(kind: NoiseKind, pos: Vec2, loudness: f32) -> Self {
+        Noise {
+            kind,
+            pos,
+            radius: kind.radius() * loudness,
+        }
+    }
+    /// Whether it reaches the listener, each wall tile on the way dampens it
+    pub fn reaches<F: Fn(i32, i32) -> bool>(
+        &self,
+        listener: Vec2,
+        tile_size: f32,
+        is_wall: F,
+    ) -> bool {
+        let distance = (listener - self.pos).length() / tile_size;
+        if distance > self.radius {
+            return false;
+        }
+        let mut radius = self.radius;
+        let steps = (distance * STEPS_PER_TILE).ceil() as usize;
+        let mut last_tile = (i32::MIN, i32::MIN);
+        for step in 1..steps {
+            let point = self.pos.lerp(listener, step as f32 / steps as f32) / tile_size;
+            let tile = (point.x.floor() as i32, point.y.floor() as i32);
+            if tile != last_tile && is_wall(tile.0, tile.1) {
+                radius *= WALL_DAMPING;
+                if distance > radius {
+                    return false;
+                }
+            }
+            last_tile = tile;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILE: f32 = 10.;
+
+    fn at(x: f32, y: f32) -> Vec2 {
+        Vec2::new(x * TILE, y * TILE)
+    }
+
+    #[test]
+    fn heard_in_the_open_up_to_its_radius() {
+        let noise = Noise::new(NoiseKind::Footstep, at(0.5, 0.5), 2.); // 6 tiles
+        assert!(noise.reaches(at(5.5, 0.5), TILE, |_, _| false));
+        assert!(noise.reaches(at(6.5, 0.5), TILE, |_, _| false));
+        assert!(!noise.reaches(at(7.5, 0.5), TILE, |_, _| false));
+    }
+
+    #[test]
+    fn each_wall_on_the_way_halves_the_radius() {
+        let noise = Noise::new(NoiseKind::Gunshot, at(0.5, 0.5), 1.); // 10 tiles
+        let one_wall = |x: i32, _| x == 2;
+        assert!(noise.reaches(at(5.5, 0.5), TILE, one_wall));
+        assert!(!noise.reaches(at(6.5, 0.5), TILE, one_wall));
+
+        let two_walls = |x: i32, _| x == 2 || x == 3;
+        assert!(noise.reaches(at(4.5, 0.5), TILE, one_wall));
+        assert!(!noise.reaches(at(4.5, 0.5), TILE, two_walls));
+    }
+
+    #[test]
+    fn walls_off_the_line_dont_count() {
+        let noise = Noise::new(NoiseKind::Gunshot, at(0.5, 0.5), 1.);
+        assert!(noise.reaches(at(8.5, 0.5), TILE, |_, y| y != 0));
+    }
+}
@@ -0,0 +1,323 @@
+use ggez::graphics::{Color, DrawMode, Rect};
+use glam::Vec2;
+
+use crate::{input, map, player, view};
+
+const BASE_SCALE: f32 = 2.; // Gui.scale the layout is made for
+const MARGIN: f32 = 20.;
+const HP_BAR_SIZE: (f32, f32) = (320., 28.);
+const FLASH_TIME: f32 = 0.3; // in seconds, after taking damage
+const RING_RADIUS: f32 = 22.;
+const MINIMAP_SIZE: f32 = 220.;
+const STATUS_ICON_SIZE: f32 = 32.;
+
+/// What the HUD shows, gathered by the game every frame
+pub struct HudInfo<'a> {
+    pub player: &'a player::Player,
+    pub keymap: &'a input::KeyMap,
+    pub location: String,
+    pub map: Option<&'a map::Map>, // None in the open world, the minimap stays empty
+    pub debug: String,
+    pub font: ggez::graphics::Font,
+    pub gui_scale: f32,
+}
+
+pub struct Hud {
+    pub show_debug: bool,
+    last_hp: i32,
+    flash: f32, // seconds left of the damage flash
+}
+
+/// Green when full, yellow at half, red when empty
+pub fn hp_color(ratio: f32) -> Color {
+    let ratio = ratio.clamp(0., 1.);
+    Color::new((2. - 2. * ratio).min(1.), (2. * ratio).min(1.), 0., 1.)
+}
+
+impl Hud {
+    pub fn new() -> Self {
+        Hud {
+            show_debug: false,
+            last_hp: player::PLAYER_BASE_HP,
+            flash: 0.,
+        }
+    }
+    /// For a new run, the debug line stays as it was
+    pub fn reset(&mut self) {
+        self.last_hp = player::PLAYER_BASE_HP;
+        self.flash = 0.;
+    }
+    /// Flashes the health bar when the hp goes down
+    pub fn update(&mut self, dt: f32, hp: i32) {
+        if hp < self.last_hp {
+            self.flash = FLASH_TIME;
+        }
+        self.last_hp = hp;
+        self.flash = (self.flash - dt).max(0.);
+    }
+
+    /// In virtual pixels, the screen coordinates have to be set before
+    pub fn draw(&self, ctx: &mut ggez::Context, info: &HudInfo) -> ggez::GameResult {
+        let s = info.gui_scale / BASE_SCALE;
+        let font = info.font;
+        let anchor = |anchor: view::Anchor, x: f32, y: f32| {
+            view::ViewTransform::anchor(anchor, Vec2::new(x, y) * s)
+        };
+        let mut mesh = ggez::graphics::MeshBuilder::new();
+        let mut texts: Vec<(ggez::graphics::Text, Vec2, Color)> = Vec::new();
+
+        // Health bar, top left
+        let hp_ratio = info.player.hp as f32 / player::PLAYER_BASE_HP as f32;
+        let bar_pos = anchor(view::Anchor::TopLeft, MARGIN, MARGIN);
+        let bar = Rect::new(bar_pos.x, bar_pos.y, HP_BAR_SIZE.0 * s, HP_BAR_SIZE.1 * s);
+        let flash = self.flash / FLASH_TIME;
+        mesh.rectangle(
+            DrawMode::fill(),
+            bar,
+            Color::new(0.1 + 0.7 * flash, 0.1, 0.1, 0.8),
+        )?;
+        if hp_ratio > 0. {
+            mesh.rectangle(
+                DrawMode::fill(),
+                Rect::new(bar.x, bar.y, bar.w * hp_ratio.min(1.), bar.h),
+                hp_color(hp_ratio),
+            )?;
+        }
+        mesh.rectangle(
+            DrawMode::stroke(2. * s),
+            bar,
+            Color::new(1., 1., 1., 0.5 + 0.5 * flash),
+        )?;
+        texts.push((
+            ggez::graphics::Text::new((
+                format!("{} / {}", info.player.hp.max(0), player::PLAYER_BASE_HP),
+                font,
+                22. * s,
+            )),
+            bar_pos + Vec2::new(8., 3.) * s,
+            Color::WHITE,
+        ));
+
+        // Depth and biome, under it
+        texts.push((
+            ggez::graphics::Text::new((info.location.clone(), font, 26. * s)),
+            bar_pos + Vec2::new(0., HP_BAR_SIZE.1 + 8.) * s,
+            Color::WHITE,
+        ));
+
+        // One icon per status effect, with its stack count
+        let status_pos = bar_pos + Vec2::new(0., HP_BAR_SIZE.1 + 44.) * s;
+        for (index, effect) in info.player.status.list.iter().enumerate() {
+            let pos = status_pos + Vec2::new(index as f32 * (STATUS_ICON_SIZE + 8.) * s, 0.);
+            mesh.rectangle(
+                DrawMode::fill(),
+                Rect::new(pos.x, pos.y, STATUS_ICON_SIZE * s, STATUS_ICON_SIZE * s),
+                effect.kind.color(),
+            )?;
+            let icon_str = format!(
+                "{}{}",
+                &effect.kind.name()[..1],
+                if effect.stacks > 1 {
+                    effect.stacks.to_string()
+                } else {
+                    String::new()
+                }
+            );
+            texts.push((
+                ggez::graphics::Text::new((icon_str, font, 20. * s)),
+                pos + Vec2::new(4., 6.) * s,
+                Color::BLACK,
+            ));
+        }
+
+        // Selected weapon, its cooldown and its ammo, bottom left
+        let ring_center = anchor(
+            view::Anchor::BottomLeft,
+            MARGIN + RING_RADIUS,
+            MARGIN + RING_RADIUS,
+        );
+        mesh.circle(
+            DrawMode::fill(),
+            ring_center,
+            RING_RADIUS * s,
+            0.5,
+            Color::new(0., 0., 0., 0.6),
+        )?;
+        let (weapon_str, cooldown) = match info.player.inventory.selected() {
+            Some(weapon) => (weapon.name(), weapon.cooldown()),
+            None => ("No weapon".to_string(), 0.),
+        };
+        // What's left to wait, clockwise from the top
+        if cooldown > 0.01 {
+            let steps = (cooldown * 32.).ceil() as usize + 1;
+            let points: Vec<Vec2> = (0..steps)
+                .map(|i| {
+                    let angle = -std::f32::consts::FRAC_PI_2
+                        + std::f32::consts::PI * 2. * cooldown * i as f32 / (steps - 1) as f32;
+                    ring_center + Vec2::new(angle.cos(), angle.sin()) * (RING_RADIUS - 3.) * s
+                })
+                .collect();
+            mesh.polyline(DrawMode::stroke(5. * s), &points, Color::WHITE)?;
+        }
+        // Weapons don't run out of ammo
+        texts.push((
+            ggez::graphics::Text::new(("\u{221e}", font, 26. * s)),
+            ring_center - Vec2::new(8., 14.) * s,
+            Color::WHITE,
+        ));
+        texts.push((
+            ggez::graphics::Text::new((weapon_str, font, 26. * s)),
+            ring_center + Vec2::new(RING_RADIUS + 12., -14.) * s,
+            Color::WHITE,
+        ));
+
+        // Hotbar, bottom center
+        let hotbar_str = info
+            .player
+            .items
+            .hotbar
+            .iter()
+            .enumerate()
+            .map(|(slot, consumable)| match consumable {
+                Some(c) => format!(
+                    "[{}] {} x{}",
+                    info.keymap.get(input::Action::Hotbar(slot)).name(),
+                    c.name(),
+                    info.player.items.count(*c)
+                ),
+                None => format!(
+                    "[{}] -",
+                    info.keymap.get(input::Action::Hotbar(slot)).name()
+                ),
+            })
+            .collect::<Vec<String>>()
+            .join("  ");
+        let hotbar_display = ggez::graphics::Text::new((hotbar_str, font, 24. * s));
+        let hotbar_pos = anchor(view::Anchor::Bottom, 0., MARGIN + 24.)
+            - Vec2::new(hotbar_display.width(ctx) / 2., 0.);
+        texts.push((hotbar_display, hotbar_pos, Color::WHITE));
+
+        // Minimap, bottom right
+        let slot_pos = anchor(
+            view::Anchor::BottomRight,
+            MARGIN + MINIMAP_SIZE,
+            MARGIN + MINIMAP_SIZE,
+        );
+        let slot = Rect::new(slot_pos.x, slot_pos.y, MINIMAP_SIZE * s, MINIMAP_SIZE * s);
+        mesh.rectangle(DrawMode::fill(), slot, Color::new(0., 0., 0., 0.6))?;
+        if let Some(map) = info.map {
+            self.minimap(&mut mesh, map, info.player, slot)?;
+        }
+        mesh.rectangle(DrawMode::stroke(2. * s), slot, Color::new(1., 1., 1., 0.5))?;
+
+        // Fps and debug line, top right
+        if self.show_debug {
+            let debug_display = ggez::graphics::Text::new((info.debug.clone(), font, 22. * s));
+            let debug_pos = anchor(view::Anchor::TopRight, MARGIN, MARGIN)
+                - Vec2::new(debug_display.width(ctx), 0.);
+            texts.push((debug_display, debug_pos, Color::WHITE));
+        }
+
+        let mesh = mesh.build(ctx)?;
+        ggez::graphics::draw(ctx, &mesh, ggez::graphics::DrawParam::new())?;
+        for (text, pos, color) in texts.iter() {
+            ggez::graphics::draw(ctx, text, (*pos, 0.0, *color))?;
+        }
+        Ok(())
+    }
+
+    /// The explored tiles of the floor, fitted in the slot
+    fn minimap(
+        &self,
+        mesh: &mut ggez::graphics::MeshBuilder,
+        map: &map::Map,
+        player: &player::Player,
+        slot: Rect,
+    ) -> ggez::GameResult {
+        let (cols, rows) = (map.total_cols as usize, map.total_rows as usize);
+        if cols == 0 || rows == 0 {
+            return Ok(());
+        }
+        let cell = slot.w / cols.max(rows) as f32;
+        let origin = Vec2::new(
+            slot.x + (slot.w - cell * cols as f32) / 2.,
+            slot.y + (slot.h - cell * rows as f32) / 2.,
+        );
+        for y in 0..rows {
+            for x in 0..cols {
+                if !map.explored[y][x] {
+                    continue;
+                }
+                let color = match map.map_file_content[y][x] {
+                    4 => Color::new(0.55, 0.55, 0.55, 1.),
+                    9 => Color::new(0.2, 0.4, 0.9, 1.),
+                    18 => Color::new(0.95, 0.4, 0.1, 1.),
+                    20 | 21 => Color::new(0.3, 0.9, 0.3, 1.),
+                    26..=28 => Color::new(0.6, 0.4, 0.2, 1.),
+                    _ => Color::new(0.2, 0.2, 0.2, 1.),
+                };
+                mesh.rectangle(
+                    DrawMode::fill(),
+                    Rect::new(
+                        origin.x + x as f32 * cell,
+                        origin.y + y as f32 * cell,
+                        cell,
+                        cell,
+                    ),
+                    color,
+                )?;
+            }
+        }
+        let player_pos = origin + Vec2::from(player.hitbox.center()) / map.tile_size * cell;
+        mesh.circle(
+            DrawMode::fill(),
+            player_pos,
+            (cell * 1.5).max(2.),
+            0.5,
+            Color::new(1., 0.2, 0.2, 1.),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_hp_color_goes_from_green_to_red() {
+        assert_eq!(hp_color(1.), Color::new(0., 1., 0., 1.));
+        assert_eq!(hp_color(0.5), Color::new(1., 1., 0., 1.));
+        assert_eq!(hp_color(0.), Color::new(1., 0., 0., 1.));
+        // Overheal and overkill stay in the range
+        assert_eq!(hp_color(1.5), hp_color(1.));
+        assert_eq!(hp_color(-0.5), hp_color(0.));
+    }
+
+    #[test]
+    fn the_bar_flashes_when_hurt_only() {
+        let mut hud = Hud::new();
+        hud.update(0.1, player::PLAYER_BASE_HP);
+        assert_eq!(hud.flash, 0.);
+
+        hud.update(0.1, player::PLAYER_BASE_HP - 10);
+        assert!(hud.flash > 0.);
+
+        let mut healed = Hud::new();
+        healed.update(0.1, 50);
+        healed.update(FLASH_TIME, 50);
+        healed.update(0.1, 80);
+        assert_eq!(healed.flash, 0.);
+    }
+
+    #[test]
+    fn the_flash_fades_out() {
+        let mut hud = Hud::new();
+        hud.update(0., player::PLAYER_BASE_HP - 10);
+        assert_eq!(hud.flash, FLASH_TIME);
+        hud.update(FLASH_TIME / 2., player::PLAYER_BASE_HP - 10);
+        assert!(hud.flash > 0. && hud.flash < FLASH_TIME);
+        hud.update(FLASH_TIME, player::PLAYER_BASE_HP - 10);
+        assert_eq!(hud.flash, 0.);
+    }
+}
@@ -1,60 +1,614 @@
 use crate::physics::Pos2D;
 
-use ggez::event::KeyCode;
+use ggez::event::{Axis, Button, KeyCode, MouseButton};
+use glam::Vec2;
+use std::collections::{HashMap, HashSet};
 
+pub const AXIS_PRESS_THRESHOLD: f32 = 0.5; // how far a stick goes to count as a press, when rebinding
+pub const DEAD_ZONE_RANGE: (f32, f32) = (0., 0.9);
+
+/// What the player wants to do, whatever it's bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Fire,
+    Sneak,
+    NextMap,
+    Interact,
+    NextWeapon,
+    Inventory,
+    Pause,
+    Debug,
+    Hotbar(usize),
+}
+
+/// Actions with a value between -1 and 1, y goes down
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AxisAction {
+    MoveX,
+    MoveY,
+    AimX,
+    AimY,
+}
+
+/// How the stick position turns into a value, past the dead zone
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResponseCurve {
+    Linear,
+    Quadratic, // finer control of small movements
+    Cubic,
+}
+
+/// Shared by both sticks
+#[derive(Clone, Copy, Debug)]
+pub struct StickTuning {
+    pub dead_zone: f32, // radial, as a part of the full tilt
+    pub curve: ResponseCurve,
+    pub aim_assist: bool, // pulls the stick aim toward a monster in sight
+}
+
+/// State of the actions, fed by the input events and read by the gameplay.
+/// Pressed and released only last for the frame they happened in.
 #[derive(Default)]
 pub struct Input {
-    pub up: bool,
-    pub left: bool,
-    pub down: bool,
-    pub right: bool,
-    pub pointing: Pos2D<f32>,
-    pub rightpad: Pos2D<i32>,
+    held: HashMap<Action, u32>, // how many of its bindings are down
+    pressed: HashSet<Action>,
+    released: HashSet<Action>,
+    axes: HashMap<AxisAction, f32>, // from the gamepad
+    pub sticks: StickTuning,
+    pub pointing: Pos2D<f32>, // the mouse, in window pixels
+    pub mouse_aim: Vec2,      // the mouse in the world, set before each update of the game
+    pub gamepad: bool,        // the last input came from a gamepad
+}
+
+/// Everything the gameplay reads from an Input during one update, to record it and play it back
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub held: Vec<Action>,
+    pub pressed: Vec<Action>,
+    pub released: Vec<Action>,
+    pub axes: [f32; 4], // raw, in the order of AxisAction::ALL
+    pub mouse_aim: Vec2,
     pub gamepad: bool,
-    pub mouse_left: bool,
-    pub mouse_right: bool,
-    pub controler_south: bool,
-    pub controler_east: bool,
-    pub controler_west: bool,
-    pub controler_north: bool,
-    pub controler_right_trigger_1: bool,
-    pub controler_left_trigger_1: bool,
-    pub controler_right_trigger_2: bool,
-    pub controler_left_trigger_2: bool,
-    pub controler_start: bool,
-    pub controler_select: bool,
-    pub controler_mode: bool,
-    pub controler_dpad_right: bool,
-    pub controler_dpad_up: bool,
-    pub controler_dpad_left: bool,
-    pub controler_dpad_down: bool,
-    pub controler_left_thumb: bool,
-    pub controler_right_thumb: bool,
 }
 
+impl Action {
+    /// The name it's saved under
+    pub fn name(&self) -> String {
+        match self {
+            Action::Up => "up".to_string(),
+            Action::Down => "down".to_string(),
+            Action::Left => "left".to_string(),
+            Action::Right => "right".to_string(),
+            Action::Fire => "fire".to_string(),
+            Action::Sneak => "sneak".to_string(),
+            Action::NextMap => "next_map".to_string(),
+            Action::Interact => "interact".to_string(),
+            Action::NextWeapon => "next_weapon".to_string(),
+            Action::Inventory => "inventory".to_string(),
+            Action::Pause => "pause".to_string(),
+            Action::Debug => "debug".to_string(),
+            Action::Hotbar(slot) => format!("hotbar_{}", slot + 1),
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(slot) = name.strip_prefix("hotbar_") {
+            return match slot.parse::<usize>() {
+                Ok(slot) if slot >= 1 => Some(Action::Hotbar(slot - 1)),
+                _ => None,
+            };
+        }
+        [
+            Action::Up,
+            Action::Down,
+            Action::Left,
+            Action::Right,
+            Action::Fire,
+            Action::Sneak,
+            Action::NextMap,
+            Action::Interact,
+            Action::NextWeapon,
+            Action::Inventory,
+            Action::Pause,
+            Action::Debug,
+        ]
+        .iter()
+        .copied()
+        .find(|action| action.name() == name)
+    }
+}
+
+impl AxisAction {
+    pub const ALL: [AxisAction; 4] = [
+        AxisAction::MoveX,
+        AxisAction::MoveY,
+        AxisAction::AimX,
+        AxisAction::AimY,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            AxisAction::MoveX => "move_x",
+            AxisAction::MoveY => "move_y",
+            AxisAction::AimX => "aim_x",
+            AxisAction::AimY => "aim_y",
+        }
+    }
+}
+
+impl ResponseCurve {
+    pub const ALL: [ResponseCurve; 3] = [
+        ResponseCurve::Linear,
+        ResponseCurve::Quadratic,
+        ResponseCurve::Cubic,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResponseCurve::Linear => "linear",
+            ResponseCurve::Quadratic => "quadratic",
+            ResponseCurve::Cubic => "cubic",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|curve| curve.name() == name)
+    }
+    /// From 0 to 1
+    pub fn apply(&self, value: f32) -> f32 {
+        match self {
+            ResponseCurve::Linear => value,
+            ResponseCurve::Quadratic => value * value,
+            ResponseCurve::Cubic => value * value * value,
+        }
+    }
+}
+
+impl Default for StickTuning {
+    fn default() -> Self {
+        StickTuning {
+            dead_zone: 0.2,
+            curve: ResponseCurve::Linear,
+            aim_assist: true,
+        }
+    }
+}
+
+impl StickTuning {
+    /// Nothing inside the dead zone, then from 0 to 1 along the curve. The direction is kept,
+    /// so a diagonal isn't pulled toward the axes like with a dead zone per axis.
+    pub fn apply(&self, stick: Vec2) -> Vec2 {
+        let tilt = stick.length().min(1.);
+        if tilt <= self.dead_zone || tilt == 0. {
+            return Vec2::ZERO;
+        }
+        let tilt_past = (tilt - self.dead_zone) / (1. - self.dead_zone);
+        stick / stick.length() * self.curve.apply(tilt_past)
+    }
+}
+
+impl Input {
+    pub fn press(&mut self, action: Action) {
+        let count = self.held.entry(action).or_insert(0);
+        if *count == 0 {
+            self.pressed.insert(action);
+        }
+        *count += 1;
+    }
+    pub fn release(&mut self, action: Action) {
+        if let Some(count) = self.held.get_mut(&action) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.held.remove(&action);
+                self.released.insert(action);
+            }
+        }
+    }
+    /// Went down this frame
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+    pub fn held(&self, action: Action) -> bool {
+        self.held.contains_key(&action)
+    }
+    /// Went up this frame
+    pub fn released(&self, action: Action) -> bool {
+        self.released.contains(&action)
+    }
+    /// To call once everything had a chance to look at this frame's inputs
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        // Sorted by name, the same state always gives the same snapshot
+        let sorted = |mut actions: Vec<Action>| {
+            actions.sort_by_key(|action| action.name());
+            actions
+        };
+        let mut axes = [0.; 4];
+        for (index, axis) in AxisAction::ALL.iter().enumerate() {
+            axes[index] = self.axes.get(axis).copied().unwrap_or(0.);
+        }
+        Snapshot {
+            held: sorted(self.held.keys().copied().collect()),
+            pressed: sorted(self.pressed.iter().copied().collect()),
+            released: sorted(self.released.iter().copied().collect()),
+            axes,
+            mouse_aim: self.mouse_aim,
+            gamepad: self.gamepad,
+        }
+    }
+    /// Puts back the state of a snapshot, the stick tuning and the mouse pointer are left alone
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.held = snapshot.held.iter().map(|action| (*action, 1)).collect();
+        self.pressed = snapshot.pressed.iter().copied().collect();
+        self.released = snapshot.released.iter().copied().collect();
+        self.axes = AxisAction::ALL
+            .iter()
+            .copied()
+            .zip(snapshot.axes.iter().copied())
+            .collect();
+        self.mouse_aim = snapshot.mouse_aim;
+        self.gamepad = snapshot.gamepad;
+    }
+
+    /// The value the gamepad gives, y going up
+    pub fn set_axis(&mut self, axis: AxisAction, value: f32) {
+        let value = match axis {
+            AxisAction::MoveY | AxisAction::AimY => -value,
+            _ => value,
+        };
+        self.axes.insert(axis, value);
+    }
+    fn stick(&self, x: AxisAction, y: AxisAction) -> Vec2 {
+        let value = |axis| self.axes.get(&axis).copied().unwrap_or(0.);
+        self.sticks.apply(Vec2::new(value(x), value(y)))
+    }
+    /// Up to a length of 1, the keys always go full speed and the stick as far as it's tilted
+    pub fn movement(&self) -> Vec2 {
+        let digital = |negative: Action, positive: Action| {
+            self.held(positive) as i32 as f32 - self.held(negative) as i32 as f32
+        };
+        let digital = Vec2::new(
+            digital(Action::Left, Action::Right),
+            digital(Action::Up, Action::Down),
+        );
+        let digital = if digital == Vec2::ZERO {
+            digital
+        } else {
+            digital.normalize()
+        };
+        let movement = digital + self.stick(AxisAction::MoveX, AxisAction::MoveY);
+        if movement.length() > 1. {
+            movement.normalize()
+        } else {
+            movement
+        }
+    }
+    /// The aim stick, zero when it's let go
+    pub fn aim(&self) -> Vec2 {
+        self.stick(AxisAction::AimX, AxisAction::AimY)
+    }
+}
+
+/// Something that can be bound to an action, on the keyboard or the mouse
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+/// A primary and a secondary binding, either can be empty
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bind(pub [Option<Binding>; 2]);
+
+/// Keyboard and mouse bindings of each action
+#[derive(Clone)]
 pub struct KeyMap {
-    pub up: KeyCode,
-    pub down: KeyCode,
-    pub left: KeyCode,
-    pub right: KeyCode,
-    pub next_map: KeyCode,
-    pub escape: KeyCode,
-    pub inventory: KeyCode,
+    pub binds: Vec<(Action, Bind)>,
+}
+
+impl Binding {
+    /// Keys have the name winit gives them, mouse buttons are "MouseLeft", "Mouse4"...
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(MouseButton::Left) => "MouseLeft".to_string(),
+            Binding::Mouse(MouseButton::Right) => "MouseRight".to_string(),
+            Binding::Mouse(MouseButton::Middle) => "MouseMiddle".to_string(),
+            Binding::Mouse(MouseButton::Other(button)) => format!("Mouse{}", button),
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "MouseLeft" => Some(Binding::Mouse(MouseButton::Left)),
+            "MouseRight" => Some(Binding::Mouse(MouseButton::Right)),
+            "MouseMiddle" => Some(Binding::Mouse(MouseButton::Middle)),
+            _ => match name.strip_prefix("Mouse").map(|n| n.parse::<u16>()) {
+                Some(Ok(button)) => Some(Binding::Mouse(MouseButton::Other(button))),
+                _ => key_from_name(name).map(Binding::Key),
+            },
+        }
+    }
+}
+
+impl Bind {
+    fn key(key: KeyCode) -> Self {
+        Bind([Some(Binding::Key(key)), None])
+    }
+    fn keys(primary: KeyCode, secondary: KeyCode) -> Self {
+        Bind([Some(Binding::Key(primary)), Some(Binding::Key(secondary))])
+    }
+    pub fn matches(&self, input: Binding) -> bool {
+        self.0.contains(&Some(input))
+    }
+    /// The first binding set, for the HUD
+    pub fn name(&self) -> String {
+        match self.0.iter().flatten().next() {
+            Some(binding) => binding.name(),
+            None => "-".to_string(),
+        }
+    }
 }
 
 impl Default for KeyMap {
+    fn default() -> Self {
+        let mut binds = vec![
+            (Action::Up, Bind::keys(KeyCode::W, KeyCode::Up)),
+            (Action::Down, Bind::keys(KeyCode::S, KeyCode::Down)),
+            (Action::Left, Bind::keys(KeyCode::A, KeyCode::Left)),
+            (Action::Right, Bind::keys(KeyCode::D, KeyCode::Right)),
+            (
+                Action::Fire,
+                Bind([Some(Binding::Mouse(MouseButton::Left)), None]),
+            ),
+            (Action::Sneak, Bind::key(KeyCode::LShift)),
+            (Action::NextMap, Bind::key(KeyCode::R)),
+            (Action::Pause, Bind::key(KeyCode::Escape)),
+            (Action::Inventory, Bind::key(KeyCode::E)),
+            (Action::NextWeapon, Bind::key(KeyCode::Q)),
+            (Action::Debug, Bind::key(KeyCode::F3)),
+            (Action::Interact, Bind::key(KeyCode::F)),
+        ];
+        let hotbar: [KeyCode; crate::item::HOTBAR_SIZE] =
+            [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
+        for (slot, key) in hotbar.iter().enumerate() {
+            binds.push((Action::Hotbar(slot), Bind::key(*key)));
+        }
+        Self { binds }
+    }
+}
+
+impl KeyMap {
+    pub fn get(&self, action: Action) -> Bind {
+        self.binds
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .map(|(_, bind)| *bind)
+            .unwrap_or(Bind([None, None]))
+    }
+    /// Every action bound to the input
+    pub fn actions(&self, input: Binding) -> Vec<Action> {
+        self.binds
+            .iter()
+            .filter(|(_, bind)| bind.matches(input))
+            .map(|(action, _)| *action)
+            .collect()
+    }
+    /// Every action with the name it's saved under
+    pub fn bindings_mut(&mut self) -> Vec<(String, &mut Bind)> {
+        self.binds
+            .iter_mut()
+            .map(|(action, bind)| (action.name(), bind))
+            .collect()
+    }
+}
+
+/// Gamepad buttons and sticks of each action
+#[derive(Clone)]
+pub struct PadMap {
+    pub buttons: Vec<(Action, Button)>,
+    pub axes: Vec<(AxisAction, Axis)>,
+    pub sticks: StickTuning,
+}
+
+impl Default for PadMap {
     fn default() -> Self {
         Self {
-            up: KeyCode::W,
-            down: KeyCode::S,
-            left: KeyCode::A,
-            right: KeyCode::D,
-            next_map: KeyCode::R,
-            escape: KeyCode::Escape,
-            inventory: KeyCode::E,
+            buttons: vec![
+                (Action::Fire, Button::South),
+                (Action::Sneak, Button::LeftTrigger2),
+                (Action::Interact, Button::West),
+                (Action::Inventory, Button::North),
+                (Action::NextMap, Button::East),
+                (Action::NextWeapon, Button::RightTrigger),
+                (Action::Pause, Button::Start),
+                (Action::Hotbar(0), Button::DPadLeft),
+                (Action::Hotbar(1), Button::DPadUp),
+                (Action::Hotbar(2), Button::DPadRight),
+            ],
+            axes: vec![
+                (AxisAction::MoveX, Axis::LeftStickX),
+                (AxisAction::MoveY, Axis::LeftStickY),
+                (AxisAction::AimX, Axis::RightStickX),
+                (AxisAction::AimY, Axis::RightStickY),
+            ],
+            sticks: StickTuning::default(),
         }
     }
 }
+
+impl PadMap {
+    pub fn actions(&self, button: Button) -> Vec<Action> {
+        self.buttons
+            .iter()
+            .filter(|(_, bound)| *bound == button)
+            .map(|(action, _)| *action)
+            .collect()
+    }
+    pub fn axis_actions(&self, axis: Axis) -> Vec<AxisAction> {
+        self.axes
+            .iter()
+            .filter(|(_, bound)| *bound == axis)
+            .map(|(action, _)| *action)
+            .collect()
+    }
+    /// Every binding with the name it's saved under
+    pub fn bindings_mut(&mut self) -> Vec<(String, &mut Button)> {
+        self.buttons
+            .iter_mut()
+            .map(|(action, button)| (action.name(), button))
+            .collect()
+    }
+    pub fn axes_mut(&mut self) -> Vec<(String, &mut Axis)> {
+        self.axes
+            .iter_mut()
+            .map(|(action, axis)| (action.name().to_string(), axis))
+            .collect()
+    }
+}
+
+/// Keys that can be bound, winit can't turn a name back into a key on its own
+const KEYS: [KeyCode; 80] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Escape,
+    KeyCode::Tab,
+    KeyCode::Space,
+    KeyCode::Return,
+    KeyCode::Back,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+    KeyCode::LAlt,
+    KeyCode::RAlt,
+    KeyCode::Capital,
+    KeyCode::Grave,
+    KeyCode::Minus,
+    KeyCode::Equals,
+    KeyCode::LBracket,
+    KeyCode::RBracket,
+    KeyCode::Backslash,
+    KeyCode::Semicolon,
+    KeyCode::Apostrophe,
+    KeyCode::Comma,
+    KeyCode::Period,
+];
+
+const BUTTONS: [Button; 19] = [
+    Button::South,
+    Button::East,
+    Button::North,
+    Button::West,
+    Button::C,
+    Button::Z,
+    Button::LeftTrigger,
+    Button::LeftTrigger2,
+    Button::RightTrigger,
+    Button::RightTrigger2,
+    Button::Select,
+    Button::Start,
+    Button::Mode,
+    Button::LeftThumb,
+    Button::RightThumb,
+    Button::DPadUp,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+];
+
+/// Only the keys listed above can be saved and read back
+pub fn can_bind(key: KeyCode) -> bool {
+    KEYS.contains(&key)
+}
+
+/// The same name as the one shown in the settings menu
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEYS.iter()
+        .copied()
+        .find(|key| format!("{:?}", key) == name)
+}
+
+const AXES: [Axis; 6] = [
+    Axis::LeftStickX,
+    Axis::LeftStickY,
+    Axis::RightStickX,
+    Axis::RightStickY,
+    Axis::LeftZ,
+    Axis::RightZ,
+];
+
+pub fn axis_from_name(name: &str) -> Option<Axis> {
+    AXES.iter()
+        .copied()
+        .find(|axis| format!("{:?}", axis) == name)
+}
+
+pub fn button_from_name(name: &str) -> Option<Button> {
+    BUTTONS
+        .iter()
+        .copied()
+        .find(|button| format!("{:?}", button) == name)
+}
 /*
 fn egui_to_winit_key_code(key: Key) -> Option<KeyCode> {
     Some(match key {
@@ -106,3 +660,141 @@ fn egui_to_winit_key_code(key: Key) -> Option<KeyCode> {
     })
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressed_lasts_one_frame_and_held_until_released() {
+        let mut input = Input::default();
+        input.press(Action::Fire);
+        assert!(input.pressed(Action::Fire));
+        assert!(input.held(Action::Fire));
+        assert!(!input.released(Action::Fire));
+
+        input.end_frame();
+        assert!(!input.pressed(Action::Fire));
+        assert!(input.held(Action::Fire));
+
+        input.release(Action::Fire);
+        assert!(!input.held(Action::Fire));
+        assert!(input.released(Action::Fire));
+
+        input.end_frame();
+        assert!(!input.released(Action::Fire));
+    }
+
+    #[test]
+    fn a_tap_within_a_frame_is_both_pressed_and_released() {
+        let mut input = Input::default();
+        input.press(Action::Hotbar(0));
+        input.release(Action::Hotbar(0));
+        assert!(input.pressed(Action::Hotbar(0)));
+        assert!(input.released(Action::Hotbar(0)));
+        assert!(!input.held(Action::Hotbar(0)));
+    }
+
+    #[test]
+    fn an_action_stays_held_while_one_of_its_bindings_is_down() {
+        let mut input = Input::default();
+        input.press(Action::Up); // W
+        input.end_frame();
+        input.press(Action::Up); // and the arrow
+        assert!(!input.pressed(Action::Up));
+
+        input.release(Action::Up);
+        assert!(input.held(Action::Up));
+        assert!(!input.released(Action::Up));
+
+        input.release(Action::Up);
+        assert!(!input.held(Action::Up));
+        assert!(input.released(Action::Up));
+    }
+
+    #[test]
+    fn releasing_what_was_never_pressed_does_nothing() {
+        let mut input = Input::default();
+        input.release(Action::Sneak);
+        assert!(!input.released(Action::Sneak));
+        input.press(Action::Sneak);
+        assert!(input.held(Action::Sneak));
+    }
+
+    #[test]
+    fn opposite_directions_cancel_out() {
+        let mut input = Input::default();
+        input.press(Action::Left);
+        input.press(Action::Down);
+        assert_eq!(input.movement(), Vec2::new(-1., 1.).normalize());
+        input.press(Action::Right);
+        assert_eq!(input.movement(), Vec2::new(0., 1.));
+    }
+
+    fn tuning(dead_zone: f32, curve: ResponseCurve) -> StickTuning {
+        StickTuning {
+            dead_zone,
+            curve,
+            aim_assist: false,
+        }
+    }
+
+    #[test]
+    fn nothing_up_to_the_dead_zone() {
+        let sticks = tuning(0.2, ResponseCurve::Linear);
+        assert_eq!(sticks.apply(Vec2::ZERO), Vec2::ZERO);
+        assert_eq!(sticks.apply(Vec2::new(0.1, 0.)), Vec2::ZERO);
+        assert_eq!(sticks.apply(Vec2::new(0., -0.2)), Vec2::ZERO);
+        let past = sticks.apply(Vec2::new(0.3, 0.));
+        assert!(past.x > 0. && past.x < 0.3, "{:?}", past);
+    }
+
+    #[test]
+    fn the_dead_zone_is_radial() {
+        let sticks = tuning(0.2, ResponseCurve::Linear);
+        // Past the dead zone diagonally while each axis alone is inside it
+        let diagonal = sticks.apply(Vec2::new(0.18, 0.18));
+        assert!(diagonal.x > 0. && diagonal.y > 0.);
+        assert!((diagonal.x - diagonal.y).abs() < 1e-6);
+    }
+
+    #[test]
+    fn full_tilt_is_full_speed_whatever_the_dead_zone() {
+        for dead_zone in [0., 0.2, 0.9].iter() {
+            let sticks = tuning(*dead_zone, ResponseCurve::Linear);
+            assert!((sticks.apply(Vec2::new(0., 1.)).length() - 1.).abs() < 1e-6);
+            // Square gates go past a length of 1 in the corners
+            assert!((sticks.apply(Vec2::new(1., 1.)).length() - 1.).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn each_curve_goes_from_0_to_1() {
+        for curve in ResponseCurve::ALL.iter() {
+            assert_eq!(curve.apply(0.), 0.);
+            assert_eq!(curve.apply(1.), 1.);
+        }
+        assert_eq!(ResponseCurve::Linear.apply(0.5), 0.5);
+        assert_eq!(ResponseCurve::Quadratic.apply(0.5), 0.25);
+        assert_eq!(ResponseCurve::Cubic.apply(0.5), 0.125);
+    }
+
+    #[test]
+    fn the_curve_applies_past_the_dead_zone() {
+        // Halfway between the dead zone and full tilt
+        let stick = Vec2::new(0.6, 0.);
+        let value = |curve| tuning(0.2, curve).apply(stick).x;
+        assert!((value(ResponseCurve::Linear) - 0.5).abs() < 1e-6);
+        assert!((value(ResponseCurve::Quadratic) - 0.25).abs() < 1e-6);
+        assert!((value(ResponseCurve::Cubic) - 0.125).abs() < 1e-6);
+    }
+
+    #[test]
+    fn the_stick_keeps_its_tilt_and_the_keys_go_full_speed() {
+        let mut input = Input::default();
+        input.set_axis(AxisAction::MoveX, 0.6);
+        assert!((input.movement().x - 0.5).abs() < 1e-6);
+        input.press(Action::Right);
+        assert_eq!(input.movement(), Vec2::new(1., 0.));
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+
+use crate::weapon;
+
+pub const HOTBAR_SIZE: usize = 3;
+pub const HEALTH_POTION_HEAL: i32 = 40;
+pub const BOMB_FUSE: f32 = 1.5; // seconds
+pub const BOMB_RADIUS: f32 = 2.; // in tiles
+pub const BOMB_DAMAGE: i32 = 60;
+pub const TORCH_LIGHT_RADIUS: f32 = 4.; // in tiles
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Consumable {
+    HealthPotion,
+    Torch,
+    Bomb,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub item: Consumable,
+    pub count: u32,
+}
+
+pub struct ItemInventory {
+    pub stacks: Vec<ItemStack>,
+    pub hotbar: [Option<Consumable>; HOTBAR_SIZE],
+}
+
+pub struct Pickup {
+    pub pos: glam::Vec2,
+    pub drop: weapon::ObjectDrop,
+}
+
+pub struct Bomb {
+    pub pos: glam::Vec2,
+    pub fuse: f32,
+}
+
+impl Consumable {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Consumable::HealthPotion => "Health potion",
+            Consumable::Torch => "Torch",
+            Consumable::Bomb => "Bomb",
+        }
+    }
+    /// Name of its sprite in the atlas
+    pub fn sprite(&self) -> &'static str {
+        match self {
+            Consumable::HealthPotion => "item.potion",
+            Consumable::Torch => "item.torch",
+            Consumable::Bomb => "item.bomb",
+        }
+    }
+    pub fn description(&self) -> String {
+        match self {
+            Consumable::HealthPotion => format!("Heals {} hp", HEALTH_POTION_HEAL),
+            Consumable::Torch => "Lights the tile you're standing on, for good".to_string(),
+            Consumable::Bomb => format!(
+                "Thrown where you aim, breaks walls and deals {} damage",
+                BOMB_DAMAGE
+            ),
+        }
+    }
+    pub fn max_stack(&self) -> u32 {
+        match self {
+            Consumable::HealthPotion => 5,
+            Consumable::Torch => 10,
+            Consumable::Bomb => 5,
+        }
+    }
+}
+
+impl ItemInventory {
+    pub fn new() -> Self {
+        ItemInventory {
+            stacks: vec![
+                ItemStack {
+                    item: Consumable::HealthPotion,
+                    count: 2,
+                },
+                ItemStack {
+                    item: Consumable::Torch,
+                    count: 3,
+                },
+                ItemStack {
+                    item: Consumable::Bomb,
+                    count: 1,
+                },
+            ],
+            hotbar: [
+                Some(Consumable::HealthPotion),
+                Some(Consumable::Torch),
+                Some(Consumable::Bomb),
+            ],
+        }
+    }
+    pub fn count(&self, item: Consumable) -> u32 {
+        self.stacks
+            .iter()
+            .filter(|s| s.item == item)
+            .map(|s| s.count)
+            .sum()
+    }
+    /// Fills the existing stacks first, then opens new ones
+    pub fn add(&mut self, item: Consumable, mut count: u32) {
+        for stack in self.stacks.iter_mut().filter(|s| s.item == item) {
+            let room = item.max_stack() - stack.count;
+            let added = std::cmp::min(room, count);
+            stack.count += added;
+            count -= added;
+        }
+        while count > 0 {
+            let added = std::cmp::min(item.max_stack(), count);
+            self.stacks.push(ItemStack { item, count: added });
+            count -= added;
+        }
+    }
+    /// Removes one item, returns false if there was none
+    pub fn take(&mut self, item: Consumable) -> bool {
+        match self.stacks.iter().rposition(|s| s.item == item) {
+            Some(index) => {
+                self.stacks[index].count -= 1;
+                if self.stacks[index].count == 0 {
+                    self.stacks.remove(index);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Pickup {
+    pub fn new(pos: glam::Vec2, drop: weapon::ObjectDrop) -> Self {
+        Pickup { pos, drop }
+    }
+}
+
+impl Bomb {
+    pub fn new(pos: glam::Vec2) -> Self {
+        Bomb {
+            pos,
+            fuse: BOMB_FUSE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty() -> ItemInventory {
+        ItemInventory {
+            stacks: Vec::new(),
+            hotbar: [None; HOTBAR_SIZE],
+        }
+    }
+
+    #[test]
+    fn adding_fills_the_stacks_before_opening_new_ones() {
+        let mut items = empty();
+        items.add(Consumable::Bomb, 3);
+        items.add(Consumable::Bomb, 4);
+        let counts: Vec<u32> = items.stacks.iter().map(|s| s.count).collect();
+        assert_eq!(counts, vec![5, 2]);
+        assert_eq!(items.count(Consumable::Bomb), 7);
+        assert_eq!(items.count(Consumable::Torch), 0);
+    }
+
+    #[test]
+    fn taking_empties_the_last_stack_first() {
+        let mut items = empty();
+        items.add(Consumable::HealthPotion, 6);
+        assert!(items.take(Consumable::HealthPotion));
+        assert_eq!(items.stacks.len(), 1);
+        assert_eq!(items.count(Consumable::HealthPotion), 5);
+        assert!(!items.take(Consumable::Torch));
+    }
+}
@@ -2,282 +2,504 @@
 use ggez;
 use glam;
 
+mod assets;
+mod audio;
+mod biome;
 mod bloc;
 mod camera;
+mod clock;
+mod hearing;
+mod hud;
 mod id;
 mod input;
+mod item;
 mod map;
+mod mapgen;
 mod menu;
 mod monster;
 mod physics;
 mod player;
+mod replay;
+mod run;
+mod settings;
+mod sprite;
+mod status;
+mod tileset;
+mod view;
 mod weapon;
-
-const GAMEPAD_DEAD_ZONE: f32 = 0.5;
-const GAMEPAD_SPEED: f32 = 400.;
-const DEFAULT_WINDOW_SIZE: (f32, f32) = (1920., 1080.);
+mod world;
 
 struct Game {
-    map: map::Map,
-    player: player::Player,
-    monster_manager: monster::MonsterManager,
+    run: run::Run,
+    tileset: tileset::Tileset,
     camera: camera::Camera,
+    hud: hud::Hud,
     window_size: glam::Vec2,
+    view: view::ViewTransform,
     menu: menu::Gui,
-    id_manager: id::IdManager,
     keymap: input::KeyMap,
+    padmap: input::PadMap,
+    window_settings: settings::WindowSettings,
+    sprites: sprite::SpriteAtlas,
+    assets: assets::Assets,
+    audio: audio::AudioManager,
+    font: ggez::graphics::Font,
+    recorder: Option<replay::Recorder>,
+    playback: Option<replay::Playback>,
 }
 
 impl Game {
-    fn new(ctx: &mut ggez::Context) -> ggez::GameResult<Self> {
-        let mut id_manager = id::IdManager::new();
-        // set the tile size
-        let tile_size = 60.;
-
-        // load the map
-        let mut map = map::Map::new(tile_size, ctx);
-        map.gen_new_map(ctx, &mut id_manager)?;
-
-        // Create the player
-        let player_spawn_pos = glam::Vec2::new(
-            tile_size * map.spawn.x + tile_size / 2. - 30. / 2.,
-            tile_size * map.spawn.y + tile_size / 2. - 30. / 2.,
-        );
-        let player = player::Player::new(
-            player_spawn_pos.x,
-            player_spawn_pos.y,
-            30.,
-            30.,
-            &mut id_manager,
-            ctx,
-        );
+    fn new(
+        ctx: &mut ggez::Context,
+        record: Option<std::path::PathBuf>,
+        replay: Option<std::path::PathBuf>,
+    ) -> ggez::GameResult<Self> {
+        let assets = assets::Assets::load(ctx)?;
+
+        // Load the settings, what's wrong in the file is reported and left to the default
+        let (settings, problems) = settings::Settings::load(ctx);
+        ggez::graphics::set_mode(ctx, settings.window.mode())?;
+        let (width, height) = ggez::graphics::drawable_size(ctx);
+        let window_size = glam::Vec2::new(width, height);
+
+        // A replay goes through the run it was recorded in, with the same stick tuning
+        let playback = replay.and_then(|path| match replay::Replay::load(&path) {
+            Ok(replay) => {
+                println!(
+                    "Playing back '{}', {} updates",
+                    path.display(),
+                    replay.ticks.len()
+                );
+                Some(replay::Playback::new(replay))
+            }
+            Err(e) => {
+                eprintln!(
+                    "[WARNING] Could not load the replay '{}': {}",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        });
+        let (seed, sticks) = match &playback {
+            Some(playback) => (playback.replay.seed, playback.replay.sticks),
+            None => (rand::random(), settings.padmap.sticks),
+        };
+        let mut run = run::Run::new(mapgen::GenerationConfig::load(ctx), seed)?;
+        run.player.inputs.sticks = sticks;
+
+        let mut audio = audio::AudioManager::new(ctx, &assets);
+        audio.volumes = settings.volumes;
+        audio.play(ctx, "spawn");
+        audio.play_music(ctx, &assets, run.map.biome.music());
 
         // Create the camera
-        let mut camera = camera::Camera::new(32., 18.);
-
-        let focus = player.hitbox.center();
-        camera.set_focus(
-            (focus.x, focus.y),
-            (DEFAULT_WINDOW_SIZE.0, DEFAULT_WINDOW_SIZE.1),
-            (map.total_rows, map.total_cols),
-            tile_size,
-        );
+        let mut camera = camera::Camera::new(seed);
+        camera.snap(glam::Vec2::from(run.player.hitbox.center()));
 
-        // Create the monsters
-        let mut monster_manager = monster::MonsterManager::new();
-
-        for _ in 0..1 {
-            monster_manager.new_bot(
-                monster::MonsterType::TestBot,
-                &mut id_manager,
-                (
-                    map.map_file_content.clone(),
-                    map.ghost_tiles.clone(),
-                    map.tile_size,
-                ),
+        // Create main menu
+        let mut main_menu = menu::Gui::new();
+        main_menu.scale = settings.gui_scale;
+        if playback.is_some() {
+            main_menu.show_main = false;
+            main_menu.freeze_game = false;
+        }
+        if !problems.is_empty() {
+            eprintln!(
+                "[WARNING] {} setting(s) could not be loaded, using the defaults instead:",
+                problems.len()
             );
+            for line in problems.iter() {
+                eprintln!("  - {}", line);
+            }
+            main_menu.notice = Some(format!(
+                "{} setting(s) could not be loaded and were reset, see the console",
+                problems.len()
+            ));
         }
 
-        // Create main menu
-        let main_menu = menu::Gui::new();
-
         Ok(Game {
-            map: map,
-            player: player,
-            monster_manager: monster_manager,
+            tileset: tileset::Tileset::new(
+                assets.get_image(assets.image("tileset")),
+                run.map.biome,
+            ),
+            run,
             camera: camera,
-            window_size: glam::Vec2::ZERO,
+            hud: hud::Hud::new(),
+            window_size,
+            view: view::ViewTransform::new(window_size),
             menu: main_menu,
-            id_manager: id_manager,
-            keymap: input::KeyMap::default(),
+            keymap: settings.keymap,
+            padmap: settings.padmap,
+            window_settings: settings.window,
+            sprites: sprite::SpriteAtlas::load(ctx, assets.get_image(assets.image("sprites"))),
+            font: assets.get_font(assets.font("main")),
+            assets,
+            audio,
+            recorder: match record {
+                Some(path) if playback.is_none() => {
+                    println!("Recording to '{}' until the game is closed", path.display());
+                    Some(replay::Recorder::new(path, seed, sticks))
+                }
+                _ => None,
+            },
+            playback,
         })
     }
+    /// Back to the start of the run, to play a replay again from there
+    fn restart(&mut self, ctx: &mut ggez::Context, seed: u64) -> ggez::GameResult {
+        let sticks = self.run.player.inputs.sticks;
+        self.run = run::Run::new(self.run.map.generation.clone(), seed)?;
+        self.run.player.inputs.sticks = sticks;
+        self.hud.reset();
+        self.camera.reseed(seed);
+        self.camera
+            .snap(glam::Vec2::from(self.run.player.hitbox.center()));
+        self.audio
+            .play_music(ctx, &self.assets, self.run.map.biome.music());
+        Ok(())
+    }
+    /// Plays the replay on as fast as the viewer asks, or jumps to another moment of it
+    fn update_playback(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        let mut playback = match self.playback.take() {
+            Some(playback) => playback,
+            None => return Ok(()),
+        };
+        self.menu.replay_viewer(&mut playback);
+        if let Some(target) = playback.seek.take() {
+            let target = target.min(playback.replay.ticks.len());
+            if target < playback.position {
+                self.restart(ctx, playback.replay.seed)?;
+                playback.position = 0;
+            }
+            // All at once, nothing to hear on the way
+            self.audio.muted = true;
+            while playback.position < target {
+                self.replay_tick(ctx, &playback.replay.ticks[playback.position]);
+                playback.position += 1;
+            }
+            self.audio.muted = false;
+        } else if !playback.paused && !self.menu.freeze_game {
+            for _ in 0..playback.speed {
+                if playback.is_over() {
+                    playback.paused = true;
+                    println!("End of the replay, {} updates", playback.position);
+                    break;
+                }
+                self.replay_tick(ctx, &playback.replay.ticks[playback.position]);
+                playback.position += 1;
+            }
+        }
+        if playback.stop {
+            println!(
+                "Replay stopped at update {}, the game goes on from there",
+                playback.position
+            );
+            self.run.player.inputs = input::Input::default();
+            self.run.player.inputs.sticks = self.padmap.sticks;
+        } else {
+            self.playback = Some(playback);
+        }
+        Ok(())
+    }
+    fn replay_tick(&mut self, ctx: &mut ggez::Context, tick: &replay::Tick) {
+        let outcome = self.run.replay_tick(tick);
+        self.present(ctx, tick.dt, outcome);
+    }
+    /// Plays and shows what the run went through in an update
+    fn present(&mut self, ctx: &mut ggez::Context, dt: f32, outcome: run::Outcome) {
+        for (sound, pos) in outcome.sounds.iter() {
+            self.audio.play_at(ctx, sound, *pos);
+        }
+        if outcome.new_floor {
+            self.audio.play(ctx, "spawn");
+            self.audio
+                .play_music(ctx, &self.assets, self.run.map.biome.music());
+        }
+        let player_center = glam::Vec2::from(self.run.player.hitbox.center());
+        if outcome.moved {
+            self.camera.snap(player_center);
+        }
+        for noise in outcome.noises.iter() {
+            self.camera.react(noise);
+        }
+        if outcome.hp_lost > 0 {
+            self.camera.hurt(outcome.hp_lost);
+        }
+        // The open world has no edges to keep the camera in
+        let bounds = match self.run.world {
+            Some(_) => None,
+            None => Some(ggez::graphics::Rect::new(
+                0.,
+                0.,
+                self.run.map.total_cols * self.run.map.tile_size,
+                self.run.map.total_rows * self.run.map.tile_size,
+            )),
+        };
+        self.camera.update(
+            dt,
+            player_center,
+            self.run.player.los.angle,
+            view::VIRTUAL_SIZE,
+            bounds,
+        );
+    }
+    /// Opens the main menu, or goes back to it from the settings
+    fn pause(&mut self) {
+        if !self.menu.show_main && !self.menu.show_settings {
+            self.menu.show_main = true;
+            self.menu.show_inventory = false;
+            self.menu.freeze_game = true
+        } else if self.menu.show_settings {
+            self.menu.show_settings = false;
+            self.menu.settings_closed = true;
+            self.menu.show_main = true;
+            self.menu.freeze_game = true
+        } else if self.menu.show_main {
+            self.menu.show_main = true;
+            self.menu.freeze_game = true
+        }
+    }
+    fn toggle_inventory(&mut self) {
+        if !self.menu.show_main && !self.menu.show_settings {
+            self.menu.show_inventory = !self.menu.show_inventory;
+        }
+    }
+    /// Applies what changed in the settings menu and writes it to the settings file
+    fn save_settings(&mut self, ctx: &mut ggez::Context) {
+        // A recording keeps the tuning it started with, its replay would drift otherwise
+        if self.recorder.is_none() && self.playback.is_none() {
+            self.run.player.inputs.sticks = self.padmap.sticks;
+        }
+        if let Err(e) = ggez::graphics::set_mode(ctx, self.window_settings.mode()) {
+            eprintln!("[WARNING] Could not change the window: {}", e);
+        }
+        settings::Settings {
+            keymap: self.keymap.clone(),
+            padmap: self.padmap.clone(),
+            gui_scale: self.menu.scale,
+            window: self.window_settings,
+            volumes: self.audio.volumes.clone(),
+        }
+        .save(ctx);
+    }
+    fn save_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.save();
+        }
+    }
+    /// Where the mouse is in the world
+    fn mouse_aim(&self) -> glam::Vec2 {
+        self.camera.screen_to_world(
+            self.view
+                .to_virtual(glam::Vec2::from(self.run.player.inputs.pointing)),
+            view::VIRTUAL_SIZE,
+        )
+    }
+    /// The one shot actions of this frame that open the menus, a replay doesn't go through them
+    fn handle_menu_actions(&mut self) {
+        let actions = &self.run.player.inputs;
+        let (pause, inventory, debug) = (
+            actions.pressed(input::Action::Pause),
+            actions.pressed(input::Action::Inventory),
+            actions.pressed(input::Action::Debug),
+        );
+        if pause {
+            self.pause();
+        }
+        if inventory {
+            self.toggle_inventory();
+        }
+        if debug {
+            self.hud.show_debug = !self.hud.show_debug;
+        }
+    }
 }
 impl ggez::event::EventHandler<ggez::GameError> for Game {
     fn update(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        self.handle_menu_actions();
+        // What the menus change in the game goes in the recording
+        let weapon_before = self.run.player.inventory.selected_index;
+        let hotbar_before = self.run.player.items.hotbar;
+        let mut open_world = false;
+
         // Update menu
         if self.menu.show_main {
             self.menu.main_menu(self.window_size, ctx);
         }
         if self.menu.show_settings {
-            self.menu.settings_menu(self.window_size, &mut self.keymap);
+            self.menu.settings_menu(
+                self.window_size,
+                &mut self.audio.volumes,
+                &mut self.window_settings,
+                &mut self.keymap,
+                &mut self.padmap,
+            );
         }
-        if !self.menu.freeze_game {
-            let dt = ggez::timer::delta(ctx).as_secs_f32();
-
-            // Update mouse/gamepad position
-            if self.player.inputs.gamepad {
-                match self.player.inputs.rightpad.x {
-                    1 => self.player.inputs.pointing.x += GAMEPAD_SPEED,
-                    -1 => self.player.inputs.pointing.x -= GAMEPAD_SPEED,
-                    _ => {}
-                }
-                match self.player.inputs.rightpad.y {
-                    1 => self.player.inputs.pointing.y += GAMEPAD_SPEED,
-                    -1 => self.player.inputs.pointing.y -= GAMEPAD_SPEED,
-                    _ => {}
-                }
+        if self.menu.settings_closed {
+            self.menu.settings_closed = false;
+            self.save_settings(ctx);
+        }
+        // The game follows the replay, not the menus
+        if self.menu.show_inventory && self.playback.is_none() {
+            self.menu
+                .inventory_menu(&mut self.run.player.inventory, &mut self.run.player.items);
+        }
+        if self.menu.start_open_world {
+            self.menu.start_open_world = false;
+            if self.playback.is_none() {
+                self.run.start_open_world();
+                open_world = true;
             }
-
-            // Update player
-            self.player.update_movements(
-                &mut self.map.bloc_list,
-                dt,
-                &mut self.id_manager,
-                &mut self.monster_manager,
-                ctx,
-            );
-            self.player.update_los(
-                self.camera.scroll,
-                &mut self.map.bloc_list,
-                &mut self.monster_manager.monster_list,
-            );
-            // let player_vec = Vec::new().push(self.player);
-            self.map.bloc_effects(&mut self.player);
-
-            // Update the monsters
-            while self.monster_manager.monster_list.len() < 30 {
-                self.monster_manager.new_bot(
-                    monster::MonsterType::TestBot,
-                    &mut self.id_manager,
-                    (
-                        self.map.map_file_content.clone(),
-                        self.map.ghost_tiles.clone(),
-                        self.map.tile_size,
-                    ),
-                );
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if open_world {
+                recorder.push_event(replay::Event::OpenWorld);
             }
-            self.monster_manager
-                .update(glam::Vec2::from(self.player.hitbox.center()));
-            self.monster_manager.update_movements(
-                dt,
-                &self.map.bloc_list,
-                (
-                    self.map.map_file_content.clone(),
-                    self.map.ghost_tiles.clone(),
-                    self.map.tile_size,
-                ),
-                (self.map.total_rows, self.map.total_cols),
-            );
-            for index in 0..self.monster_manager.monster_list.len() {
-                self.map
-                    .bloc_effects(&mut self.monster_manager.monster_list[index])
+            if self.run.player.inventory.selected_index != weapon_before {
+                recorder.push_event(replay::Event::SelectWeapon(
+                    self.run.player.inventory.selected_index,
+                ));
             }
-
-            // Update the camera
-            // let focus =
-            // physics::EntityTrait::get_hitbox(&self.monster_manager.monster_list[0]).center();
-            let focus = self.player.hitbox.center();
-            self.camera.set_focus(
-                (focus.x, focus.y),
-                (self.window_size.x, self.window_size.y),
-                (self.map.total_rows, self.map.total_cols),
-                self.map.tile_size,
-            );
+            for (slot, bound) in self.run.player.items.hotbar.iter().enumerate() {
+                if let Some(consumable) = bound {
+                    if hotbar_before[slot] != *bound {
+                        recorder.push_event(replay::Event::BindHotbar(slot, *consumable));
+                    }
+                }
+            }
+        }
+        // Quit from the main menu
+        if !ctx.continuing {
+            self.save_recording();
+        }
+        self.audio
+            .set_listener(glam::Vec2::from(self.run.player.hitbox.center()));
+        self.audio.update(
+            ctx,
+            self.run.player.hp as f32 / player::PLAYER_BASE_HP as f32,
+        );
+        if !self.menu.freeze_game {
+            self.hud
+                .update(ggez::timer::delta(ctx).as_secs_f32(), self.run.player.hp);
+        }
+        if self.playback.is_some() {
+            self.update_playback(ctx)?;
+        } else if !self.menu.freeze_game {
+            let dt = ggez::timer::delta(ctx).as_secs_f32();
+            self.run.player.inputs.mouse_aim = self.mouse_aim();
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.record(dt, self.run.player.inputs.snapshot());
+            }
+            let outcome = self.run.tick(dt);
+            self.present(ctx, dt, outcome);
         }
 
+        self.run.player.inputs.end_frame();
         Ok(())
     }
     fn draw(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
         ggez::graphics::clear(ctx, ggez::graphics::Color::BLACK);
-        let draw_offset = glam::Vec2::new(-self.camera.scroll.x, -self.camera.scroll.y);
-        self.map.draw(ctx, draw_offset)?;
-        self.monster_manager.draw_monsters(ctx, draw_offset)?;
-        self.player.draw(ctx, draw_offset)?;
-        if self.menu.show_main || self.menu.show_settings {
-            self.menu.draw(ctx, draw_offset)?;
-        }
-
-        // Draw the GUI
-        let font = ggez::graphics::Font::new(ctx, "/LiberationMono-Regular.ttf")?;
-
-        let level_dest = glam::Vec2::new(10.0, 10.0);
-        let hp_dest = glam::Vec2::new(200.0, 10.0);
-        let fps_dest = glam::Vec2::new(10.0, 50.0);
-
-        let level_str = format!("Level: {}", self.map.difficulty);
-        let hp_str = format!("HP: {}", self.player.hp);
-        let fps_str = format!("Fps: {:.2}", ggez::timer::fps(ctx));
-
-        let player_hp_color = ggez::graphics::Color::from_rgb(
-            std::cmp::min(
-                255,
-                (255 - (255 * ((self.player.hp - (100 - self.player.hp)) / 100))) as u8,
-            ),
-            std::cmp::min(255, (255 * (self.player.hp / (100 / 2))) as u8),
-            0,
-        );
-
-        let hp_text_fragment = ggez::graphics::TextFragment::new(hp_str).color(player_hp_color);
-        let level_display = ggez::graphics::Text::new((level_str, font, 32.0));
-        let hp_display = ggez::graphics::Text::new((hp_text_fragment, font, 32.0));
-        let fps_display = ggez::graphics::Text::new((fps_str, font, 32.0));
-        ggez::graphics::draw(
+        // World -> view of the camera -> virtual screen -> window.
+        // The zoom and the shake only apply to the world, not to the menus and the HUD.
+        let draw_offset = self.camera.draw_offset(view::VIRTUAL_SIZE);
+        let view = self.camera.view(view::VIRTUAL_SIZE);
+        ggez::graphics::set_screen_coordinates(
             ctx,
-            &level_display,
-            (level_dest, 0.0, ggez::graphics::Color::WHITE),
+            self.view
+                .screen_coordinates(self.camera.screen_coordinates(view::VIRTUAL_SIZE)),
         )?;
-        ggez::graphics::draw(
-            ctx,
-            &hp_display,
-            (hp_dest, 0.0, ggez::graphics::Color::WHITE),
-        )?;
-        ggez::graphics::draw(
+        if let Some(world) = self.run.world.as_mut() {
+            world.draw(
+                ctx,
+                &mut self.tileset,
+                draw_offset,
+                view,
+                glam::Vec2::from(self.run.player.hitbox.center()),
+            )?;
+        } else {
+            self.run.map.draw(
+                ctx,
+                &mut self.tileset,
+                draw_offset,
+                glam::Vec2::from(self.run.player.hitbox.center()),
+                view,
+                &mut self.sprites,
+            )?;
+        }
+        self.run
+            .monster_manager
+            .draw_monsters(ctx, draw_offset, view, &mut self.sprites)?;
+        self.run.player.draw(ctx, draw_offset, &mut self.sprites)?;
+
+        // Draw the HUD, in virtual pixels and anchored to the edges of the screen
+        self.view.apply(ctx)?;
+        let location = match &self.run.world {
+            Some(world) => format!("Open world - {}", world.biome.name()),
+            None => format!(
+                "Depth {} - {}",
+                self.run.map.difficulty,
+                self.run.map.biome.name()
+            ),
+        };
+        let debug = if self.hud.show_debug {
+            let player_center = self.run.player.hitbox.center();
+            format!(
+                "Fps: {:.2}  Monsters: {}  Pos: {:.0}, {:.0}  Zoom: {:.2}",
+                ggez::timer::fps(ctx),
+                self.run.monster_manager.monster_list.len(),
+                player_center.x,
+                player_center.y,
+                self.camera.zoom
+            )
+        } else {
+            String::new()
+        };
+        self.hud.draw(
             ctx,
-            &fps_display,
-            (fps_dest, 0.0, ggez::graphics::Color::WHITE),
+            &hud::HudInfo {
+                player: &self.run.player,
+                keymap: &self.keymap,
+                location,
+                map: if self.run.world.is_none() {
+                    Some(&self.run.map)
+                } else {
+                    None
+                },
+                debug,
+                font: self.font,
+                gui_scale: self.menu.scale,
+            },
         )?;
 
+        self.view.draw_letterbox(ctx)?;
+        self.view.reset(ctx)?;
+        if self.menu.show_main || self.menu.show_settings || self.menu.show_inventory {
+            self.menu.draw(ctx, glam::Vec2::ZERO)?;
+        }
+
         ggez::graphics::present(ctx)?;
         ggez::timer::yield_now();
         Ok(())
     }
     fn key_down_event(
         &mut self,
-        ctx: &mut ggez::Context,
+        _ctx: &mut ggez::Context,
         keycode: ggez::event::KeyCode,
         keymod: ggez::input::keyboard::KeyMods,
-        _repeat: bool,
+        repeat: bool,
     ) -> () {
+        let input = input::Binding::Key(keycode);
+        if self.menu.is_capturing() {
+            self.menu.capture_input(menu::Pressed::Input(input));
+            return;
+        }
         self.menu.egui_backend.input.key_down_event(keycode, keymod);
-        if self.menu.show_settings {
-            self.menu.latest = keycode;
+        if repeat {
+            return;
         }
-
-        if keycode == self.keymap.up {
-            self.player.inputs.up = true;
-        } else if keycode == self.keymap.down {
-            self.player.inputs.down = true;
-        } else if keycode == self.keymap.left {
-            self.player.inputs.left = true;
-        } else if keycode == self.keymap.right {
-            self.player.inputs.right = true;
-        } else if keycode == self.keymap.next_map {
-            let distance_from_end = physics::RayCasting::get_distance(
-                glam::Vec2::from(self.player.hitbox.center()),
-                glam::Vec2::new(
-                    self.map.end.x * self.map.tile_size + (self.map.tile_size / 2.),
-                    self.map.end.y * self.map.tile_size + (self.map.tile_size / 2.),
-                ),
-            );
-            if distance_from_end < self.map.tile_size {
-                self.map.difficulty += 1;
-                self.map.gen_new_map(ctx, &mut self.id_manager).unwrap();
-                self.player.hitbox.x = self.map.spawn.x * self.map.tile_size;
-                self.player.hitbox.y = self.map.spawn.y * self.map.tile_size;
-            }
-        } else if keycode == self.keymap.escape {
-            if !self.menu.show_main && !self.menu.show_settings {
-                self.menu.show_main = true;
-                self.menu.freeze_game = true
-            } else if self.menu.show_settings {
-                self.menu.show_settings = false;
-                self.menu.show_main = true;
-                self.menu.freeze_game = true
-            } else if self.menu.show_main {
-                self.menu.show_main = true;
-                self.menu.freeze_game = true
-            }
+        for action in self.keymap.actions(input) {
+            self.run.player.inputs.press(action);
         }
     }
     fn key_up_event(
@@ -286,14 +508,8 @@ impl ggez::event::EventHandler<ggez::GameError> for Game {
         keycode: ggez::event::KeyCode,
         _keymod: ggez::input::keyboard::KeyMods,
     ) {
-        if keycode == self.keymap.up {
-            self.player.inputs.up = false;
-        } else if keycode == self.keymap.down {
-            self.player.inputs.down = false;
-        } else if keycode == self.keymap.left {
-            self.player.inputs.left = false;
-        } else if keycode == self.keymap.right {
-            self.player.inputs.right = false;
+        for action in self.keymap.actions(input::Binding::Key(keycode)) {
+            self.run.player.inputs.release(action);
         }
     }
     fn mouse_button_down_event(
@@ -303,11 +519,14 @@ impl ggez::event::EventHandler<ggez::GameError> for Game {
         _x: f32,
         _y: f32,
     ) {
+        let input = input::Binding::Mouse(button);
+        if self.menu.is_capturing() {
+            self.menu.capture_input(menu::Pressed::Input(input));
+            return;
+        }
         self.menu.egui_backend.input.mouse_button_down_event(button);
-        match button {
-            ggez::input::mouse::MouseButton::Left => self.player.inputs.mouse_left = true,
-            ggez::input::mouse::MouseButton::Right => self.player.inputs.mouse_right = true,
-            _ => (),
+        for action in self.keymap.actions(input) {
+            self.run.player.inputs.press(action);
         }
     }
 
@@ -319,24 +538,29 @@ impl ggez::event::EventHandler<ggez::GameError> for Game {
         _y: f32,
     ) {
         self.menu.egui_backend.input.mouse_button_up_event(button);
-        match button {
-            ggez::input::mouse::MouseButton::Left => self.player.inputs.mouse_left = false,
-            ggez::input::mouse::MouseButton::Right => self.player.inputs.mouse_right = false,
-            _ => (),
+        for action in self.keymap.actions(input::Binding::Mouse(button)) {
+            self.run.player.inputs.release(action);
         }
     }
 
     fn mouse_motion_event(&mut self, _ctx: &mut ggez::Context, x: f32, y: f32, _dx: f32, _dy: f32) {
         self.menu.egui_backend.input.mouse_motion_event(x, y);
-        self.player.inputs.pointing = physics::Pos2D { x: x, y: y };
-        self.player.inputs.gamepad = false;
+        self.run.player.inputs.pointing = physics::Pos2D { x, y };
+        self.run.player.inputs.gamepad = false;
     }
 
     fn mouse_wheel_event(&mut self, _ctx: &mut ggez::Context, x: f32, y: f32) {
         self.menu.egui_backend.input.mouse_wheel_event(x, y);
+        // The wheel scrolls the menus when they're open
+        if !(self.menu.show_main || self.menu.show_settings || self.menu.show_inventory) {
+            self.camera.zoom_by(y);
+        }
     }
 
     fn text_input_event(&mut self, _ctx: &mut ggez::Context, character: char) {
+        if self.menu.is_capturing() {
+            return;
+        }
         self.menu.egui_backend.input.text_input_event(character);
     }
 
@@ -346,29 +570,13 @@ impl ggez::event::EventHandler<ggez::GameError> for Game {
         btn: ggez::event::Button,
         _id: ggez::input::gamepad::GamepadId,
     ) {
-        match btn {
-            ggez::event::Button::South => self.player.inputs.controler_south = true,
-            ggez::event::Button::North => self.player.inputs.controler_north = true,
-            ggez::event::Button::East => self.player.inputs.controler_east = true,
-            ggez::event::Button::West => self.player.inputs.controler_west = true,
-            ggez::event::Button::Select => self.player.inputs.controler_select = true,
-            ggez::event::Button::Start => self.player.inputs.controler_start = true,
-            ggez::event::Button::Mode => self.player.inputs.controler_mode = true,
-            ggez::event::Button::RightTrigger => {
-                self.player.inputs.controler_right_trigger_1 = true
-            }
-            ggez::event::Button::LeftTrigger => self.player.inputs.controler_left_trigger_1 = true,
-            ggez::event::Button::RightTrigger2 => {
-                self.player.inputs.controler_right_trigger_2 = true
-            }
-            ggez::event::Button::LeftTrigger2 => self.player.inputs.controler_left_trigger_2 = true,
-            ggez::event::Button::DPadRight => self.player.inputs.controler_dpad_right = true,
-            ggez::event::Button::DPadUp => self.player.inputs.controler_dpad_up = true,
-            ggez::event::Button::DPadLeft => self.player.inputs.controler_dpad_left = true,
-            ggez::event::Button::DPadDown => self.player.inputs.controler_dpad_down = true,
-            ggez::event::Button::LeftThumb => self.player.inputs.controler_left_thumb = true,
-            ggez::event::Button::RightThumb => self.player.inputs.controler_right_thumb = true,
-            _ => (),
+        if self.menu.is_capturing() {
+            self.menu.capture_input(menu::Pressed::Button(btn));
+            return;
+        }
+        self.run.player.inputs.gamepad = true;
+        for action in self.padmap.actions(btn) {
+            self.run.player.inputs.press(action);
         }
     }
 
@@ -378,31 +586,8 @@ impl ggez::event::EventHandler<ggez::GameError> for Game {
         btn: ggez::event::Button,
         _id: ggez::input::gamepad::GamepadId,
     ) {
-        match btn {
-            ggez::event::Button::South => self.player.inputs.controler_south = false,
-            ggez::event::Button::North => self.player.inputs.controler_north = false,
-            ggez::event::Button::East => self.player.inputs.controler_east = false,
-            ggez::event::Button::West => self.player.inputs.controler_west = false,
-            ggez::event::Button::Select => self.player.inputs.controler_select = false,
-            ggez::event::Button::Start => self.player.inputs.controler_start = false,
-            ggez::event::Button::Mode => self.player.inputs.controler_mode = false,
-            ggez::event::Button::RightTrigger => {
-                self.player.inputs.controler_right_trigger_1 = false
-            }
-            ggez::event::Button::LeftTrigger => self.player.inputs.controler_left_trigger_1 = false,
-            ggez::event::Button::RightTrigger2 => {
-                self.player.inputs.controler_right_trigger_2 = false
-            }
-            ggez::event::Button::LeftTrigger2 => {
-                self.player.inputs.controler_left_trigger_2 = false
-            }
-            ggez::event::Button::DPadRight => self.player.inputs.controler_dpad_right = false,
-            ggez::event::Button::DPadUp => self.player.inputs.controler_dpad_up = false,
-            ggez::event::Button::DPadLeft => self.player.inputs.controler_dpad_left = false,
-            ggez::event::Button::DPadDown => self.player.inputs.controler_dpad_down = false,
-            ggez::event::Button::LeftThumb => self.player.inputs.controler_left_thumb = false,
-            ggez::event::Button::RightThumb => self.player.inputs.controler_right_thumb = false,
-            _ => (),
+        for action in self.padmap.actions(btn) {
+            self.run.player.inputs.release(action);
         }
     }
 
@@ -413,84 +598,54 @@ impl ggez::event::EventHandler<ggez::GameError> for Game {
         value: f32,
         _id: ggez::input::gamepad::GamepadId,
     ) {
-        self.player.inputs.gamepad = true;
-        match axis {
-            ggez::event::Axis::LeftStickY => {
-                if value >= GAMEPAD_DEAD_ZONE {
-                    self.player.inputs.down = false;
-                    self.player.inputs.up = true;
-                } else if value <= -GAMEPAD_DEAD_ZONE {
-                    self.player.inputs.up = false;
-                    self.player.inputs.down = true;
-                } else {
-                    self.player.inputs.up = false;
-                    self.player.inputs.down = false;
-                }
-            }
-            ggez::event::Axis::LeftStickX => {
-                if value >= GAMEPAD_DEAD_ZONE {
-                    self.player.inputs.left = false;
-                    self.player.inputs.right = true;
-                } else if value <= -GAMEPAD_DEAD_ZONE {
-                    self.player.inputs.right = false;
-                    self.player.inputs.left = true;
-                } else {
-                    self.player.inputs.right = false;
-                    self.player.inputs.left = false;
-                }
-            }
-            ggez::event::Axis::RightStickX => {
-                if value >= GAMEPAD_DEAD_ZONE {
-                    self.player.inputs.rightpad.x = 1;
-                } else if value <= -GAMEPAD_DEAD_ZONE {
-                    self.player.inputs.rightpad.x = -1;
-                } else {
-                    self.player.inputs.rightpad.x = 0;
-                }
+        if self.menu.is_capturing() {
+            if value.abs() >= input::AXIS_PRESS_THRESHOLD {
+                self.menu.capture_input(menu::Pressed::Axis(axis));
             }
-            ggez::event::Axis::RightStickY => {
-                if value >= GAMEPAD_DEAD_ZONE {
-                    self.player.inputs.rightpad.y = -1;
-                } else if value <= -GAMEPAD_DEAD_ZONE {
-                    self.player.inputs.rightpad.y = 1;
-                } else {
-                    self.player.inputs.rightpad.y = 0;
-                }
-            }
-            _ => {}
+            return;
+        }
+        // A resting stick drifts a little, it shouldn't take the aim from the mouse
+        if value.abs() > self.padmap.sticks.dead_zone {
+            self.run.player.inputs.gamepad = true;
+        }
+        for action in self.padmap.axis_actions(axis) {
+            self.run.player.inputs.set_axis(action, value);
         }
     }
 
     fn focus_event(&mut self, _ctx: &mut ggez::Context, _gained: bool) {}
 
     fn quit_event(&mut self, _ctx: &mut ggez::Context) -> bool {
+        self.save_recording();
         false
     }
 
     fn resize_event(&mut self, _ctx: &mut ggez::Context, width: f32, height: f32) {
         self.menu.egui_backend.input.resize_event(width, height);
         self.window_size = glam::Vec2::new(width, height);
+        self.view = view::ViewTransform::new(self.window_size);
         println!("Resized to {}x{}", width, height);
     }
 }
 
 fn main() -> ggez::GameResult {
+    // The saved window setup is read by ggez from its conf.toml, over these defaults
     let cb = ggez::ContextBuilder::new("test game 1", "ggez")
-        .window_setup(
-            ggez::conf::WindowSetup::default()
-                .title("The game title")
-                .vsync(false),
-        )
-        .window_mode(
-            ggez::conf::WindowMode::default()
-                .dimensions(DEFAULT_WINDOW_SIZE.0, DEFAULT_WINDOW_SIZE.1)
-                .fullscreen_type(ggez::conf::FullscreenType::Desktop)
-                .resizable(true),
-        );
+        .window_setup(settings::WindowSettings::default().setup())
+        .window_mode(settings::WindowSettings::default().mode());
     // maybe resource dir
 
     let (mut ctx, events_loop) = cb.build()?;
 
-    let game = Game::new(&mut ctx)?;
+    // --record <file> saves the inputs of the session, --replay <file> plays them back
+    let args: Vec<String> = std::env::args().collect();
+    let path_after = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .and_then(|index| args.get(index + 1))
+            .map(std::path::PathBuf::from)
+    };
+
+    let game = Game::new(&mut ctx, path_after("--record"), path_after("--replay"))?;
     ggez::event::run(ctx, events_loop, game)
 }
@@ -1,15 +1,22 @@
 use ggez;
-use ggez::audio::SoundSource;
 use glam::Vec2;
-use noise::{
-    utils::{NoiseMapBuilder, PlaneMapBuilder},
-    Seedable, SuperSimplex,
-};
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
 use std::time::SystemTime;
 
-use crate::{bloc, id, physics};
+use crate::{
+    biome, bloc, hearing, id, item, mapgen, monster, physics, sprite, status, tileset, weapon,
+};
+
+pub const PLAYER_LIGHT_RADIUS: f32 = 7.; // in tiles
+const MINABLE: [i32; 1] = [4]; // materials a bomb can blow up
+const HOLE: i32 = 29; // left by a collapsing floor
+const TRAP_REVEAL_LIGHT: f32 = 0.35; // torch light needed to spot a hidden trap
+const TRAP_REVEAL_DISTANCE: f32 = 1.5; // in tiles, when moving carefully
+const ROOM_COUNT: usize = 3;
+const VAULT_LOOT: usize = 3;
+const SECTION_SIZE: usize = 16; // in tiles, each one has its own cached sprite batch
+const CULL_MARGIN: f32 = 1.; // in tiles, drawn around the view so nothing pops in
 
 pub struct Map {
     pub map_title: String,
@@ -20,11 +27,59 @@ pub struct Map {
     pub total_rows: f32,
     pub total_cols: f32,
     pub diag_size: f32,
-    pub image_hashmap: HashMap<i32, ggez::graphics::spritebatch::SpriteBatch>,
     pub difficulty: u32,
     pub spawn: Vec2,
     pub end: Vec2,
-    pub new_level_sound: ggez::audio::Source,
+    pub biome: biome::Biome,
+    pub torches: Vec<(usize, usize)>,
+    pub bombs: Vec<item::Bomb>,
+    pub pickups: Vec<item::Pickup>,
+    pub vault_locks: HashMap<(usize, usize), i32>, // door position, key id
+    vault: Option<Room>,                           // its walls can't be blown up
+    pub generation: mapgen::GenerationConfig,
+    pub run_seed: u64,
+    pub floors: HashMap<u32, Floor>, // every visited floor but the current one
+    pub explored: Vec<Vec<bool>>,
+    pub sections: Vec<Section>, // row major, SECTION_SIZE x SECTION_SIZE tiles each
+    pub section_cols: usize,
+}
+
+/// Part of the map drawn from a cached sprite batch, only rebuilt when something in it changed.
+/// It holds the tiles with the ambient and torch light, the player light is added on top.
+pub struct Section {
+    pub batch: Option<ggez::graphics::spritebatch::SpriteBatch>, // made on the first draw
+    pub dirty: bool,
+}
+
+/// Everything needed to find a floor the way it was left
+pub struct Floor {
+    pub grid: Vec<Vec<i32>>,
+    pub bloc_list: Vec<bloc::Bloc>,
+    pub explored: Vec<Vec<bool>>,
+    pub monsters: Vec<monster::Monster>,
+    pub pickups: Vec<item::Pickup>,
+    pub torches: Vec<(usize, usize)>,
+    pub vault_locks: HashMap<(usize, usize), i32>,
+    vault: Option<Room>,
+    pub spawn: Vec2,
+    pub end: Vec2,
+}
+
+pub enum DoorResult {
+    Opened,
+    Closed,
+    Unlocked,
+    Locked,
+    NoDoor,
+}
+
+#[derive(Clone, Copy)]
+struct Room {
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+    door: (usize, usize),
 }
 
 pub struct Tile {
@@ -32,9 +87,10 @@ pub struct Tile {
     pub material: i32,
     pub transparent: bool,
     pub angle: f32,
+    pub variant: usize, // column of the tileset, picked from the neighbours
 }
 impl Map {
-    pub fn new(tile_size: f32, ctx: &mut ggez::Context) -> Self {
+    pub fn new(tile_size: f32, generation: mapgen::GenerationConfig, run_seed: u64) -> Self {
         Map {
             map_title: String::new(),
             tile_size: tile_size,
@@ -44,139 +100,199 @@ impl Map {
             total_rows: 0.,
             total_cols: 0.,
             diag_size: 0.,
-            image_hashmap: HashMap::new(),
             difficulty: 0,
             spawn: Vec2::new(0., 0.),
             end: Vec2::new(0., 0.),
-            new_level_sound: ggez::audio::Source::new(ctx, "/sounds/spawn.wav").unwrap(),
+            biome: biome::Biome::MossyCaves,
+            torches: Vec::new(),
+            bombs: Vec::new(),
+            pickups: Vec::new(),
+            vault_locks: HashMap::new(),
+            vault: None,
+            generation,
+            run_seed,
+            floors: HashMap::new(),
+            explored: Vec::new(),
+            sections: Vec::new(),
+            section_cols: 0,
         }
     }
 
-    pub fn gen_new_map(
+    /// Puts the current floor aside and goes to another one, generating it on the first visit.
+    /// The monsters of the current floor are swapped for the ones of the new floor.
+    /// If the new floor can't be generated, everything stays as it was.
+    pub fn change_floor(
         &mut self,
-        ctx: &mut ggez::Context,
+        depth: u32,
+        monsters: &mut Vec<monster::Monster>,
         id_manager: &mut id::IdManager,
     ) -> ggez::GameResult {
-        const MAP_WIDTH: usize = 100;
-        const MAP_HEIGHT: usize = 100;
+        let previous = self.difficulty;
+        let current = Floor {
+            grid: std::mem::take(&mut self.map_file_content),
+            bloc_list: std::mem::take(&mut self.bloc_list),
+            explored: std::mem::take(&mut self.explored),
+            monsters: std::mem::take(monsters),
+            pickups: std::mem::take(&mut self.pickups),
+            torches: std::mem::take(&mut self.torches),
+            vault_locks: std::mem::take(&mut self.vault_locks),
+            vault: self.vault.take(),
+            spawn: self.spawn,
+            end: self.end,
+        };
+        self.floors.insert(self.difficulty, current);
+        self.difficulty = depth;
 
-        let start_time = SystemTime::now();
-        self.new_level_sound.play(ctx);
-
-        self.ghost_tiles = vec![-1., 9., 10., 18., 19., 20., 21.];
-
-        let tile_translate: HashMap<i32, String> = vec![
-            (-1, "ground".to_string()),
-            (4, "wall".to_string()),
-            (9, "water".to_string()),
-            (12, "crate".to_string()),
-            (18, "lava".to_string()),
-            (21, "end".to_string()),
-            (20, "spawn".to_string()),
-        ]
-        .into_iter()
-        .collect();
-
-        let mut image_hashmap: HashMap<i32, ggez::graphics::spritebatch::SpriteBatch> =
-            HashMap::new();
-        for (key, value) in tile_translate.iter() {
-            if value != "air" {
-                let mut texture_file_name: String = value.to_string();
-                texture_file_name.push_str(".png");
-                let pth = format!("/tiles/{}", texture_file_name);
-                println!("Loading: '{}'", pth);
-                let image = ggez::graphics::Image::new(ctx, pth);
-                image_hashmap.insert(
-                    *key,
-                    ggez::graphics::spritebatch::SpriteBatch::new(image.clone().unwrap()),
-                );
+        match self.floors.remove(&depth) {
+            Some(floor) => {
+                *monsters = self.load_floor(floor);
+                println!("Back on stage '{}'", self.difficulty);
+            }
+            None => {
+                if let Err(e) = self.gen_new_map(id_manager) {
+                    self.difficulty = previous;
+                    let floor = self.floors.remove(&previous).unwrap();
+                    *monsters = self.load_floor(floor);
+                    return Err(e);
+                }
             }
         }
+        self.bombs = Vec::new();
+        Ok(())
+    }
+    /// Puts back a floor put aside by change_floor and returns its monsters
+    fn load_floor(&mut self, floor: Floor) -> Vec<monster::Monster> {
+        self.enter_floor();
+        self.total_rows = floor.grid.len() as f32;
+        self.total_cols = floor.grid[0].len() as f32;
+        self.diag_size =
+            physics::get_diagonal_size(self.total_cols, self.total_rows, self.tile_size);
+        self.map_title = self.difficulty.to_string();
+        self.map_file_content = floor.grid;
+        self.bloc_list = floor.bloc_list;
+        self.explored = floor.explored;
+        self.pickups = floor.pickups;
+        self.torches = floor.torches;
+        self.vault_locks = floor.vault_locks;
+        self.vault = floor.vault;
+        self.spawn = floor.spawn;
+        self.end = floor.end;
+        self.reset_sections();
+        floor.monsters
+    }
+    /// Biome of the floor we're arriving on
+    fn enter_floor(&mut self) {
+        self.biome = biome::Biome::from_depth(self.difficulty);
+        self.ghost_tiles = ghost_tiles();
+    }
+    pub fn gen_new_map(&mut self, id_manager: &mut id::IdManager) -> ggez::GameResult {
+        let start_time = SystemTime::now();
+        self.enter_floor();
+        let biome = self.biome;
 
-        let simplex = SuperSimplex::default().set_seed(rand::random::<u32>());
-
-        let noise_map = PlaneMapBuilder::new(&simplex)
-            .set_size(MAP_WIDTH, MAP_HEIGHT)
-            .set_x_bounds(-5.0, 5.0)
-            .set_y_bounds(-5.0, 5.0)
-            .build();
-
-        let mut map = Box::new([[0; MAP_WIDTH]; MAP_HEIGHT]);
-        let mut line = [0; MAP_WIDTH];
-        map[0] = [4; MAP_HEIGHT];
-        map[MAP_HEIGHT - 1] = [4; MAP_HEIGHT];
-
-        for i in 1..MAP_HEIGHT - 1 {
-            for j in 1..MAP_WIDTH - 1 {
-                line[0] = 4;
-                line[MAP_WIDTH - 1] = 4;
-                let level = noise_map.get_value(i, j);
-                if level <= -0.6 {
-                    if self.difficulty >= 5 && self.difficulty < 20 {
-                        line[j] = 9;
-                    } else if self.difficulty >= 20 {
-                        line[j] = 18;
-                    } else {
-                        line[j] = -1;
-                    }
-                } else if level > -0.6 && level <= 0.5 {
-                    line[j] = -1;
-                } else {
-                    line[j] = 4;
+        // The same run always digs the same floors
+        let mut rng = rand::rngs::StdRng::seed_from_u64(
+            self.run_seed
+                .wrapping_add(self.difficulty as u64 * 0x9E37_79B9_7F4A_7C15),
+        );
+        let generator = mapgen::generator_for_depth(self.difficulty, &mut rng);
+        println!(
+            "Generating stage '{}' with {}",
+            self.difficulty,
+            generator.name()
+        );
+        let mut config = self.generation.for_depth(self.difficulty);
+        config.liquid.get_or_insert(biome.liquid());
+        let mut map_vec = mapgen::generate(generator.as_ref(), &config, &mut rng);
+
+        // Hide some traps in the ground, more and more as you go down
+        let trap_chance =
+            ((0.5 + self.difficulty as f64 * 0.2).min(4.) * biome.hazard_mult()).min(8.) / 100.;
+        for row in map_vec.iter_mut() {
+            for material in row.iter_mut() {
+                if *material == -1 && rng.gen_bool(trap_chance) {
+                    *material = rng.gen_range(22..=25);
                 }
+            }
+        }
 
-                map[i] = line;
+        // Closed rooms, and a locked vault whose key lies somewhere in the cave
+        self.pickups = Vec::new();
+        self.vault_locks = HashMap::new();
+        self.vault = None;
+        let mut rooms: Vec<Room> = Vec::new();
+        let mut vault_key: Option<i32> = None;
+        for _ in 0..ROOM_COUNT {
+            if let Some(room) = carve_room(&mut map_vec, &rooms, 27, &self.ghost_tiles, &mut rng) {
+                rooms.push(room);
             }
         }
+        if let Some(vault) = carve_room(&mut map_vec, &rooms, 28, &self.ghost_tiles, &mut rng) {
+            let key = id_manager.get_new_id();
+            self.vault_locks.insert(vault.door, key);
+            for _ in 0..VAULT_LOOT {
+                let loot_pos = (
+                    rng.gen_range(vault.x + 1..vault.x + vault.w - 1),
+                    rng.gen_range(vault.y + 1..vault.y + vault.h - 1),
+                );
+                self.pickups.push(item::Pickup::new(
+                    self.tile_center(loot_pos.0, loot_pos.1),
+                    weapon::generate_vault_loot(id_manager, self.difficulty, &mut rng),
+                ));
+            }
+            rooms.push(vault);
+            self.vault = Some(vault);
+            vault_key = Some(key);
+        }
 
-        let mut map_vec: Vec<Vec<i32>> = Vec::new();
-        for i in 0..MAP_HEIGHT {
-            map_vec.push(map[i].to_vec());
+        // Everything left has to be reachable from the spawn
+        let main_region =
+            mapgen::connect_regions(&mut map_vec, |x, y| rooms.iter().any(|r| r.contains(x, y)));
+        let open_ground: Vec<(usize, usize)> = main_region
+            .into_iter()
+            .filter(|&(x, y)| map_vec[y][x] == -1 && !rooms.iter().any(|r| r.contains(x, y)))
+            .collect();
+        if open_ground.len() < 3 {
+            return Err(ggez::GameError::CustomError(format!(
+                "Stage '{}' has no room for the spawn and the end",
+                self.difficulty
+            )));
         }
 
-        let mut rng = rand::thread_rng();
-        let mut start: (usize, usize);
-        let mut end: (usize, usize);
-        let mut start_end_found = false;
-        while !start_end_found {
-            start = (
-                rng.gen_range(1..MAP_WIDTH / 2),
-                rng.gen_range(1..MAP_HEIGHT / 2),
-            );
-            end = (
-                rng.gen_range(MAP_WIDTH / 2..MAP_WIDTH - 1),
-                rng.gen_range(MAP_HEIGHT / 2..MAP_HEIGHT - 1),
-            );
-            // let start_pos = (
-            //     start.0 as f32 * self.tile_size,
-            //     start.1 as f32 * self.tile_size,
-            // );
-            let start_pos = (start.0 as f32, start.1 as f32);
-            // let end_pos = (end.0 as f32 * self.tile_size, end.1 as f32 * self.tile_size);
-            let end_pos = (end.0 as f32, end.1 as f32);
-            match physics::PathFinding::astar(
-                Vec2::from(start_pos),
-                Vec2::from(end_pos),
-                (map_vec.clone(), self.ghost_tiles.clone(), self.tile_size),
-            ) {
-                physics::PathFindingResult::Ok(_) => {
-                    start_end_found = true;
-                    map_vec[start.1][start.0] = 20;
-                    map_vec[end.1][end.0] = 21;
-                    self.spawn = Vec2::from((start.0 as f32, start.1 as f32));
-                    self.end = Vec2::from((end.0 as f32, end.1 as f32));
+        // The end is as far as it gets from the spawn
+        let start = open_ground[rng.gen_range(0..open_ground.len())];
+        let distances = mapgen::distances_from(&map_vec, start);
+        let end = *open_ground
+            .iter()
+            .max_by_key(|&&(x, y)| distances[y][x].unwrap_or(0))
+            .unwrap();
+        map_vec[start.1][start.0] = 20;
+        map_vec[end.1][end.0] = 21;
+        self.spawn = Vec2::from((start.0 as f32, start.1 as f32));
+        self.end = Vec2::from((end.0 as f32, end.1 as f32));
+
+        if let Some(key) = vault_key {
+            let key_pos = loop {
+                let pos = open_ground[rng.gen_range(0..open_ground.len())];
+                if pos != start && pos != end {
+                    break pos;
                 }
-                physics::PathFindingResult::Fail => start_end_found = false,
             };
+            self.pickups.push(item::Pickup::new(
+                self.tile_center(key_pos.0, key_pos.1),
+                weapon::ObjectDrop::Key(key),
+            ));
         }
 
         self.map_file_content = map_vec;
-        self.total_rows = MAP_HEIGHT as f32;
-        self.total_cols = MAP_WIDTH as f32;
+        self.total_rows = config.height as f32;
+        self.total_cols = config.width as f32;
         self.diag_size =
             physics::get_diagonal_size(self.total_cols, self.total_rows, self.tile_size);
         self.map_title = self.difficulty.to_string();
-        self.image_hashmap = image_hashmap;
+        self.explored = vec![vec![false; config.width]; config.height];
+        self.torches = Vec::new();
+        self.bombs = Vec::new();
         self.crate_tilemap(id_manager);
 
         match start_time.elapsed() {
@@ -199,95 +315,430 @@ impl Map {
 
         for (y, row) in self.map_file_content.iter().enumerate() {
             for (x, material) in row.iter().enumerate() {
-                let tile = Tile::new(
-                    (x as f32 * self.tile_size) as f32,
-                    (y as f32 * self.tile_size) as f32,
-                    self.tile_size,
-                    *material,
-                    self.ghost_tiles.contains(&(*material as f32)),
-                    0.,
-                );
-
-                let (ok, new_bloc) = match material {
-                    -1 => (
-                        true,
-                        Some(bloc::Bloc::Air(bloc::Air::new(
-                            id_manager.get_new_id(),
-                            tile,
-                        ))),
-                    ),
-                    4 => (
-                        true,
-                        Some(bloc::Bloc::Wall(bloc::Wall::new(
-                            id_manager.get_new_id(),
-                            tile,
-                        ))),
-                    ),
-                    9 => (
-                        true,
-                        Some(bloc::Bloc::Water(bloc::Water::new(
-                            id_manager.get_new_id(),
-                            tile,
-                        ))),
-                    ),
-                    18 => (
-                        true,
-                        Some(bloc::Bloc::Lava(bloc::Lava::new(
-                            id_manager.get_new_id(),
-                            tile,
-                        ))),
-                    ),
-                    20 => (
-                        true,
-                        Some(bloc::Bloc::Spawn(bloc::Spawn::new(
-                            id_manager.get_new_id(),
-                            tile,
-                        ))),
-                    ),
-                    21 => (
-                        true,
-                        Some(bloc::Bloc::End(bloc::End::new(
-                            id_manager.get_new_id(),
-                            tile,
-                        ))),
-                    ),
-                    _ => (false, None),
-                };
-
-                if ok {
-                    bloclist.push(new_bloc.unwrap())
-                } else {
-                    println!(
+                match self.new_bloc(x, y, *material, id_manager) {
+                    Some(new_bloc) => bloclist.push(new_bloc),
+                    None => println!(
                         "[WARNING] Failed to create bloc(x: {}, y:{}, material: {})",
                         x, y, material
-                    )
+                    ),
                 }
             }
         }
         self.bloc_list = bloclist;
+        for y in 0..self.total_rows as usize {
+            for x in 0..self.total_cols as usize {
+                self.autotile(x, y);
+            }
+        }
+        self.reset_sections();
         // println!("Bloc list size: {}", self.bloc_list.len());
     }
-    pub fn bloc_effects<E: physics::EntityTrait>(&mut self, entity: &mut E) {
+    /// New empty sections for the current grid and textures, all of them to be built
+    fn reset_sections(&mut self) {
+        let section_rows = (self.total_rows as usize).div_ceil(SECTION_SIZE);
+        self.section_cols = (self.total_cols as usize).div_ceil(SECTION_SIZE);
+        self.sections = (0..section_rows * self.section_cols)
+            .map(|_| Section {
+                batch: None,
+                dirty: true,
+            })
+            .collect();
+    }
+    /// Picks the sprite of the tile from its neighbours
+    fn autotile(&mut self, x: usize, y: usize) {
+        let grid = &self.map_file_content;
+        let (variant, angle) = tileset::autotile(x as i32, y as i32, |x, y| {
+            match grid.get(y as usize).and_then(|row| row.get(x as usize)) {
+                Some(material) if x >= 0 && y >= 0 => *material,
+                // The border looks solid
+                _ => mapgen::WALL,
+            }
+        });
+        let tile = self.bloc_list[y * self.total_cols as usize + x].tile_mut();
+        tile.variant = variant;
+        tile.angle = angle;
+    }
+    /// The section holding this tile has to be built again
+    pub fn mark_dirty(&mut self, x: usize, y: usize) {
+        let index = (y / SECTION_SIZE) * self.section_cols + x / SECTION_SIZE;
+        if let Some(section) = self.sections.get_mut(index) {
+            section.dirty = true;
+        }
+    }
+    fn mark_all_dirty(&mut self) {
+        for section in self.sections.iter_mut() {
+            section.dirty = true;
+        }
+    }
+    fn new_bloc(
+        &self,
+        x: usize,
+        y: usize,
+        material: i32,
+        id_manager: &mut id::IdManager,
+    ) -> Option<bloc::Bloc> {
+        let tile = Tile::new(
+            x as f32 * self.tile_size,
+            y as f32 * self.tile_size,
+            self.tile_size,
+            material,
+            self.ghost_tiles.contains(&(material as f32)),
+            0.,
+        );
+
+        match material {
+            -1 => Some(bloc::Bloc::Air(bloc::Air::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            4 => Some(bloc::Bloc::Wall(bloc::Wall::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            9 => Some(bloc::Bloc::Water(bloc::Water::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            18 => Some(bloc::Bloc::Lava(bloc::Lava::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            20 => Some(bloc::Bloc::Spawn(bloc::Spawn::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            21 => Some(bloc::Bloc::End(bloc::End::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            22 => Some(bloc::Bloc::Spikes(bloc::Spikes::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            23 => Some(bloc::Bloc::CollapsingFloor(bloc::CollapsingFloor::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            24 => Some(bloc::Bloc::GasVent(bloc::GasVent::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            25 => Some(bloc::Bloc::PressurePlate(bloc::PressurePlate::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            HOLE => Some(bloc::Bloc::Hole(bloc::Hole::new(
+                id_manager.get_new_id(),
+                tile,
+            ))),
+            26 | 27 => Some(bloc::Bloc::Door(bloc::Door::new(
+                id_manager.get_new_id(),
+                tile,
+                material == 26,
+                None,
+            ))),
+            28 => Some(bloc::Bloc::Door(bloc::Door::new(
+                id_manager.get_new_id(),
+                tile,
+                false,
+                self.vault_locks.get(&(x, y)).copied(),
+            ))),
+            _ => None,
+        }
+    }
+    pub fn tile_center(&self, x: usize, y: usize) -> glam::Vec2 {
+        glam::Vec2::new(
+            x as f32 * self.tile_size + self.tile_size / 2.,
+            y as f32 * self.tile_size + self.tile_size / 2.,
+        )
+    }
+    /// Opens or closes the door, using one of the keys if it's locked
+    pub fn toggle_door(&mut self, x: usize, y: usize, keys: &[i32]) -> DoorResult {
+        let index = y * self.total_cols as usize + x;
+        let (result, material) = match &mut self.bloc_list[index] {
+            bloc::Bloc::Door(door) => match door.key {
+                Some(key) if keys.contains(&key) => {
+                    door.key = None;
+                    (DoorResult::Unlocked, door.set_open(true))
+                }
+                Some(_) => return DoorResult::Locked,
+                None => {
+                    if door.open {
+                        (DoorResult::Closed, door.set_open(false))
+                    } else {
+                        (DoorResult::Opened, door.set_open(true))
+                    }
+                }
+            },
+            _ => return DoorResult::NoDoor,
+        };
+        self.map_file_content[y][x] = material;
+        self.mark_dirty(x, y);
+        if let DoorResult::Unlocked = result {
+            self.vault_locks.remove(&(x, y));
+        }
+        result
+    }
+    /// Monsters can open doors, as long as they're not locked
+    pub fn open_door(&mut self, x: usize, y: usize) {
+        let index = y * self.total_cols as usize + x;
+        if let bloc::Bloc::Door(door) = &mut self.bloc_list[index] {
+            if !door.open && !door.is_locked() {
+                self.map_file_content[y][x] = door.set_open(true);
+                self.mark_dirty(x, y);
+            }
+        }
+    }
+    /// The door closest to the given position, within reach
+    pub fn door_near(&self, pos: glam::Vec2, reach: f32) -> Option<(usize, usize)> {
+        let mut closest: Option<((usize, usize), f32)> = None;
+        for (index, bloc) in self.bloc_list.iter().enumerate() {
+            if let bloc::Bloc::Door(door) = bloc {
+                let d = physics::RayCasting::get_distance(
+                    pos,
+                    glam::Vec2::from(door.tile.hitbox.center()),
+                );
+                let is_closer = match closest {
+                    Some((_, min_d)) => d < min_d,
+                    None => true,
+                };
+                if d <= reach && is_closer {
+                    let cols = self.total_cols as usize;
+                    closest = Some(((index % cols, index / cols), d));
+                }
+            }
+        }
+        closest.map(|(coords, _)| coords)
+    }
+    /// Removes and returns everything lying under the hitbox
+    pub fn collect_pickups(&mut self, hitbox: ggez::graphics::Rect) -> Vec<weapon::ObjectDrop> {
+        let mut collected: Vec<weapon::ObjectDrop> = Vec::new();
+        let mut index = 0;
+        while index < self.pickups.len() {
+            if physics::CheckCollision::point_in_rect(self.pickups[index].pos, hitbox) {
+                collected.push(self.pickups.swap_remove(index).drop);
+            } else {
+                index += 1;
+            }
+        }
+        collected
+    }
+    /// Changes a single tile, keeping the grid and the bloc list in sync
+    pub fn set_material(
+        &mut self,
+        x: usize,
+        y: usize,
+        material: i32,
+        id_manager: &mut id::IdManager,
+    ) {
+        let index = y * self.total_cols as usize + x;
+        if let Some(new_bloc) = self.new_bloc(x, y, material, id_manager) {
+            self.map_file_content[y][x] = material;
+            self.bloc_list[index] = new_bloc;
+            // The walls around may need another sprite now
+            for ny in y.saturating_sub(1)..(y + 2).min(self.total_rows as usize) {
+                for nx in x.saturating_sub(1)..(x + 2).min(self.total_cols as usize) {
+                    self.autotile(nx, ny);
+                    self.mark_dirty(nx, ny);
+                }
+            }
+        }
+    }
+    pub fn tile_coords(&self, pos: glam::Vec2) -> Option<(usize, usize)> {
+        if pos.x < 0. || pos.y < 0. {
+            return None;
+        }
+        let (x, y) = (
+            (pos.x / self.tile_size) as usize,
+            (pos.y / self.tile_size) as usize,
+        );
+        if x < self.total_cols as usize && y < self.total_rows as usize {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+    pub fn place_torch(&mut self, pos: glam::Vec2) -> bool {
+        match self.tile_coords(pos) {
+            Some(coords) if !self.torches.contains(&coords) => {
+                self.torches.push(coords);
+                // The light goes across sections
+                self.mark_all_dirty();
+                true
+            }
+            _ => false,
+        }
+    }
+    /// Ticks the fuses and returns the position of the bombs that just exploded
+    pub fn update_bombs(&mut self, dt: f32) -> Vec<glam::Vec2> {
+        let mut exploded: Vec<glam::Vec2> = Vec::new();
+        for bomb in self.bombs.iter_mut() {
+            bomb.fuse -= dt;
+            if bomb.fuse <= 0. {
+                exploded.push(bomb.pos);
+            }
+        }
+        self.bombs.retain(|b| b.fuse > 0.);
+        exploded
+    }
+    /// Turns every minable tile in the blast radius into ground, except the outer border and the
+    /// walls of the vault. Returns what the monsters can hear of it.
+    pub fn explode(&mut self, pos: glam::Vec2, id_manager: &mut id::IdManager) -> hearing::Noise {
+        let radius = item::BOMB_RADIUS * self.tile_size;
+        for y in 1..self.total_rows as usize - 1 {
+            for x in 1..self.total_cols as usize - 1 {
+                if !MINABLE.contains(&self.map_file_content[y][x]) {
+                    continue;
+                }
+                if self.vault.is_some_and(|vault| vault.contains(x, y)) {
+                    continue;
+                }
+                let tile_center = glam::Vec2::new(
+                    x as f32 * self.tile_size + self.tile_size / 2.,
+                    y as f32 * self.tile_size + self.tile_size / 2.,
+                );
+                if physics::RayCasting::get_distance(pos, tile_center) <= radius {
+                    self.set_material(x, y, -1, id_manager);
+                }
+            }
+        }
+        hearing::Noise::new(hearing::NoiseKind::Explosion, pos, 1.)
+    }
+    /// Footsteps in water are splashes
+    pub fn surface_noise(&self, noise: hearing::Noise) -> hearing::Noise {
+        let in_water = match self.tile_coords(noise.pos) {
+            Some((x, y)) => self.map_file_content[y][x] == 9,
+            None => false,
+        };
+        if noise.kind == hearing::NoiseKind::Footstep && in_water {
+            hearing::Noise::new(
+                hearing::NoiseKind::Splash,
+                noise.pos,
+                noise.radius / noise.kind.radius(),
+            )
+        } else {
+            noise
+        }
+    }
+    /// Out of the map counts as a wall
+    pub fn blocks_sound(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.total_cols as i32 || y >= self.total_rows as i32 {
+            return true;
+        }
+        !self
+            .ghost_tiles
+            .contains(&(self.map_file_content[y as usize][x as usize] as f32))
+    }
+    /// 0 is pitch black, 1 is fully lit
+    pub fn light_level(&self, pos: glam::Vec2, light_center: glam::Vec2) -> f32 {
+        let d = physics::RayCasting::get_distance(light_center, pos)
+            / (PLAYER_LIGHT_RADIUS * self.tile_size);
+        self.biome
+            .ambient_light()
+            .max(1. - d)
+            .max(self.torch_light(pos))
+    }
+    pub fn torch_light(&self, pos: glam::Vec2) -> f32 {
+        let mut light: f32 = 0.;
+        for (x, y) in self.torches.iter() {
+            let torch_center = glam::Vec2::new(
+                *x as f32 * self.tile_size + self.tile_size / 2.,
+                *y as f32 * self.tile_size + self.tile_size / 2.,
+            );
+            let d = physics::RayCasting::get_distance(torch_center, pos)
+                / (item::TORCH_LIGHT_RADIUS * self.tile_size);
+            light = light.max(1. - d);
+        }
+        light
+    }
+    /// Traps show up under a torch, when you're carefully looking where you step, or once
+    /// you stepped on them. The monsters don't give them away.
+    pub fn reveal_traps(&mut self, player_center: glam::Vec2, careful: bool) {
+        for bloc_index in 0..self.bloc_list.len() {
+            if !self.bloc_list[bloc_index].is_hidden() {
+                continue;
+            }
+            let hitbox = self.bloc_list[bloc_index].tile().hitbox;
+            let tile_center = glam::Vec2::from(hitbox.center());
+            let close = physics::RayCasting::get_distance(player_center, tile_center)
+                < TRAP_REVEAL_DISTANCE * self.tile_size;
+            let under = physics::CheckCollision::point_in_rect(player_center, hitbox);
+            if under || (careful && close) || self.torch_light(tile_center) > TRAP_REVEAL_LIGHT {
+                self.bloc_list[bloc_index].reveal();
+                let cols = self.total_cols as usize;
+                self.mark_dirty(bloc_index % cols, bloc_index / cols);
+            }
+        }
+    }
+    /// The collapsing floors that gave way turn into holes
+    pub fn update_traps(&mut self, id_manager: &mut id::IdManager) {
+        let cols = self.total_cols as usize;
+        let collapsed: Vec<usize> = self
+            .bloc_list
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| matches!(b, bloc::Bloc::CollapsingFloor(c) if c.collapsed()))
+            .map(|(index, _)| index)
+            .collect();
+        for index in collapsed {
+            self.set_material(index % cols, index / cols, HOLE, id_manager);
+        }
+    }
+    pub fn bloc_effects<E: physics::EntityTrait>(
+        &mut self,
+        entity: &mut E,
+    ) -> Vec<bloc::BlocEvent> {
+        let mut events: Vec<bloc::BlocEvent> = Vec::new();
         for bloc_index in 0..self.bloc_list.len() {
             let hitbox = physics::EntityTrait::get_hitbox(entity);
             if physics::CheckCollision::point_in_rect(
                 glam::Vec2::from(hitbox.center()),
                 physics::EntityTrait::get_hitbox(&self.bloc_list[bloc_index]),
             ) {
+                let was_hidden = self.bloc_list[bloc_index].is_hidden();
                 match &mut self.bloc_list[bloc_index] {
                     bloc::Bloc::Air(_a) => {}
                     bloc::Bloc::Water(_w) => {
-                        println!("No effect on water for now")
+                        entity.apply_status(status::StatusKind::Slow);
                     }
                     bloc::Bloc::Lava(l) => {
                         l.damage(entity);
                     }
+                    bloc::Bloc::Spikes(s) => {
+                        s.step(entity);
+                    }
+                    bloc::Bloc::CollapsingFloor(c) => {
+                        c.step();
+                    }
+                    bloc::Bloc::Hole(h) => {
+                        events.push(h.step(entity));
+                    }
+                    bloc::Bloc::GasVent(g) => {
+                        g.step(entity);
+                    }
+                    bloc::Bloc::PressurePlate(p) => {
+                        events.extend(p.step());
+                    }
                     _ => {}
                 }
+                // Stepping on a trap shows it
+                if was_hidden && !self.bloc_list[bloc_index].is_hidden() {
+                    let cols = self.total_cols as usize;
+                    self.mark_dirty(bloc_index % cols, bloc_index / cols);
+                }
             }
         }
+        events
     }
-    pub fn draw(&mut self, ctx: &mut ggez::Context, draw_offset: glam::Vec2) -> ggez::GameResult {
+    pub fn draw(
+        &mut self,
+        ctx: &mut ggez::Context,
+        tileset: &mut tileset::Tileset,
+        draw_offset: glam::Vec2,
+        light_center: glam::Vec2,
+        view: ggez::graphics::Rect,
+        sprites: &mut sprite::SpriteAtlas,
+    ) -> ggez::GameResult {
         let draw_hitboxes = false;
         let draw_images = true;
 
@@ -295,30 +746,146 @@ impl Map {
             self.draw_hitboxes(ctx, draw_offset)?;
         }
         if draw_images {
-            self.draw_images(ctx, draw_offset)?;
+            tileset.set_biome(self.biome);
+            self.draw_images(ctx, tileset, draw_offset, light_center, view)?;
         }
+        self.draw_items(ctx, draw_offset, sprites)?;
         Ok(())
     }
+    /// Marks everything the player light reaches as explored
+    pub fn explore(&mut self, center: glam::Vec2) {
+        let radius = PLAYER_LIGHT_RADIUS * self.tile_size;
+        let (cols, rows) = (self.total_cols as usize, self.total_rows as usize);
+        let (min, max) = (
+            center - glam::Vec2::splat(radius),
+            center + glam::Vec2::splat(radius),
+        );
+        let x_range = (min.x / self.tile_size).max(0.) as usize
+            ..((max.x / self.tile_size) as usize + 1).min(cols);
+        let y_range = (min.y / self.tile_size).max(0.) as usize
+            ..((max.y / self.tile_size) as usize + 1).min(rows);
+        for y in y_range {
+            for x in x_range.clone() {
+                if !self.explored[y][x]
+                    && physics::RayCasting::get_distance(center, self.tile_center(x, y)) <= radius
+                {
+                    self.explored[y][x] = true;
+                    self.mark_dirty(x, y);
+                }
+            }
+        }
+    }
     pub fn draw_images(
         &mut self,
         ctx: &mut ggez::Context,
+        tileset: &mut tileset::Tileset,
         draw_offset: glam::Vec2,
+        light_center: glam::Vec2,
+        view: ggez::graphics::Rect,
     ) -> ggez::GameResult {
-        let tile_size_mult = self.tile_size / 32.;
+        let (x_range, y_range) = self.tile_range(view, CULL_MARGIN * self.tile_size);
+        if x_range.is_empty() || y_range.is_empty() {
+            return Ok(());
+        }
+
+        // The sections in view, rebuilt if something changed since their last draw
+        for section_y in y_range.start / SECTION_SIZE..=(y_range.end - 1) / SECTION_SIZE {
+            for section_x in x_range.start / SECTION_SIZE..=(x_range.end - 1) / SECTION_SIZE {
+                let index = section_y * self.section_cols + section_x;
+                if self.sections[index].dirty {
+                    self.build_section(tileset, section_x, section_y);
+                }
+                if let Some(batch) = &self.sections[index].batch {
+                    ggez::graphics::draw(
+                        ctx,
+                        batch,
+                        (draw_offset, 0., ggez::graphics::Color::WHITE),
+                    )?;
+                }
+            }
+        }
+
+        // The player light changes every frame, so the lit tiles are drawn again over the sections,
+        // along with the animated ones the sections leave out
+        let time = ggez::timer::time_since_start(ctx).as_secs_f32();
+        let light_radius = PLAYER_LIGHT_RADIUS * self.tile_size;
+        let lit_area = ggez::graphics::Rect::new(
+            light_center.x - light_radius,
+            light_center.y - light_radius,
+            light_radius * 2.,
+            light_radius * 2.,
+        );
+        let (lit_x, lit_y) = self.tile_range(lit_area, 0.);
+        for y in y_range {
+            for x in x_range.clone() {
+                if !self.explored[y][x] {
+                    continue;
+                }
+                let animated = tileset.is_animated(self.map_file_content[y][x]);
+                let lit = lit_x.contains(&x) && lit_y.contains(&y);
+                if !animated && !lit {
+                    continue;
+                }
+                let light = self.light_level(self.tile_center(x, y), light_center);
+                if !animated && light <= self.static_light(self.tile_center(x, y)) {
+                    continue;
+                }
+                if let Some(drawparams) = self.tile_drawparams(tileset, x, y, light, time) {
+                    tileset.batch.add(drawparams);
+                }
+            }
+        }
+        ggez::graphics::draw(
+            ctx,
+            &tileset.batch,
+            (draw_offset, 0., ggez::graphics::Color::WHITE),
+        )?;
+        tileset.batch.clear();
+        Ok(())
+    }
+    /// Tiles touching the area grown by the margin, clamped to the map
+    fn tile_range(
+        &self,
+        area: ggez::graphics::Rect,
+        margin: f32,
+    ) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+        let (cols, rows) = (self.total_cols as usize, self.total_rows as usize);
+        let first = |start: f32| ((start - margin) / self.tile_size).max(0.) as usize;
+        let last = |end: f32, count: usize| {
+            (((end + margin) / self.tile_size).max(0.) as usize + 1).min(count)
+        };
+        (
+            first(area.x).min(cols)..last(area.right(), cols),
+            first(area.y).min(rows)..last(area.bottom(), rows),
+        )
+    }
+    /// Light that doesn't depend on the player, baked in the sections
+    fn static_light(&self, pos: glam::Vec2) -> f32 {
+        self.biome.ambient_light().max(self.torch_light(pos))
+    }
+    fn tile_drawparams(
+        &self,
+        tileset: &tileset::Tileset,
+        x: usize,
+        y: usize,
+        light: f32,
+        time: f32,
+    ) -> Option<ggez::graphics::DrawParam> {
+        let tile_size_mult = self.tile_size / tileset::CELL_SIZE;
         let rotation_offset = 0.;
+        let tint = self.biome.tint();
+        let bloc = &self.bloc_list[y * self.total_cols as usize + x];
+        let tile = bloc.tile();
 
-        for bloc in self.bloc_list.iter() {
-            let tile = match bloc {
-                bloc::Bloc::Air(a) => &a.tile,
-                bloc::Bloc::Wall(w) => &w.tile,
-                bloc::Bloc::Water(w) => &w.tile,
-                bloc::Bloc::Lava(l) => &l.tile,
-                bloc::Bloc::Spawn(s) => &s.tile,
-                bloc::Bloc::End(e) => &e.tile,
-            };
+        // A hidden trap looks just like the ground around it
+        let material = if bloc.is_hidden() { -1 } else { tile.material };
+        // A hole has no cell in the tileset, the background shows through
+        let src = tileset.src_at(material, tile.variant, time)?;
 
-            let point = glam::Vec2::new(0.5, 0.5);
-            let tile_drawparams = ggez::graphics::DrawParam::new()
+        let point = glam::Vec2::new(0.5, 0.5);
+        Some(
+            ggez::graphics::DrawParam::new()
+                .src(src)
                 .dest(ggez::mint::Point2::from_slice(&[
                     tile.hitbox.center().x,
                     tile.hitbox.center().y,
@@ -328,17 +895,105 @@ impl Map {
                     tile_size_mult,
                 ]))
                 .offset(point)
-                .rotation(tile.angle + rotation_offset);
-            let h = self.image_hashmap.get_mut(&(tile.material as i32)).unwrap();
-            h.add(tile_drawparams);
+                .rotation(tile.angle + rotation_offset)
+                .color(ggez::graphics::Color::new(
+                    light * tint.0,
+                    light * tint.1,
+                    light * tint.2,
+                    1.,
+                )),
+        )
+    }
+    fn build_section(&mut self, tileset: &tileset::Tileset, section_x: usize, section_y: usize) {
+        let x_range = section_x * SECTION_SIZE
+            ..((section_x + 1) * SECTION_SIZE).min(self.total_cols as usize);
+        let y_range = section_y * SECTION_SIZE
+            ..((section_y + 1) * SECTION_SIZE).min(self.total_rows as usize);
+        let mut drawparams = Vec::new();
+        for y in y_range {
+            for x in x_range.clone() {
+                // Never seen, so still black, and the animated tiles are drawn every frame
+                if !self.explored[y][x] || tileset.is_animated(self.map_file_content[y][x]) {
+                    continue;
+                }
+                let light = self.static_light(self.tile_center(x, y));
+                drawparams.extend(self.tile_drawparams(tileset, x, y, light, 0.));
+            }
         }
-
-        for image in self.image_hashmap.clone().keys() {
-            let h = self.image_hashmap.get_mut(image).unwrap();
-            ggez::graphics::draw(ctx, h, (draw_offset, 0., ggez::graphics::Color::WHITE))?;
-            h.clear();
+        let section = &mut self.sections[section_y * self.section_cols + section_x];
+        // The shared batch is empty between two draws, a copy has the atlas and nothing else
+        let batch = section.batch.get_or_insert_with(|| tileset.batch.clone());
+        batch.clear();
+        for drawparam in drawparams {
+            batch.add(drawparam);
         }
-        Ok(())
+        section.dirty = false;
+    }
+    pub fn draw_items(
+        &self,
+        ctx: &mut ggez::Context,
+        draw_offset: glam::Vec2,
+        sprites: &mut sprite::SpriteAtlas,
+    ) -> ggez::GameResult {
+        if self.torches.is_empty() && self.bombs.is_empty() && self.pickups.is_empty() {
+            return Ok(());
+        }
+        let time = ggez::timer::time_since_start(ctx).as_secs_f32();
+        let item_size = self.tile_size * 0.6;
+        for (x, y) in self.torches.iter() {
+            sprites.add(
+                "item.torch",
+                time,
+                self.tile_center(*x, *y),
+                item_size,
+                0.,
+                ggez::graphics::Color::WHITE,
+            );
+        }
+        for pickup in self.pickups.iter() {
+            let name = match pickup.drop {
+                weapon::ObjectDrop::Ok(_) => "item.weapon",
+                weapon::ObjectDrop::Consumable(consumable, _) => consumable.sprite(),
+                weapon::ObjectDrop::Key(_) => "item.key",
+                weapon::ObjectDrop::None => continue,
+            };
+            sprites.add(
+                name,
+                time,
+                pickup.pos,
+                item_size,
+                0.,
+                ggez::graphics::Color::WHITE,
+            );
+        }
+        if !self.bombs.is_empty() {
+            let mut fuse_mesh = ggez::graphics::MeshBuilder::new();
+            for bomb in self.bombs.iter() {
+                sprites.add(
+                    "item.bomb",
+                    time,
+                    bomb.pos,
+                    item_size,
+                    0.,
+                    ggez::graphics::Color::WHITE,
+                );
+                // The fuse gets shorter as the bomb is about to blow
+                fuse_mesh.circle(
+                    ggez::graphics::DrawMode::stroke(2.),
+                    bomb.pos,
+                    self.tile_size / 5. + bomb.fuse * 10.,
+                    0.1,
+                    ggez::graphics::Color::RED,
+                )?;
+            }
+            let builded = fuse_mesh.build(ctx)?;
+            ggez::graphics::draw(
+                ctx,
+                &builded,
+                (draw_offset, 0., ggez::graphics::Color::WHITE),
+            )?;
+        }
+        sprites.draw(ctx, draw_offset)
     }
     pub fn draw_hitboxes(
         &self,
@@ -348,14 +1003,7 @@ impl Map {
         let mut hitbox_mesh = ggez::graphics::MeshBuilder::new();
 
         for bloc in &self.bloc_list {
-            let tile = match bloc {
-                bloc::Bloc::Air(a) => &a.tile,
-                bloc::Bloc::Wall(w) => &w.tile,
-                bloc::Bloc::Water(w) => &w.tile,
-                bloc::Bloc::Lava(l) => &l.tile,
-                bloc::Bloc::Spawn(s) => &s.tile,
-                bloc::Bloc::End(e) => &e.tile,
-            };
+            let tile = bloc.tile();
 
             //  THIS IS TEMPORARY
 
@@ -392,9 +1040,111 @@ impl Tile {
             material: material,
             transparent: transparent,
             angle: a,
+            variant: 0,
         }
     }
     pub fn get_rotated_hitbox(&self) -> Vec<glam::Vec2> {
         physics::rotate_square(self.hitbox, self.angle)
     }
 }
+
+/// Materials entities can walk through
+pub fn ghost_tiles() -> Vec<f32> {
+    vec![
+        -1., 9., 10., 18., 19., 20., 21., 22., 23., 24., 25., 26., 29.,
+    ]
+}
+
+impl Room {
+    fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+    fn overlaps(&self, other: &Room) -> bool {
+        // Keep at least one tile between two rooms
+        self.x <= other.x + other.w
+            && other.x <= self.x + self.w
+            && self.y <= other.y + other.h
+            && other.y <= self.y + self.h
+    }
+}
+
+/// Floor reached by taking the stairs, down from the end and up from the spawn.
+/// None when not on any stairs, or on the way up from the first floor.
+pub fn stairs_destination(depth: u32, on_end: bool, on_spawn: bool) -> Option<u32> {
+    if on_end {
+        Some(depth + 1)
+    } else if on_spawn {
+        depth.checked_sub(1)
+    } else {
+        None
+    }
+}
+
+/// Walls a rectangle off, puts a door in the middle of one side and digs from the door until
+/// it reaches the cave
+fn carve_room<R: Rng>(
+    map_vec: &mut [Vec<i32>],
+    rooms: &[Room],
+    door_material: i32,
+    ghost_tiles: &[f32],
+    rng: &mut R,
+) -> Option<Room> {
+    let (height, width) = (map_vec.len(), map_vec[0].len());
+    for _ in 0..50 {
+        let (w, h) = (rng.gen_range(5..=9), rng.gen_range(5..=8));
+        let (x, y) = (
+            rng.gen_range(2..width - w - 2),
+            rng.gen_range(2..height - h - 2),
+        );
+        let (door, dir): ((usize, usize), (i32, i32)) = match rng.gen_range(0..4) {
+            0 => ((x + w / 2, y), (0, -1)),
+            1 => ((x + w / 2, y + h - 1), (0, 1)),
+            2 => ((x, y + h / 2), (-1, 0)),
+            _ => ((x + w - 1, y + h / 2), (1, 0)),
+        };
+        let room = Room { x, y, w, h, door };
+        if rooms.iter().any(|r| r.overlaps(&room)) {
+            continue;
+        }
+
+        for (yy, row) in map_vec.iter_mut().enumerate().skip(y).take(h) {
+            for (xx, material) in row.iter_mut().enumerate().skip(x).take(w) {
+                let border = yy == y || yy == y + h - 1 || xx == x || xx == x + w - 1;
+                *material = if border { 4 } else { -1 };
+            }
+        }
+        map_vec[door.1][door.0] = door_material;
+
+        let (mut cx, mut cy) = (door.0 as i32 + dir.0, door.1 as i32 + dir.1);
+        while cx > 0
+            && cy > 0
+            && cx < width as i32 - 1
+            && cy < height as i32 - 1
+            && !ghost_tiles.contains(&(map_vec[cy as usize][cx as usize] as f32))
+        {
+            map_vec[cy as usize][cx as usize] = -1;
+            cx += dir.0;
+            cy += dir.1;
+        }
+        return Some(room);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_end_leads_down_and_the_spawn_back_up() {
+        assert_eq!(stairs_destination(3, true, false), Some(4));
+        assert_eq!(stairs_destination(3, false, true), Some(2));
+        assert_eq!(stairs_destination(3, false, false), None);
+    }
+
+    #[test]
+    fn no_way_up_from_the_first_floor() {
+        assert_eq!(stairs_destination(0, false, true), None);
+        assert_eq!(stairs_destination(0, true, false), Some(1));
+    }
+}
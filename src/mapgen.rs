@@ -0,0 +1,833 @@
+use noise::{NoiseFn, Seedable, SuperSimplex};
+use rand::{Rng, RngCore};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::io::Read;
+
+pub const GROUND: i32 = -1;
+pub const WALL: i32 = 4;
+const MIN_REGION_SIZE: usize = 20; // smaller pockets are filled instead of connected
+const MIN_MAP_SIZE: usize = 30; // rooms and BSP leaves need at least that much
+const CONFIG_PATH: &str = "/generation.toml";
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Liquid {
+    None,
+    Water,
+    Lava,
+}
+
+/// Tunables of the generation, read from the assets so floors can be tweaked without
+/// recompiling. Any missing key keeps its default value.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct GenerationConfig {
+    pub width: usize,
+    pub height: usize,
+    pub noise_frequency: f64, // the noise is sampled from -frequency to frequency
+    pub noise_octaves: usize,
+    pub liquid_threshold: f64,  // noise below this is liquid
+    pub wall_threshold: f64,    // noise above this is wall
+    pub liquid: Option<Liquid>, // None lets the biome decide
+    pub depths: Vec<DepthOverride>,
+}
+
+/// Replaces some of the values of the config between two depths (both included)
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DepthOverride {
+    pub min: u32,
+    pub max: Option<u32>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub noise_frequency: Option<f64>,
+    pub noise_octaves: Option<usize>,
+    pub liquid_threshold: Option<f64>,
+    pub wall_threshold: Option<f64>,
+    pub liquid: Option<Liquid>,
+}
+
+pub trait MapGenerator {
+    fn name(&self) -> &'static str;
+    /// Returns a height x width grid of materials, the border doesn't need to be closed
+    fn generate(&self, config: &GenerationConfig, rng: &mut dyn RngCore) -> Vec<Vec<i32>>;
+}
+
+/// The original generator, a threshold over simplex noise
+pub struct SimplexCaves;
+
+/// Noise made of several octaves, each with twice the frequency and half the weight
+pub struct LayeredNoise {
+    octaves: Vec<SuperSimplex>,
+    total_weight: f64,
+}
+
+/// Random walls smoothed until they look like natural caves
+pub struct CellularCaves {
+    pub wall_chance: f64,
+    pub iterations: usize,
+}
+
+/// Binary space partitioning, one room per leaf and corridors between siblings
+pub struct BspRooms {
+    pub min_leaf_size: usize,
+}
+
+/// A few walkers digging random tunnels until enough ground is carved
+pub struct DrunkardWalk {
+    pub walkers: usize,
+    pub ground_ratio: f32,
+}
+
+/// Ground from either generator, liquids from the first one
+pub struct Blend {
+    pub first: Box<dyn MapGenerator>,
+    pub second: Box<dyn MapGenerator>,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        GenerationConfig {
+            width: 100,
+            height: 100,
+            noise_frequency: 5.,
+            noise_octaves: 1,
+            liquid_threshold: -0.6,
+            wall_threshold: 0.5,
+            liquid: None,
+            depths: Vec::new(),
+        }
+    }
+}
+
+impl GenerationConfig {
+    /// What a noise level turns into
+    pub fn material_for(&self, level: f64) -> i32 {
+        if level <= self.liquid_threshold {
+            self.liquid.unwrap_or(Liquid::None).material()
+        } else if level <= self.wall_threshold {
+            GROUND
+        } else {
+            WALL
+        }
+    }
+    /// Falls back to the default config if the file is missing or broken
+    pub fn load(ctx: &mut ggez::Context) -> Self {
+        let mut content = String::new();
+        let read = ggez::filesystem::open(ctx, CONFIG_PATH).and_then(|mut file| {
+            file.read_to_string(&mut content)
+                .map_err(ggez::GameError::from)
+        });
+        if let Err(e) = read {
+            eprintln!(
+                "Could not read '{}', using the default generation: {}",
+                CONFIG_PATH, e
+            );
+            return GenerationConfig::default();
+        }
+        match toml::from_str(&content) {
+            Ok(config) => {
+                println!("Loaded: '{}'", CONFIG_PATH);
+                config
+            }
+            Err(e) => {
+                eprintln!(
+                    "Could not parse '{}', using the default generation: {}",
+                    CONFIG_PATH, e
+                );
+                GenerationConfig::default()
+            }
+        }
+    }
+    /// The config with every override matching the depth applied, in file order
+    pub fn for_depth(&self, depth: u32) -> GenerationConfig {
+        let mut config = self.clone();
+        config.depths = Vec::new();
+        for o in self.depths.iter() {
+            if depth < o.min || o.max.is_some_and(|max| depth > max) {
+                continue;
+            }
+            config.width = o.width.unwrap_or(config.width);
+            config.height = o.height.unwrap_or(config.height);
+            config.noise_frequency = o.noise_frequency.unwrap_or(config.noise_frequency);
+            config.noise_octaves = o.noise_octaves.unwrap_or(config.noise_octaves);
+            config.liquid_threshold = o.liquid_threshold.unwrap_or(config.liquid_threshold);
+            config.wall_threshold = o.wall_threshold.unwrap_or(config.wall_threshold);
+            config.liquid = o.liquid.or(config.liquid);
+        }
+        config.width = config.width.max(MIN_MAP_SIZE);
+        config.height = config.height.max(MIN_MAP_SIZE);
+        config.noise_octaves = config.noise_octaves.max(1);
+        config
+    }
+}
+
+impl Liquid {
+    /// Material of the pools, plain ground when there is no liquid
+    pub fn material(&self) -> i32 {
+        match self {
+            Liquid::None => GROUND,
+            Liquid::Water => 9,
+            Liquid::Lava => 18,
+        }
+    }
+}
+
+/// Which generator builds the floor at this depth
+pub fn generator_for_depth(depth: u32, rng: &mut dyn RngCore) -> Box<dyn MapGenerator> {
+    let cellular = || -> Box<dyn MapGenerator> {
+        Box::new(CellularCaves {
+            wall_chance: 0.45,
+            iterations: 5,
+        })
+    };
+    let bsp = || -> Box<dyn MapGenerator> { Box::new(BspRooms { min_leaf_size: 14 }) };
+    let drunkard = || -> Box<dyn MapGenerator> {
+        Box::new(DrunkardWalk {
+            walkers: 4,
+            ground_ratio: 0.4,
+        })
+    };
+
+    if depth < 3 {
+        Box::new(SimplexCaves)
+    } else if depth < 6 {
+        cellular()
+    } else if depth < 10 {
+        Box::new(Blend {
+            first: Box::new(SimplexCaves),
+            second: bsp(),
+        })
+    } else {
+        match rng.gen_range(0..4) {
+            0 => cellular(),
+            1 => bsp(),
+            2 => Box::new(Blend {
+                first: cellular(),
+                second: drunkard(),
+            }),
+            _ => Box::new(Blend {
+                first: bsp(),
+                second: drunkard(),
+            }),
+        }
+    }
+}
+
+/// Runs the generator and makes sure nothing can walk out of the map
+pub fn generate(
+    generator: &dyn MapGenerator,
+    config: &GenerationConfig,
+    rng: &mut dyn RngCore,
+) -> Vec<Vec<i32>> {
+    let (width, height) = (config.width, config.height);
+    let mut grid = generator.generate(config, rng);
+    for (y, row) in grid.iter_mut().enumerate() {
+        for (x, material) in row.iter_mut().enumerate() {
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                *material = WALL;
+            }
+        }
+    }
+    grid
+}
+
+fn wall_neighbours(grid: &[Vec<i32>], x: usize, y: usize) -> usize {
+    let mut count = 0;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+            // Outside of the map counts as wall, so the caves don't touch the border
+            if nx < 0
+                || ny < 0
+                || ny as usize >= grid.len()
+                || nx as usize >= grid[0].len()
+                || grid[ny as usize][nx as usize] == WALL
+            {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+fn carve_rect(grid: &mut [Vec<i32>], x: usize, y: usize, w: usize, h: usize) {
+    for row in grid.iter_mut().skip(y).take(h) {
+        for material in row.iter_mut().skip(x).take(w) {
+            *material = GROUND;
+        }
+    }
+}
+
+/// L shaped corridor, horizontal part first
+fn carve_corridor(grid: &mut [Vec<i32>], from: (usize, usize), to: (usize, usize)) {
+    let (min_x, max_x) = (from.0.min(to.0), from.0.max(to.0));
+    let (min_y, max_y) = (from.1.min(to.1), from.1.max(to.1));
+    for material in grid[from.1][min_x..=max_x].iter_mut() {
+        *material = GROUND;
+    }
+    for row in grid[min_y..=max_y].iter_mut() {
+        row[to.0] = GROUND;
+    }
+}
+
+impl LayeredNoise {
+    pub fn new(octave_count: usize, rng: &mut dyn RngCore) -> Self {
+        let octaves: Vec<SuperSimplex> = (0..octave_count.max(1))
+            .map(|_| SuperSimplex::default().set_seed(rng.next_u32()))
+            .collect();
+        let total_weight = (0..octaves.len()).map(|i| 0.5f64.powi(i as i32)).sum();
+        LayeredNoise {
+            octaves,
+            total_weight,
+        }
+    }
+    pub fn get(&self, x: f64, y: f64) -> f64 {
+        let mut level = 0.;
+        for (i, octave) in self.octaves.iter().enumerate() {
+            let scale = 2f64.powi(i as i32);
+            level += octave.get([x * scale, y * scale]) / scale;
+        }
+        level / self.total_weight
+    }
+}
+
+impl MapGenerator for SimplexCaves {
+    fn name(&self) -> &'static str {
+        "simplex caves"
+    }
+    fn generate(&self, config: &GenerationConfig, rng: &mut dyn RngCore) -> Vec<Vec<i32>> {
+        let (width, height) = (config.width, config.height);
+        let noise = LayeredNoise::new(config.noise_octaves, rng);
+        let step_x = config.noise_frequency * 2. / width as f64;
+        let step_y = config.noise_frequency * 2. / height as f64;
+
+        let mut grid = vec![vec![WALL; width]; height];
+        for (y, row) in grid.iter_mut().enumerate() {
+            for (x, material) in row.iter_mut().enumerate() {
+                *material = config.material_for(noise.get(
+                    -config.noise_frequency + step_x * x as f64,
+                    -config.noise_frequency + step_y * y as f64,
+                ));
+            }
+        }
+        grid
+    }
+}
+
+impl MapGenerator for CellularCaves {
+    fn name(&self) -> &'static str {
+        "cellular caves"
+    }
+    fn generate(&self, config: &GenerationConfig, rng: &mut dyn RngCore) -> Vec<Vec<i32>> {
+        let (width, height) = (config.width, config.height);
+        let mut grid = vec![vec![WALL; width]; height];
+        for row in grid.iter_mut() {
+            for material in row.iter_mut() {
+                if !rng.gen_bool(self.wall_chance) {
+                    *material = GROUND;
+                }
+            }
+        }
+        for _ in 0..self.iterations {
+            let mut next = grid.clone();
+            for (y, row) in next.iter_mut().enumerate() {
+                for (x, material) in row.iter_mut().enumerate() {
+                    let walls = wall_neighbours(&grid, x, y);
+                    if walls >= 5 {
+                        *material = WALL;
+                    } else if walls <= 3 {
+                        *material = GROUND;
+                    }
+                }
+            }
+            grid = next;
+        }
+        grid
+    }
+}
+
+impl BspRooms {
+    /// Splits the area until it's too small, returns the center of the room it dug
+    fn split(
+        &self,
+        grid: &mut [Vec<i32>],
+        area: (usize, usize, usize, usize),
+        rng: &mut dyn RngCore,
+    ) -> (usize, usize) {
+        let (x, y, w, h) = area;
+        let can_split_w = w >= self.min_leaf_size * 2;
+        let can_split_h = h >= self.min_leaf_size * 2;
+
+        if !can_split_w && !can_split_h {
+            let room_w = rng.gen_range(self.min_leaf_size / 2..=w - 2);
+            let room_h = rng.gen_range(self.min_leaf_size / 2..=h - 2);
+            let room_x = x + rng.gen_range(1..=w - room_w - 1);
+            let room_y = y + rng.gen_range(1..=h - room_h - 1);
+            carve_rect(grid, room_x, room_y, room_w, room_h);
+            return (room_x + room_w / 2, room_y + room_h / 2);
+        }
+
+        let vertical = if can_split_w && can_split_h {
+            rng.gen_bool(0.5)
+        } else {
+            can_split_w
+        };
+        let (first, second) = if vertical {
+            let cut = rng.gen_range(self.min_leaf_size..=w - self.min_leaf_size);
+            ((x, y, cut, h), (x + cut, y, w - cut, h))
+        } else {
+            let cut = rng.gen_range(self.min_leaf_size..=h - self.min_leaf_size);
+            ((x, y, w, cut), (x, y + cut, w, h - cut))
+        };
+        let first_center = self.split(grid, first, rng);
+        let second_center = self.split(grid, second, rng);
+        carve_corridor(grid, first_center, second_center);
+        first_center
+    }
+}
+
+impl MapGenerator for BspRooms {
+    fn name(&self) -> &'static str {
+        "rooms and corridors"
+    }
+    fn generate(&self, config: &GenerationConfig, rng: &mut dyn RngCore) -> Vec<Vec<i32>> {
+        let (width, height) = (config.width, config.height);
+        let mut grid = vec![vec![WALL; width]; height];
+        self.split(&mut grid, (0, 0, width, height), rng);
+        grid
+    }
+}
+
+impl MapGenerator for DrunkardWalk {
+    fn name(&self) -> &'static str {
+        "drunkard walk"
+    }
+    fn generate(&self, config: &GenerationConfig, rng: &mut dyn RngCore) -> Vec<Vec<i32>> {
+        let (width, height) = (config.width, config.height);
+        let mut grid = vec![vec![WALL; width]; height];
+        let wanted = ((width * height) as f32 * self.ground_ratio) as usize;
+        let mut carved = 0;
+        // Every walker starts where the previous one went, so the tunnels stay connected
+        let (mut x, mut y) = (width / 2, height / 2);
+        for walker in 0..self.walkers {
+            let walker_goal = wanted * (walker + 1) / self.walkers;
+            while carved < walker_goal {
+                if grid[y][x] == WALL {
+                    grid[y][x] = GROUND;
+                    carved += 1;
+                }
+                match rng.gen_range(0..4) {
+                    0 if x > 1 => x -= 1,
+                    1 if x < width - 2 => x += 1,
+                    2 if y > 1 => y -= 1,
+                    3 if y < height - 2 => y += 1,
+                    _ => {}
+                }
+            }
+        }
+        grid
+    }
+}
+
+impl MapGenerator for Blend {
+    fn name(&self) -> &'static str {
+        "blend"
+    }
+    fn generate(&self, config: &GenerationConfig, rng: &mut dyn RngCore) -> Vec<Vec<i32>> {
+        let mut grid = self.first.generate(config, rng);
+        let second = self.second.generate(config, rng);
+        for (row, second_row) in grid.iter_mut().zip(second.iter()) {
+            for (material, &other) in row.iter_mut().zip(second_row.iter()) {
+                if *material == WALL && other != WALL {
+                    *material = other;
+                }
+            }
+        }
+        grid
+    }
+}
+
+/// Passable tiles, liquids and traps included
+fn is_open(material: i32) -> bool {
+    material != WALL
+}
+
+/// 4-neighbourhood, the border of the map is left out since it always stays a wall
+fn inner_neighbours(x: usize, y: usize, width: usize, height: usize) -> Vec<(usize, usize)> {
+    let mut neighbours: Vec<(usize, usize)> = Vec::new();
+    if x > 1 {
+        neighbours.push((x - 1, y));
+    }
+    if x < width - 2 {
+        neighbours.push((x + 1, y));
+    }
+    if y > 1 {
+        neighbours.push((x, y - 1));
+    }
+    if y < height - 2 {
+        neighbours.push((x, y + 1));
+    }
+    neighbours
+}
+
+/// Groups the open tiles that can reach each other, biggest region first
+fn find_regions(grid: &[Vec<i32>]) -> Vec<Vec<(usize, usize)>> {
+    let (height, width) = (grid.len(), grid[0].len());
+    let mut seen = vec![vec![false; width]; height];
+    let mut regions: Vec<Vec<(usize, usize)>> = Vec::new();
+
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            if seen[y][x] || !is_open(grid[y][x]) {
+                continue;
+            }
+            let mut region: Vec<(usize, usize)> = Vec::new();
+            let mut queue = VecDeque::new();
+            seen[y][x] = true;
+            queue.push_back((x, y));
+            while let Some((cx, cy)) = queue.pop_front() {
+                region.push((cx, cy));
+                for (nx, ny) in inner_neighbours(cx, cy, width, height) {
+                    if !seen[ny][nx] && is_open(grid[ny][nx]) {
+                        seen[ny][nx] = true;
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+            regions.push(region);
+        }
+    }
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    regions
+}
+
+/// Fills the small pockets and digs a tunnel from every other region to the biggest one.
+/// Protected tiles (rooms) are never filled nor dug through.
+/// Returns the tiles of the main region once everything is connected.
+pub fn connect_regions<F: Fn(usize, usize) -> bool>(
+    grid: &mut [Vec<i32>],
+    is_protected: F,
+) -> Vec<(usize, usize)> {
+    let (height, width) = (grid.len(), grid[0].len());
+    let mut regions = find_regions(grid);
+    if regions.is_empty() {
+        return Vec::new();
+    }
+
+    let mut in_main = vec![vec![false; width]; height];
+    for &(x, y) in regions.remove(0).iter() {
+        in_main[y][x] = true;
+    }
+
+    // Pockets first, so a tunnel never goes through one that gets filled afterwards
+    regions.retain(|region| {
+        if region.len() >= MIN_REGION_SIZE || region.iter().any(|&(x, y)| is_protected(x, y)) {
+            return true;
+        }
+        for &(x, y) in region.iter() {
+            grid[y][x] = WALL;
+        }
+        false
+    });
+
+    for region in regions.iter() {
+        // Breadth first from the whole region, the first main tile found is the closest one
+        let mut parent: Vec<Vec<Option<(usize, usize)>>> = vec![vec![None; width]; height];
+        let mut seen = vec![vec![false; width]; height];
+        let mut queue = VecDeque::new();
+        for &(x, y) in region.iter() {
+            seen[y][x] = true;
+            queue.push_back((x, y));
+        }
+        let mut reached: Option<(usize, usize)> = None;
+        while let Some((x, y)) = queue.pop_front() {
+            if in_main[y][x] {
+                reached = Some((x, y));
+                break;
+            }
+            for (nx, ny) in inner_neighbours(x, y, width, height) {
+                if !seen[ny][nx] && !is_protected(nx, ny) {
+                    seen[ny][nx] = true;
+                    parent[ny][nx] = Some((x, y));
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+
+        match reached {
+            Some(mut current) => {
+                while let Some(previous) = parent[current.1][current.0] {
+                    if grid[previous.1][previous.0] == WALL {
+                        grid[previous.1][previous.0] = GROUND;
+                    }
+                    in_main[previous.1][previous.0] = true;
+                    current = previous;
+                }
+                for &(x, y) in region.iter() {
+                    in_main[y][x] = true;
+                }
+            }
+            None => println!("A region of {} tiles couldn't be connected", region.len()),
+        }
+    }
+
+    let mut main_region: Vec<(usize, usize)> = Vec::new();
+    for (y, row) in in_main.iter().enumerate() {
+        for (x, &main) in row.iter().enumerate() {
+            if main {
+                main_region.push((x, y));
+            }
+        }
+    }
+    main_region
+}
+
+/// Walking distance from the start to every open tile, None where it can't be reached
+pub fn distances_from(grid: &[Vec<i32>], start: (usize, usize)) -> Vec<Vec<Option<usize>>> {
+    let (height, width) = (grid.len(), grid[0].len());
+    let mut distances: Vec<Vec<Option<usize>>> = vec![vec![None; width]; height];
+    let mut queue = VecDeque::new();
+    distances[start.1][start.0] = Some(0);
+    queue.push_back(start);
+    while let Some((x, y)) = queue.pop_front() {
+        let distance = distances[y][x].unwrap_or(0);
+        for (nx, ny) in inner_neighbours(x, y, width, height) {
+            if distances[ny][nx].is_none() && is_open(grid[ny][nx]) {
+                distances[ny][nx] = Some(distance + 1);
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const SEEDS: [u64; 4] = [0, 1, 42, 0xDEAD_BEEF];
+    const SIZES: [(usize, usize); 3] = [(30, 30), (80, 45), (60, 100)]; // width, height
+
+    fn generators() -> Vec<Box<dyn MapGenerator>> {
+        vec![
+            Box::new(SimplexCaves),
+            Box::new(CellularCaves {
+                wall_chance: 0.45,
+                iterations: 5,
+            }),
+            Box::new(BspRooms { min_leaf_size: 14 }),
+            Box::new(DrunkardWalk {
+                walkers: 4,
+                ground_ratio: 0.4,
+            }),
+            Box::new(Blend {
+                first: Box::new(SimplexCaves),
+                second: Box::new(BspRooms { min_leaf_size: 14 }),
+            }),
+            Box::new(Blend {
+                first: Box::new(CellularCaves {
+                    wall_chance: 0.45,
+                    iterations: 5,
+                }),
+                second: Box::new(DrunkardWalk {
+                    walkers: 4,
+                    ground_ratio: 0.4,
+                }),
+            }),
+        ]
+    }
+
+    fn config(width: usize, height: usize) -> GenerationConfig {
+        GenerationConfig {
+            width,
+            height,
+            liquid: Some(Liquid::Water),
+            ..GenerationConfig::default()
+        }
+    }
+
+    /// Right size, border closed and some ground to stand on
+    fn check_floor(name: &str, seed: u64, grid: &[Vec<i32>], width: usize, height: usize) {
+        assert_eq!(grid.len(), height, "{} seed {}: wrong height", name, seed);
+        for (y, row) in grid.iter().enumerate() {
+            assert_eq!(row.len(), width, "{} seed {}: ragged row {}", name, seed, y);
+            for (x, &material) in row.iter().enumerate() {
+                if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                    assert_eq!(
+                        material, WALL,
+                        "{} seed {}: open border at {},{}",
+                        name, seed, x, y
+                    );
+                }
+            }
+        }
+        assert!(
+            grid.iter().flatten().any(|&material| material != WALL),
+            "{} seed {}: no open ground",
+            name,
+            seed
+        );
+    }
+
+    /// Every open tile reachable from the first tile of the main region
+    fn check_connected(name: &str, seed: u64, grid: &[Vec<i32>], main_region: &[(usize, usize)]) {
+        assert!(
+            !main_region.is_empty(),
+            "{} seed {}: no main region",
+            name,
+            seed
+        );
+        let spawn = main_region[0];
+        let distances = distances_from(grid, spawn);
+        for (y, row) in grid.iter().enumerate() {
+            for (x, &material) in row.iter().enumerate() {
+                if is_open(material) {
+                    assert!(
+                        distances[y][x].is_some(),
+                        "{} seed {}: {},{} can't be reached from {:?}",
+                        name,
+                        seed,
+                        x,
+                        y,
+                        spawn
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_generator_gives_a_closed_connected_floor() {
+        for generator in generators() {
+            for &(width, height) in SIZES.iter() {
+                let config = config(width, height);
+                for &seed in SEEDS.iter() {
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    let mut grid = generate(generator.as_ref(), &config, &mut rng);
+                    let main_region = connect_regions(&mut grid, |_, _| false);
+                    check_floor(generator.name(), seed, &grid, width, height);
+                    check_connected(generator.name(), seed, &grid, &main_region);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_depth_gives_a_closed_connected_floor() {
+        let config = config(70, 40);
+        for &seed in SEEDS.iter() {
+            for depth in 0..14 {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let generator = generator_for_depth(depth, &mut rng);
+                let mut grid = generate(generator.as_ref(), &config, &mut rng);
+                let main_region = connect_regions(&mut grid, |_, _| false);
+                check_floor(generator.name(), seed, &grid, 70, 40);
+                check_connected(generator.name(), seed, &grid, &main_region);
+            }
+        }
+    }
+
+    #[test]
+    fn the_first_floors_keep_the_original_caves() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for depth in 0..3 {
+            assert_eq!(generator_for_depth(depth, &mut rng).name(), "simplex caves");
+        }
+    }
+
+    #[test]
+    fn protected_tiles_are_left_alone() {
+        // A closed room in the middle of the ground, nothing may dig into it
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut grid = generate(
+            &DrunkardWalk {
+                walkers: 4,
+                ground_ratio: 0.4,
+            },
+            &config(40, 40),
+            &mut rng,
+        );
+        let room = |x: usize, y: usize| (15..25).contains(&x) && (15..25).contains(&y);
+        for (y, row) in grid.iter_mut().enumerate() {
+            for (x, material) in row.iter_mut().enumerate() {
+                if room(x, y) {
+                    *material = WALL;
+                }
+            }
+        }
+        connect_regions(&mut grid, room);
+        for (y, row) in grid.iter().enumerate() {
+            for (x, &material) in row.iter().enumerate() {
+                if room(x, y) {
+                    assert_eq!(material, WALL, "dug into the room at {},{}", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_floor() {
+        let config = config(60, 45);
+        for generator in generators() {
+            let first = generate(generator.as_ref(), &config, &mut StdRng::seed_from_u64(3));
+            let second = generate(generator.as_ref(), &config, &mut StdRng::seed_from_u64(3));
+            assert_eq!(first, second, "{} isn't deterministic", generator.name());
+        }
+    }
+
+    #[test]
+    fn overrides_apply_in_file_order_between_their_depths() {
+        let config: GenerationConfig = toml::from_str(
+            r#"
+            width = 80
+            [[depths]]
+            min = 2
+            max = 4
+            width = 50
+            liquid = "water"
+            [[depths]]
+            min = 4
+            liquid = "lava"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.height, GenerationConfig::default().height);
+
+        let depth_1 = config.for_depth(1);
+        assert_eq!((depth_1.width, depth_1.liquid), (80, None));
+        let depth_3 = config.for_depth(3);
+        assert_eq!((depth_3.width, depth_3.liquid), (50, Some(Liquid::Water)));
+        let depth_4 = config.for_depth(4);
+        assert_eq!((depth_4.width, depth_4.liquid), (50, Some(Liquid::Lava)));
+        let depth_9 = config.for_depth(9);
+        assert_eq!((depth_9.width, depth_9.liquid), (80, Some(Liquid::Lava)));
+    }
+
+    #[test]
+    fn tiny_maps_are_made_big_enough() {
+        let config = GenerationConfig {
+            width: 3,
+            height: 0,
+            noise_octaves: 0,
+            depths: Vec::new(),
+            ..GenerationConfig::default()
+        }
+        .for_depth(0);
+        assert_eq!((config.width, config.height), (MIN_MAP_SIZE, MIN_MAP_SIZE));
+        assert_eq!(config.noise_octaves, 1);
+    }
+
+    #[test]
+    fn the_shipped_config_parses() {
+        let content = std::fs::read_to_string("assets/generation.toml").unwrap();
+        let config: GenerationConfig = toml::from_str(&content).unwrap();
+        assert!(!config.depths.is_empty());
+    }
+}
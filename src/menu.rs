@@ -1,14 +1,183 @@
 use egui::Window;
 use ggez_egui::EguiBackend;
-use ggez::event::KeyCode;
+use ggez::event::{Axis, Button, KeyCode};
+
+use crate::input::{self, Binding, KeyMap, PadMap};
 
 pub struct Gui {
     pub egui_backend: EguiBackend,
     pub scale: f32,
     pub show_main: bool,
     pub show_settings: bool,
+    pub show_inventory: bool,
     pub freeze_game: bool,
-    pub latest: KeyCode,
+    capture: Option<Capture>, // the binding being set, waiting for an input
+    pressed: Option<Pressed>,
+    conflict: Option<Conflict>,
+    pub start_open_world: bool,
+    pub settings_closed: bool, // the settings are saved when leaving the menu
+    pub notice: Option<String>, // shown in the main menu
+    scrub: Option<usize>, // where the replay slider is dragged, the replay jumps there once let go
+}
+
+/// Slot of a binding being set in the settings menu
+#[derive(Clone, Debug, PartialEq)]
+struct Capture {
+    action: String,
+    slot: usize,
+    device: Device,
+}
+
+/// Which of the bindings the capture is for
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Device {
+    Keys,
+    Buttons,
+    Axes,
+}
+
+/// What was pressed while capturing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pressed {
+    Input(Binding),
+    Button(Button),
+    Axis(Axis),
+    Clear,
+}
+
+/// The binding is already used by another action, the player chooses to swap them or not
+struct Conflict {
+    capture: Capture,
+    pressed: Pressed,
+    other: (String, usize),
+}
+
+impl Pressed {
+    fn name(&self) -> String {
+        match self {
+            Pressed::Input(binding) => binding.name(),
+            Pressed::Button(button) => format!("{:?}", button),
+            Pressed::Axis(axis) => format!("{:?}", axis),
+            Pressed::Clear => "-".to_string(),
+        }
+    }
+}
+
+fn action_label(action: &str) -> String {
+    match action {
+        "up" => "Up".to_string(),
+        "down" => "Down".to_string(),
+        "left" => "Left".to_string(),
+        "right" => "Right".to_string(),
+        "fire" => "Fire".to_string(),
+        "sneak" => "Sneak (reveals traps around you)".to_string(),
+        "next_map" => "Take the stairs (down on the ladder, up where you arrived)".to_string(),
+        "pause" => "Escape and pause. Go back to the main menu".to_string(),
+        "next_weapon" => "Next weapon".to_string(),
+        "move_x" => "Move left and right".to_string(),
+        "move_y" => "Move up and down".to_string(),
+        "aim_x" => "Aim left and right".to_string(),
+        "aim_y" => "Aim up and down".to_string(),
+        "interact" => "Open and close doors".to_string(),
+        "inventory" => "Inventory".to_string(),
+        "debug" => "Show the fps and debug info".to_string(),
+        _ => match action.strip_prefix("hotbar_") {
+            Some(slot) => format!("Hotbar slot {}", slot),
+            None => action.to_string(),
+        },
+    }
+}
+
+/// Puts the binding in the slot. If another slot already has it nothing changes and that slot
+/// is returned, unless swapping: then the other slot gets what this one had.
+fn set_binding<T: Copy + PartialEq>(
+    rows: &mut Vec<(String, &mut [T])>,
+    action: &str,
+    slot: usize,
+    binding: T,
+    swap: bool,
+) -> Option<(String, usize)> {
+    let other = rows.iter().find_map(|(name, slots)| {
+        slots
+            .iter()
+            .enumerate()
+            .find(|(other_slot, other)| {
+                **other == binding && !(name == action && *other_slot == slot)
+            })
+            .map(|(other_slot, _)| (name.clone(), other_slot))
+    });
+    let old = rows
+        .iter()
+        .find(|(name, _)| name == action)
+        .map(|(_, slots)| slots[slot])?;
+    if let Some((other_action, other_slot)) = &other {
+        if !swap {
+            return other;
+        }
+        for (name, slots) in rows.iter_mut() {
+            if name == other_action {
+                slots[*other_slot] = old;
+            }
+        }
+    }
+    for (name, slots) in rows.iter_mut() {
+        if name == action {
+            slots[slot] = binding;
+        }
+    }
+    None
+}
+
+/// Applies what was pressed to the slot being captured, returns the slot it conflicts with
+fn bind(
+    keymap: &mut KeyMap,
+    padmap: &mut PadMap,
+    capture: &Capture,
+    pressed: Pressed,
+    swap: bool,
+) -> Option<(String, usize)> {
+    match pressed {
+        Pressed::Input(binding) => {
+            let mut rows = keymap
+                .bindings_mut()
+                .into_iter()
+                .map(|(name, bind)| (name, &mut bind.0[..]))
+                .collect();
+            set_binding(
+                &mut rows,
+                &capture.action,
+                capture.slot,
+                Some(binding),
+                swap,
+            )
+        }
+        Pressed::Button(button) => {
+            let mut rows = padmap
+                .bindings_mut()
+                .into_iter()
+                .map(|(name, button)| (name, std::slice::from_mut(button)))
+                .collect();
+            set_binding(&mut rows, &capture.action, capture.slot, button, swap)
+        }
+        Pressed::Axis(axis) => {
+            let mut rows = padmap
+                .axes_mut()
+                .into_iter()
+                .map(|(name, axis)| (name, std::slice::from_mut(axis)))
+                .collect();
+            set_binding(&mut rows, &capture.action, capture.slot, axis, swap)
+        }
+        Pressed::Clear => {
+            if let Some((_, bind)) = keymap
+                .bindings_mut()
+                .into_iter()
+                .find(|(name, _)| *name == capture.action)
+            {
+                bind.0[capture.slot] = None;
+            }
+            None
+        }
+    }
 }
 
 impl Gui {
@@ -18,8 +187,38 @@ impl Gui {
             scale: 2.0,
             show_main: true,
             show_settings: false,
+            show_inventory: false,
             freeze_game: true,
-            latest: KeyCode::Key0,
+            capture: None,
+            pressed: None,
+            conflict: None,
+            start_open_world: false,
+            settings_closed: false,
+            notice: None,
+            scrub: None,
+        }
+    }
+
+    /// While a binding is being set, every input goes here instead of the game and the menus
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+    pub fn capture_input(&mut self, pressed: Pressed) {
+        let device = match &self.capture {
+            Some(capture) => capture.device,
+            None => return,
+        };
+        match (pressed, device) {
+            (Pressed::Input(Binding::Key(KeyCode::Escape)), _) => self.capture = None,
+            (Pressed::Input(Binding::Key(KeyCode::Back)), Device::Keys) => {
+                self.pressed = Some(Pressed::Clear)
+            }
+            // A key without a name couldn't be saved in the settings, wait for another one
+            (Pressed::Input(Binding::Key(key)), Device::Keys) if !input::can_bind(key) => (),
+            (Pressed::Input(_), Device::Keys)
+            | (Pressed::Button(_), Device::Buttons)
+            | (Pressed::Axis(_), Device::Axes) => self.pressed = Some(pressed),
+            _ => (),
         }
     }
 
@@ -36,6 +235,12 @@ impl Gui {
                     self.show_settings = false;
                     self.freeze_game = false;
                 }
+                if ui.button("open world").clicked() {
+                    self.start_open_world = true;
+                    self.show_main = false;
+                    self.show_settings = false;
+                    self.freeze_game = false;
+                }
                 if ui.button("settings").clicked() {
                     self.show_settings = true;
                     self.show_main = false;
@@ -44,10 +249,30 @@ impl Gui {
                 if ui.button("quit").clicked() {
                     ggez::event::quit(ggez_ctx);
                 }
+                if let Some(notice) = &self.notice {
+                    ui.label(notice);
+                }
             });
     }
 
-    pub fn settings_menu(&mut self, window_size: glam::Vec2, keymap: &mut crate::input::KeyMap) {
+    pub fn settings_menu(
+        &mut self,
+        window_size: glam::Vec2,
+        volumes: &mut crate::audio::Volumes,
+        window: &mut crate::settings::WindowSettings,
+        keymap: &mut KeyMap,
+        padmap: &mut PadMap,
+    ) {
+        if let Some(pressed) = self.pressed.take() {
+            if let Some(capture) = self.capture.take() {
+                self.conflict =
+                    bind(keymap, padmap, &capture, pressed, false).map(|other| Conflict {
+                        capture,
+                        pressed,
+                        other,
+                    });
+            }
+        }
         let egui_ctx = self.egui_backend.get_context();
         Window::new("Settings")
             .open(&mut true)
@@ -64,67 +289,320 @@ impl Gui {
                     });
                 });
                 ui.group(|ui| {
-                    ui.label("Input settings");
-                    ui.horizontal(|ui| {
-                        ui.label("Up");
-                        let response = ui.add(egui::TextEdit::singleline(&mut format!("{:?}", keymap.up)));
-                        if response.changed() {
-                            keymap.up = self.latest;
-                        }
-                    });
+                    ui.label("Window");
                     ui.horizontal(|ui| {
-                        ui.label("Down");
-                        let response = ui.add(egui::TextEdit::singleline(&mut format!("{:?}", keymap.down)));
-                        if response.changed() {
-                            keymap.down = self.latest;
+                        for kind in [
+                            crate::settings::WindowKind::Windowed,
+                            crate::settings::WindowKind::Borderless,
+                            crate::settings::WindowKind::Fullscreen,
+                        ]
+                        .iter()
+                        {
+                            if ui.radio(window.kind == *kind, kind.name()).clicked() {
+                                window.kind = *kind;
+                            }
                         }
                     });
                     ui.horizontal(|ui| {
-                        ui.label("Left");
-                        let response = ui.add(egui::TextEdit::singleline(&mut format!("{:?}", keymap.left)));
-                        if response.changed() {
-                            keymap.left = self.latest;
+                        for (width, height) in crate::settings::RESOLUTIONS.iter() {
+                            let selected = window.width == *width && window.height == *height;
+                            if ui
+                                .radio(selected, format!("{}x{}", width, height))
+                                .clicked()
+                            {
+                                window.width = *width;
+                                window.height = *height;
+                            }
                         }
                     });
-                    ui.horizontal(|ui| {
-                        ui.label("Right");
-                        let response = ui.add(egui::TextEdit::singleline(&mut format!("{:?}", keymap.right)));
-                        if response.changed() {
-                            keymap.right = self.latest;
+                    ui.checkbox(&mut window.vsync, "Vsync (on the next start)");
+                });
+                ui.group(|ui| {
+                    ui.label("Volume");
+                    ui.add(egui::Slider::new(&mut volumes.master, 0.0..=1.0).text("Master"));
+                    ui.add(egui::Slider::new(&mut volumes.sfx, 0.0..=1.0).text("Effects"));
+                    ui.add(egui::Slider::new(&mut volumes.music, 0.0..=1.0).text("Music"));
+                    ui.add(egui::Slider::new(&mut volumes.ui, 0.0..=1.0).text("Interface"));
+                });
+                ui.group(|ui| {
+                    ui.label("Input settings");
+                    ui.label("Click a binding then press a key, a mouse button, a gamepad button");
+                    ui.label("or move a stick. Escape cancels, Backspace clears.");
+                    if let Some(conflict) = self.conflict.take() {
+                        ui.label(format!(
+                            "{} is already used by {}",
+                            conflict.pressed.name(),
+                            action_label(&conflict.other.0)
+                        ));
+                        ui.horizontal(|ui| {
+                            if ui.button("Swap").clicked() {
+                                bind(keymap, padmap, &conflict.capture, conflict.pressed, true);
+                            } else if !ui.button("Cancel").clicked() {
+                                self.conflict = Some(conflict);
+                            }
+                        });
+                    }
+                    egui::Grid::new("keys").show(ui, |ui| {
+                        for (action, bind) in keymap.bindings_mut() {
+                            ui.label(action_label(&action));
+                            for slot in 0..bind.0.len() {
+                                let capture = Capture {
+                                    action: action.clone(),
+                                    slot,
+                                    device: Device::Keys,
+                                };
+                                let text = if self.capture.as_ref() == Some(&capture) {
+                                    "Press a key...".to_string()
+                                } else {
+                                    bind.0[slot]
+                                        .map(|binding| binding.name())
+                                        .unwrap_or_else(|| "-".to_string())
+                                };
+                                if ui.button(text).clicked() {
+                                    self.capture = Some(capture);
+                                }
+                            }
+                            ui.end_row();
                         }
                     });
-                    ui.horizontal(|ui| {
-                        ui.label("Go to the next map (use when you're on the ladder)");
-                        let response = ui.add(egui::TextEdit::singleline(&mut format!("{:?}", keymap.next_map)));
-                        if response.changed() {
-                            keymap.next_map = self.latest;
+                    ui.label("Gamepad");
+                    egui::Grid::new("gamepad").show(ui, |ui| {
+                        for (action, button) in padmap.bindings_mut() {
+                            ui.label(action_label(&action));
+                            let capture = Capture {
+                                action,
+                                slot: 0,
+                                device: Device::Buttons,
+                            };
+                            let text = if self.capture.as_ref() == Some(&capture) {
+                                "Press a button...".to_string()
+                            } else {
+                                format!("{:?}", button)
+                            };
+                            if ui.button(text).clicked() {
+                                self.capture = Some(capture);
+                            }
+                            ui.end_row();
                         }
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Escape and pause. Go back to the main menu");
-                        let response = ui.add(egui::TextEdit::singleline(&mut format!("{:?}", keymap.escape)));
-                        if response.changed() {
-                            keymap.escape = self.latest;
+                        for (action, axis) in padmap.axes_mut() {
+                            ui.label(action_label(&action));
+                            let capture = Capture {
+                                action,
+                                slot: 0,
+                                device: Device::Axes,
+                            };
+                            let text = if self.capture.as_ref() == Some(&capture) {
+                                "Move a stick...".to_string()
+                            } else {
+                                format!("{:?}", axis)
+                            };
+                            if ui.button(text).clicked() {
+                                self.capture = Some(capture);
+                            }
+                            ui.end_row();
                         }
                     });
+                    let (min, max) = crate::input::DEAD_ZONE_RANGE;
+                    ui.add(
+                        egui::Slider::new(&mut padmap.sticks.dead_zone, min..=max)
+                            .text("Stick dead zone"),
+                    );
                     ui.horizontal(|ui| {
-                        ui.label("Inventory");
-                        let response = ui.add(egui::TextEdit::singleline(&mut format!("{:?}", keymap.inventory)));
-                        if response.changed() {
-                            keymap.inventory = self.latest;
+                        ui.label("Stick response");
+                        for curve in crate::input::ResponseCurve::ALL.iter() {
+                            if ui
+                                .radio(padmap.sticks.curve == *curve, curve.name())
+                                .clicked()
+                            {
+                                padmap.sticks.curve = *curve;
+                            }
                         }
                     });
+                    ui.checkbox(&mut padmap.sticks.aim_assist, "Aim assist");
+                    if ui.button("Reset to defaults").clicked() {
+                        *keymap = crate::input::KeyMap::default();
+                        *padmap = crate::input::PadMap::default();
+                        self.capture = None;
+                        self.conflict = None;
+                    }
                 });
                 if ui.button("Back").clicked() {
+                    self.capture = None;
                     self.show_settings = false;
+                    self.settings_closed = true;
                     self.show_main = true;
                     self.freeze_game = true;
                 }
             });
     }
 
+    pub fn inventory_menu(
+        &mut self,
+        inventory: &mut crate::weapon::WeaponInventory,
+        items: &mut crate::item::ItemInventory,
+    ) {
+        let egui_ctx = self.egui_backend.get_context();
+        Window::new("Inventory")
+            .anchor(egui::Align2::RIGHT_CENTER, egui::Vec2::new(-10., 0.))
+            .show(&egui_ctx, |ui| {
+                for index in 0..inventory.weapon_list.len() {
+                    let (label, tooltip) = match &inventory.weapon_list[index] {
+                        Some(w) => (w.name(), w.tooltip()),
+                        None => ("-".to_string(), "Empty slot".to_string()),
+                    };
+                    let selected = index == inventory.selected_index;
+                    let response = ui
+                        .selectable_label(selected, format!("{}: {}", index, label))
+                        .on_hover_text(tooltip);
+                    if response.clicked() {
+                        inventory.selected_index = index;
+                    }
+                }
+                if let Some(w) = inventory.selected() {
+                    ui.separator();
+                    ui.label(w.tooltip());
+                }
+                ui.separator();
+                ui.label("Items");
+                for stack in items.stacks.clone().iter() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} x{}", stack.item.name(), stack.count))
+                            .on_hover_text(stack.item.description());
+                        for slot in 0..items.hotbar.len() {
+                            let bound = items.hotbar[slot] == Some(stack.item);
+                            if ui
+                                .selectable_label(bound, format!("{}", slot + 1))
+                                .on_hover_text("Bind to this hotbar slot")
+                                .clicked()
+                            {
+                                items.hotbar[slot] = Some(stack.item);
+                            }
+                        }
+                    });
+                }
+            });
+    }
+
+    /// Debug viewer of a replay being played back
+    pub fn replay_viewer(&mut self, playback: &mut crate::replay::Playback) {
+        let egui_ctx = self.egui_backend.get_context();
+        let total = playback.replay.ticks.len();
+        Window::new("Replay")
+            .anchor(egui::Align2::CENTER_TOP, egui::Vec2::new(0., 10.))
+            .show(&egui_ctx, |ui| {
+                ui.label(format!(
+                    "Update {} / {} ({:.1} s), seed {}",
+                    playback.position,
+                    total,
+                    playback.time_at(playback.position),
+                    playback.replay.seed
+                ));
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(if playback.paused { "Play" } else { "Pause" })
+                        .clicked()
+                    {
+                        playback.paused = !playback.paused;
+                    }
+                    if ui
+                        .add(
+                            egui::Button::new("Step")
+                                .enabled(playback.paused && !playback.is_over()),
+                        )
+                        .clicked()
+                    {
+                        playback.seek = Some(playback.position + 1);
+                    }
+                    for speed in crate::replay::SPEEDS.iter() {
+                        if ui
+                            .radio(playback.speed == *speed, format!("x{}", speed))
+                            .clicked()
+                        {
+                            playback.speed = *speed;
+                        }
+                    }
+                });
+                let mut target = self.scrub.unwrap_or(playback.position);
+                let response = ui.add(egui::Slider::new(&mut target, 0..=total).text("Update"));
+                if response.dragged() {
+                    self.scrub = Some(target);
+                } else if response.changed() || response.drag_released() {
+                    self.scrub = None;
+                    playback.seek = Some(target);
+                }
+                if ui
+                    .button("Take over")
+                    .on_hover_text("Stops the replay, the game goes on from here")
+                    .clicked()
+                {
+                    playback.stop = true;
+                }
+            });
+    }
+
     pub fn draw(&self, ctx: &mut ggez::Context, draw_offset: glam::Vec2) -> ggez::GameResult {
         ggez::graphics::draw(ctx, &self.egui_backend, (draw_offset,))?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_free_binding_is_just_set() {
+        let (mut up, mut down) = ([1, 2], [3, 4]);
+        let mut rows = vec![
+            ("up".to_string(), &mut up[..]),
+            ("down".to_string(), &mut down[..]),
+        ];
+        assert_eq!(set_binding(&mut rows, "up", 1, 5, false), None);
+        assert_eq!((up, down), ([1, 5], [3, 4]));
+    }
+
+    #[test]
+    fn a_conflict_changes_nothing_and_names_the_other_slot() {
+        let (mut up, mut down) = ([1, 2], [3, 4]);
+        let mut rows = vec![
+            ("up".to_string(), &mut up[..]),
+            ("down".to_string(), &mut down[..]),
+        ];
+        assert_eq!(
+            set_binding(&mut rows, "up", 0, 4, false),
+            Some(("down".to_string(), 1))
+        );
+        assert_eq!((up, down), ([1, 2], [3, 4]));
+    }
+
+    #[test]
+    fn swapping_gives_the_other_slot_the_old_binding() {
+        let (mut up, mut down) = ([1, 2], [3, 4]);
+        let mut rows = vec![
+            ("up".to_string(), &mut up[..]),
+            ("down".to_string(), &mut down[..]),
+        ];
+        assert_eq!(set_binding(&mut rows, "up", 0, 4, true), None);
+        assert_eq!((up, down), ([4, 2], [3, 1]));
+    }
+
+    #[test]
+    fn the_primary_and_secondary_of_an_action_can_swap() {
+        let mut up = [1, 2];
+        let mut rows = vec![("up".to_string(), &mut up[..])];
+        assert_eq!(
+            set_binding(&mut rows, "up", 0, 2, false),
+            Some(("up".to_string(), 1))
+        );
+        assert_eq!(set_binding(&mut rows, "up", 0, 2, true), None);
+        assert_eq!(up, [2, 1]);
+    }
+
+    #[test]
+    fn setting_the_same_binding_again_is_no_conflict() {
+        let mut up = [1, 2];
+        let mut rows = vec![("up".to_string(), &mut up[..])];
+        assert_eq!(set_binding(&mut rows, "up", 1, 2, false), None);
+        assert_eq!(up, [1, 2]);
+    }
+}
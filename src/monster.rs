@@ -1,12 +1,20 @@
-use crate::{bloc, id, physics, weapon};
+use crate::{bloc, hearing, id, map, physics, sprite, status, weapon, world};
 use ggez;
 use glam;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 const TEST_BOT_SPEED: f32 = 250.;
 const VISION_CONE: f32 = 100.;
+const ALERT_RADIUS: f32 = 8.; // in tiles
+const CORPSE_TIME: f32 = 1.; // in seconds, long enough to see the die animation
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum MonsterType {
     TestBot,
+    MossCrawler,
+    Drowned,
+    CrystalGolem,
+    MagmaImp,
+    RustyCrab,
 }
 
 pub enum Monster {
@@ -23,20 +31,35 @@ pub struct Brain {
 
 pub struct MonsterManager {
     pub monster_list: Vec<Monster>,
+    pub corpses: Vec<Corpse>,
+    pub rng: rand::rngs::StdRng, // seeded with the run, for the spawns, the wandering and the loot
+}
+/// What's left of a dead monster, only there to play its die animation
+pub struct Corpse {
+    pub pos: glam::Vec2,
+    pub size: f32,
+    pub angle: f32,
+    pub color: ggez::graphics::Color,
+    pub time: f32,
 }
 pub struct TestBot {
     pub id: i32,
+    pub kind: MonsterType,
     pub name: String,
     pub hp: i32,
     pub hitbox: ggez::graphics::Rect,
     pub speed: f32,
     pub los: physics::LOS,
     pub brain: Brain,
+    pub status: status::StatusEffects,
+    pub animator: sprite::Animator,
 }
 impl MonsterManager {
-    pub fn new() -> Self {
+    pub fn new(seed: u64) -> Self {
         MonsterManager {
             monster_list: Vec::new(),
+            corpses: Vec::new(),
+            rng: rand::rngs::StdRng::seed_from_u64(seed),
         }
     }
     pub fn new_bot(
@@ -45,21 +68,20 @@ impl MonsterManager {
         id_manager: &mut id::IdManager,
         map_infos: (Vec<Vec<i32>>, Vec<f32>, f32),
     ) {
-        let brain = Brain::new();
         let mut pos = glam::Vec2::ZERO;
         loop {
             // This looks ok
             let random_pos = glam::Vec2::new(
-                rand::thread_rng().gen_range(0. ..map_infos.0[0].len() as f32 - 1.) * map_infos.2,
-                rand::thread_rng().gen_range(0. ..map_infos.0.len() as f32 - 1.) * map_infos.2,
+                self.rng.gen_range(0. ..map_infos.0[0].len() as f32 - 1.) * map_infos.2,
+                self.rng.gen_range(0. ..map_infos.0.len() as f32 - 1.) * map_infos.2,
             );
 
             let shifted_entity_position =
                 glam::Vec2::new(random_pos.x / map_infos.2, random_pos.y / map_infos.2);
 
             if map_infos.1.contains(
-                &(map_infos.0[shifted_entity_position.x as usize]
-                    [shifted_entity_position.y as usize] as f32),
+                &(map_infos.0[shifted_entity_position.y as usize]
+                    [shifted_entity_position.x as usize] as f32),
             ) {
                 // pos is ok
                 pos = random_pos;
@@ -67,11 +89,22 @@ impl MonsterManager {
                 break;
             }
         }
-        let new_monster = match monster_type {
-            MonsterType::TestBot => {
-                Monster::TestBot(TestBot::new(pos.x, pos.y, id_manager.get_new_id(), brain))
-            }
-        };
+        self.spawn(monster_type, pos, id_manager);
+    }
+    pub fn spawn(
+        &mut self,
+        monster_type: MonsterType,
+        pos: glam::Vec2,
+        id_manager: &mut id::IdManager,
+    ) {
+        let brain = Brain::new(&mut self.rng);
+        // Every kind shares the TestBot behaviour, only the stats change
+        let mut bot = TestBot::new(pos.x, pos.y, id_manager.get_new_id(), brain);
+        bot.kind = monster_type;
+        bot.name = monster_type.name().to_string();
+        bot.hp = monster_type.max_hp();
+        bot.speed = monster_type.speed();
+        let new_monster = Monster::TestBot(bot);
 
         self.monster_list.push(new_monster);
     }
@@ -110,9 +143,9 @@ impl MonsterManager {
                         loop {
                             // This looks ok
                             let random_pos = glam::Vec2::new(
-                                rand::thread_rng().gen_range(0. ..map_infos.0[0].len() as f32 - 1.)
+                                self.rng.gen_range(0. ..map_infos.0[0].len() as f32 - 1.)
                                     * map_infos.2,
-                                rand::thread_rng().gen_range(0. ..map_infos.0.len() as f32 - 1.)
+                                self.rng.gen_range(0. ..map_infos.0.len() as f32 - 1.)
                                     * map_infos.2,
                             );
 
@@ -122,8 +155,8 @@ impl MonsterManager {
                             );
 
                             if map_infos.1.contains(
-                                &(map_infos.0[shifted_entity_position.x as usize]
-                                    [shifted_entity_position.y as usize]
+                                &(map_infos.0[shifted_entity_position.y as usize]
+                                    [shifted_entity_position.x as usize]
                                     as f32),
                             ) {
                                 // pos is ok
@@ -153,15 +186,56 @@ impl MonsterManager {
         }
     }
 
+    /// Same wandering as on the floors, but the paths stay in the loaded chunks
+    pub fn update_movements_in_world(&mut self, dt: f32, world: &world::ChunkedWorld) {
+        let mut pathfinding_count = 0;
+        let pathfinding_threshold = 3;
+
+        // Monsters left behind in unloaded chunks are forgotten
+        self.monster_list.retain(|monster| {
+            world.is_loaded(glam::Vec2::from(
+                physics::EntityTrait::get_hitbox(monster).center(),
+            ))
+        });
+
+        for i in 0..self.monster_list.len() {
+            match &mut self.monster_list[i] {
+                Monster::TestBot(tb) => {
+                    let pos = glam::Vec2::from(tb.hitbox.center());
+                    if tb.brain.wandering_path.is_empty()
+                        && pathfinding_count < pathfinding_threshold
+                    {
+                        pathfinding_count += 1;
+                        if let Some(target) = world.wander_target(pos, &mut self.rng) {
+                            if let physics::PathFindingResult::Ok(path) =
+                                world.find_path(pos, target)
+                            {
+                                tb.brain.wandering_path = path;
+                            }
+                        }
+                    }
+                    let area = ggez::graphics::Rect::new(
+                        tb.hitbox.x - world.tile_size,
+                        tb.hitbox.y - world.tile_size,
+                        tb.hitbox.w + world.tile_size * 2.,
+                        tb.hitbox.h + world.tile_size * 2.,
+                    );
+                    tb.update_movements(dt, world.blocs_in(area));
+                }
+            }
+        }
+    }
+
     pub fn damage_monster_isdead(
         &mut self,
         monster_index: usize,
         damage: i32,
+        difficulty: u32,
         id_manager: &mut id::IdManager,
     ) -> weapon::ObjectDrop {
         let mut monster_is_dead = false;
 
-        let drop: weapon::ObjectDrop;
+        let mut drop = weapon::ObjectDrop::None;
 
         match &mut self.monster_list[monster_index] {
             Monster::TestBot(tb) => {
@@ -170,45 +244,228 @@ impl MonsterManager {
                 if tb.is_dead() {
                     monster_is_dead = true;
                 };
-                drop = weapon::generate_drop(id_manager);
             }
         }
 
         if monster_is_dead {
-            self.monster_list.swap_remove(monster_index);
+            self.kill(monster_index);
+            drop = weapon::generate_drop(id_manager, difficulty, &mut self.rng);
         }
 
         drop
     }
 
+    /// Ticks the status effects, the monsters they kill still drop their loot
+    pub fn update_status(
+        &mut self,
+        dt: f32,
+        difficulty: u32,
+        id_manager: &mut id::IdManager,
+    ) -> Vec<weapon::ObjectDrop> {
+        let mut drops: Vec<weapon::ObjectDrop> = Vec::new();
+        for index in (0..self.monster_list.len()).rev() {
+            physics::EntityTrait::update_status(&mut self.monster_list[index], dt);
+            let is_dead = match &self.monster_list[index] {
+                Monster::TestBot(tb) => tb.is_dead(),
+            };
+            if is_dead {
+                self.kill(index);
+                drops.push(weapon::generate_drop(id_manager, difficulty, &mut self.rng));
+            }
+        }
+        for corpse in self.corpses.iter_mut() {
+            corpse.time += dt;
+        }
+        self.corpses.retain(|corpse| corpse.time < CORPSE_TIME);
+        drops
+    }
+    /// Removes the monster, leaving a corpse behind
+    fn kill(&mut self, index: usize) {
+        match self.monster_list.swap_remove(index) {
+            Monster::TestBot(tb) => self.corpses.push(Corpse {
+                pos: glam::Vec2::from(tb.hitbox.center()),
+                size: tb.hitbox.w,
+                angle: tb.los.angle,
+                color: tb.kind.color(),
+                time: 0.,
+            }),
+        }
+    }
+
+    /// Sends the monsters that are close enough to check the position
+    pub fn alert(&mut self, pos: glam::Vec2, map_infos: (Vec<Vec<i32>>, Vec<f32>, f32)) {
+        let tile_size = map_infos.2;
+        let target = glam::Vec2::new((pos.x / tile_size).floor(), (pos.y / tile_size).floor());
+        for monster in self.monster_list.iter_mut() {
+            match monster {
+                Monster::TestBot(tb) => {
+                    let center = glam::Vec2::from(tb.hitbox.center());
+                    if physics::RayCasting::get_distance(center, pos) > ALERT_RADIUS * tile_size {
+                        continue;
+                    }
+                    tb.investigate(
+                        pos,
+                        physics::PathFinding::astar(
+                            glam::Vec2::new(center.x / tile_size, center.y / tile_size),
+                            target,
+                            map_infos.clone(),
+                        ),
+                    );
+                }
+            }
+        }
+    }
+    /// Monsters that hear one of the noises go where the last one came from.
+    /// The ones already seeing the player don't get distracted.
+    pub fn hear<W, P>(
+        &mut self,
+        noises: &[hearing::Noise],
+        tile_size: f32,
+        is_wall: W,
+        find_path: P,
+    ) where
+        W: Fn(i32, i32) -> bool,
+        P: Fn(glam::Vec2, glam::Vec2) -> physics::PathFindingResult,
+    {
+        if noises.is_empty() {
+            return;
+        }
+        for monster in self.monster_list.iter_mut() {
+            match monster {
+                Monster::TestBot(tb) => {
+                    if tb.brain.see_something {
+                        continue;
+                    }
+                    let center = glam::Vec2::from(tb.hitbox.center());
+                    let heard = noises.iter().rev().find(|noise| {
+                        hearing::Noise {
+                            radius: noise.radius * tb.brain.hearing(),
+                            ..**noise
+                        }
+                        .reaches(center, tile_size, &is_wall)
+                    });
+                    if let Some(noise) = heard {
+                        tb.investigate(noise.pos, find_path(center, noise.pos));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Opens the closed door on the next tile of each monster's path
+    pub fn open_doors(&self, map: &mut map::Map) {
+        for monster in self.monster_list.iter() {
+            match monster {
+                Monster::TestBot(tb) => {
+                    if let Some(next) = tb.brain.wandering_path.first() {
+                        if let Some((x, y)) = map.tile_coords(*next) {
+                            map.open_door(x, y);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: i32) {
+        self.monster_list
+            .retain(|monster| physics::EntityTrait::id(monster) != id);
+    }
+
+    pub fn damage_in_radius(
+        &mut self,
+        center: glam::Vec2,
+        radius: f32,
+        damage: i32,
+        effect: Option<status::StatusKind>,
+        difficulty: u32,
+        id_manager: &mut id::IdManager,
+    ) -> Vec<weapon::ObjectDrop> {
+        let mut drops: Vec<weapon::ObjectDrop> = Vec::new();
+        // Backward, dead monsters are swap_removed
+        for index in (0..self.monster_list.len()).rev() {
+            let monster_center = glam::Vec2::from(
+                physics::EntityTrait::get_hitbox(&self.monster_list[index]).center(),
+            );
+            if physics::RayCasting::get_distance(center, monster_center) <= radius {
+                if let Some(kind) = effect {
+                    physics::EntityTrait::apply_status(&mut self.monster_list[index], kind);
+                }
+                drops.push(self.damage_monster_isdead(index, damage, difficulty, id_manager));
+            }
+        }
+        drops
+    }
+
     pub fn draw_monsters(
         &self,
         ctx: &mut ggez::Context,
         draw_offset: glam::Vec2,
+        view: ggez::graphics::Rect,
+        sprites: &mut sprite::SpriteAtlas,
     ) -> ggez::GameResult {
-        if !self.monster_list.is_empty() {
+        for corpse in self.corpses.iter() {
+            sprites.add(
+                "monster.die",
+                corpse.time,
+                corpse.pos,
+                corpse.size * 1.4,
+                corpse.angle,
+                corpse.color,
+            );
+        }
+        // Off screen monsters don't go in the meshes, vision circles included
+        let visible: Vec<usize> = (0..self.monster_list.len())
+            .filter(|i| {
+                let hitbox = physics::EntityTrait::get_hitbox(&self.monster_list[*i]);
+                let reach = match &self.monster_list[*i] {
+                    Monster::TestBot(tb) => tb.brain.large_vision_circle.radius,
+                };
+                ggez::graphics::Rect::new(
+                    hitbox.x - reach,
+                    hitbox.y - reach,
+                    hitbox.w + reach * 2.,
+                    hitbox.h + reach * 2.,
+                )
+                .overlaps(&view)
+            })
+            .collect();
+        if !visible.is_empty() {
             let mut hitbox_mesh = ggez::graphics::MeshBuilder::new();
             let mut vision_circles_mesh = ggez::graphics::MeshBuilder::new();
 
-            for i in 0..self.monster_list.len() {
+            for i in visible {
                 let monster_hitbox = physics::EntityTrait::get_hitbox(&self.monster_list[i]);
                 let hitbox_lines = physics::rotate_square(
                     physics::EntityTrait::get_hitbox(&self.monster_list[i]),
                     physics::EntityTrait::get_angle(&self.monster_list[i]),
                 );
 
-                let (close_circle, large_circle, vision_cone, iq, see_something, hp, angle) =
-                    match &self.monster_list[i] {
-                        Monster::TestBot(tb) => (
-                            tb.brain.close_vision_circle,
-                            tb.brain.large_vision_circle,
-                            tb.brain.vision_cone,
-                            tb.brain.iq,
-                            tb.brain.see_something,
-                            tb.hp,
-                            tb.los.angle,
-                        ),
-                    };
+                let (
+                    close_circle,
+                    large_circle,
+                    vision_cone,
+                    iq,
+                    see_something,
+                    hp,
+                    max_hp,
+                    color,
+                    angle,
+                    effects,
+                ) = match &self.monster_list[i] {
+                    Monster::TestBot(tb) => (
+                        tb.brain.close_vision_circle,
+                        tb.brain.large_vision_circle,
+                        tb.brain.vision_cone,
+                        tb.brain.iq,
+                        tb.brain.see_something,
+                        tb.hp,
+                        tb.kind.max_hp(),
+                        tb.kind.color(),
+                        tb.los.angle,
+                        &tb.status.list,
+                    ),
+                };
 
                 let accent_color: ggez::graphics::Color;
                 if see_something {
@@ -234,6 +491,18 @@ impl MonsterManager {
                     vision_cone.1,
                 );
 
+                // Grey sprites, tinted with the colour of the kind
+                let animator = match &self.monster_list[i] {
+                    Monster::TestBot(tb) => &tb.animator,
+                };
+                sprites.add(
+                    &animator.sprite("monster"),
+                    animator.time,
+                    glam::Vec2::from(monster_hitbox.center()),
+                    monster_hitbox.w * 1.4,
+                    angle,
+                    color,
+                );
                 hitbox_mesh.polyline(
                     ggez::graphics::DrawMode::stroke(1.),
                     &hitbox_lines,
@@ -277,11 +546,9 @@ impl MonsterManager {
                     ggez::graphics::Color::BLACK,
                 )?;
 
-                let hp_color = ggez::graphics::Color::from_rgb(
-                    std::cmp::min(255, (255 - (255 * ((hp - (100 - hp)) / 100))) as u8),
-                    std::cmp::min(255, (255 * (hp / (100 / 2))) as u8),
-                    0,
-                );
+                // Out of 100 whatever the kind, so the colors stay the same
+                let hp = hp * 100 / max_hp;
+                let hp_color = crate::hud::hp_color(hp as f32 / 100.);
                 let hp_width = (monster_hitbox.w * hp as f32 / 100.) as i32;
 
                 let hp_rect = ggez::graphics::Rect::new(
@@ -291,8 +558,23 @@ impl MonsterManager {
                     monster_hitbox.h / 3.,
                 );
                 hitbox_mesh.rectangle(ggez::graphics::DrawMode::fill(), hp_rect, hp_color)?;
+
+                for (index, effect) in effects.iter().enumerate() {
+                    hitbox_mesh.circle(
+                        ggez::graphics::DrawMode::fill(),
+                        glam::Vec2::new(
+                            monster_hitbox.x + 5. + index as f32 * 12.,
+                            monster_hitbox.y - monster_hitbox.h / 3. - 8.,
+                        ),
+                        5.,
+                        0.1,
+                        effect.kind.color(),
+                    )?;
+                }
             }
 
+            // Under the health bars
+            sprites.draw(ctx, draw_offset)?;
             let builded_hitbox_mesh = hitbox_mesh.build(ctx)?;
 
             let builded_vision_mesh = vision_circles_mesh.build(ctx)?;
@@ -308,6 +590,8 @@ impl MonsterManager {
                 &builded_vision_mesh,
                 (draw_offset, 0., ggez::graphics::Color::WHITE),
             )?;
+        } else {
+            sprites.draw(ctx, draw_offset)?;
         }
 
         Ok(())
@@ -315,8 +599,8 @@ impl MonsterManager {
 }
 
 impl Brain {
-    pub fn new() -> Self {
-        let iq = rand::thread_rng().gen_range(60..160);
+    pub fn new<R: Rng>(rng: &mut R) -> Self {
+        let iq = rng.gen_range(60..160);
         Brain {
             iq: iq,
             close_vision_circle: physics::Circle::new(glam::Vec2::ZERO, 0.),
@@ -338,6 +622,10 @@ impl Brain {
 
         self.large_vision_circle = physics::Circle::new(entity_pos, (self.iq * 3) as f32);
     }
+    /// The smarter ones have better ears
+    pub fn hearing(&self) -> f32 {
+        self.iq as f32 / 100.
+    }
     pub fn can_see(&mut self, entity_pos: glam::Vec2, point: glam::Vec2) -> bool {
         //  Generate the view area
         //  https://cdn.discordapp.com/attachments/406461353537175573/880003880681869342/unknown.png
@@ -396,6 +684,49 @@ impl Brain {
     }
 }
 
+impl MonsterType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MonsterType::TestBot => "Test bot",
+            MonsterType::MossCrawler => "Moss crawler",
+            MonsterType::Drowned => "Drowned",
+            MonsterType::CrystalGolem => "Crystal golem",
+            MonsterType::MagmaImp => "Magma imp",
+            MonsterType::RustyCrab => "Rusty crab",
+        }
+    }
+    pub fn max_hp(&self) -> i32 {
+        match self {
+            MonsterType::TestBot => 100,
+            MonsterType::MossCrawler => 70,
+            MonsterType::Drowned => 110,
+            MonsterType::CrystalGolem => 200,
+            MonsterType::MagmaImp => 80,
+            MonsterType::RustyCrab => 140,
+        }
+    }
+    pub fn speed(&self) -> f32 {
+        match self {
+            MonsterType::TestBot => TEST_BOT_SPEED,
+            MonsterType::MossCrawler => 200.,
+            MonsterType::Drowned => 180.,
+            MonsterType::CrystalGolem => 150.,
+            MonsterType::MagmaImp => 340.,
+            MonsterType::RustyCrab => 280.,
+        }
+    }
+    pub fn color(&self) -> ggez::graphics::Color {
+        match self {
+            MonsterType::TestBot => ggez::graphics::Color::from_rgb(150, 150, 150),
+            MonsterType::MossCrawler => ggez::graphics::Color::from_rgb(70, 140, 50),
+            MonsterType::Drowned => ggez::graphics::Color::from_rgb(60, 90, 150),
+            MonsterType::CrystalGolem => ggez::graphics::Color::from_rgb(140, 220, 255),
+            MonsterType::MagmaImp => ggez::graphics::Color::from_rgb(230, 80, 20),
+            MonsterType::RustyCrab => ggez::graphics::Color::from_rgb(170, 80, 40),
+        }
+    }
+}
+
 impl TestBot {
     pub fn new(x: f32, y: f32, id: i32, mut brain: Brain) -> Self {
         let hitbox = ggez::graphics::Rect::new(x, y, 50., 50.);
@@ -404,16 +735,27 @@ impl TestBot {
         // println!("iq: {}", brain.iq);
         TestBot {
             id: id,
+            kind: MonsterType::TestBot,
             hp: 100,
             name: "monster_name".to_string(),
             hitbox: hitbox,
             speed: TEST_BOT_SPEED,
             los: los,
             brain: brain,
+            status: status::StatusEffects::default(),
+            animator: sprite::Animator::new(),
         }
     }
     pub fn take_damages(&mut self, damage: i32) {
         self.hp -= damage;
+        self.animator.hurt();
+    }
+    /// Turns toward the position, and walks there if there's a way
+    pub fn investigate(&mut self, pos: glam::Vec2, path: physics::PathFindingResult) {
+        self.los.angle = physics::two_points_angle(glam::Vec2::from(self.hitbox.center()), pos);
+        if let physics::PathFindingResult::Ok(path) = path {
+            self.brain.wandering_path = path;
+        }
     }
     pub fn is_dead(&self) -> bool {
         if self.hp < 1 {
@@ -440,7 +782,11 @@ impl TestBot {
             .can_see(glam::Vec2::from(self.hitbox.center()), player_pos);
     }
 
-    pub fn update_movements(&mut self, dt: f32, bloc_list: &Vec<bloc::Bloc>) {
+    pub fn update_movements<'a, I: IntoIterator<Item = &'a bloc::Bloc>>(
+        &mut self,
+        dt: f32,
+        bloc_list: I,
+    ) {
         if !self.brain.wandering_path.is_empty() {
             let desired_position = self.brain.wandering_path[0];
 
@@ -453,7 +799,7 @@ impl TestBot {
 
             direction = physics::normalize_point(direction);
 
-            let mut speed = TEST_BOT_SPEED * dt;
+            let mut speed = self.speed * dt * self.status.speed_mult();
 
             let distance_to_desired_position =
                 physics::RayCasting::get_distance(glam::Vec2::from(my_pos), desired_position);
@@ -468,10 +814,10 @@ impl TestBot {
             let new_hitbox = physics::CheckCollision::world_collision(
                 self.hitbox,
                 glam::Vec2::new(delta_pos.x, delta_pos.y),
-                &bloc_list,
+                bloc_list,
             );
 
-            if self.hitbox == new_hitbox {
+            if self.hitbox == new_hitbox && !self.status.is_stunned() {
                 // println!("DOOOOR STUCK, id: {}", self.id);
                 self.brain.wandering_path = Vec::new();
             } else {
@@ -487,6 +833,15 @@ impl TestBot {
                 self.brain.wandering_path.remove(0);
             }
         };
+
+        let state = if self.brain.see_something {
+            sprite::AnimationKind::Attack
+        } else if !self.brain.wandering_path.is_empty() {
+            sprite::AnimationKind::Walk
+        } else {
+            sprite::AnimationKind::Idle
+        };
+        self.animator.update(dt, state);
     }
 }
 
@@ -521,4 +876,172 @@ impl physics::EntityTrait for Monster {
             Monster::TestBot(tb) => tb.take_damages(damage),
         }
     }
+    fn status_effects(&mut self) -> Option<&mut status::StatusEffects> {
+        match self {
+            Monster::TestBot(tb) => Some(&mut tb.status),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen;
+
+    #[test]
+    fn bots_spawn_on_ground_of_a_non_square_depth() {
+        // Much wider than high, a column index used as a row goes out of the grid
+        let config: mapgen::GenerationConfig = toml::from_str(
+            "[[depths]]
+            min = 4
+            width = 120
+            height = 35",
+        )
+        .unwrap();
+        let config = config.for_depth(4);
+        assert_eq!((config.width, config.height), (120, 35));
+
+        let tile_size = 60.;
+        let ghost_tiles = map::ghost_tiles();
+        for seed in 0..8 {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            let generator = mapgen::CellularCaves {
+                wall_chance: 0.45,
+                iterations: 5,
+            };
+            let grid = mapgen::generate(&generator, &config, &mut rng);
+            let mut monster_manager = MonsterManager::new(seed);
+            let mut id_manager = id::IdManager::new();
+            for _ in 0..20 {
+                monster_manager.new_bot(
+                    MonsterType::TestBot,
+                    &mut id_manager,
+                    (grid.clone(), ghost_tiles.clone(), tile_size),
+                );
+            }
+            for monster in monster_manager.monster_list.iter() {
+                let Monster::TestBot(tb) = monster;
+                let (x, y) = (
+                    (tb.hitbox.x / tile_size) as usize,
+                    (tb.hitbox.y / tile_size) as usize,
+                );
+                assert!(
+                    ghost_tiles.contains(&(grid[y][x] as f32)),
+                    "seed {}: bot spawned in a wall at {},{}",
+                    seed,
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    /// A bot with average ears, its center on the tile
+    fn bot_at(tile_x: f32, tile_y: f32, tile_size: f32) -> Monster {
+        let mut brain = Brain::new(&mut rand::rngs::StdRng::seed_from_u64(0));
+        brain.iq = 100;
+        let center = glam::Vec2::new(tile_x + 0.5, tile_y + 0.5) * tile_size;
+        Monster::TestBot(TestBot::new(center.x - 25., center.y - 25., 0, brain))
+    }
+
+    fn wandering_path(monster: &Monster) -> &Vec<glam::Vec2> {
+        let Monster::TestBot(tb) = monster;
+        &tb.brain.wandering_path
+    }
+
+    #[test]
+    fn bots_walk_to_what_they_hear() {
+        let tile_size = 60.;
+        let mut monster_manager = MonsterManager::new(0);
+        monster_manager.monster_list.push(bot_at(5., 0., tile_size)); // in the gunshot radius
+        monster_manager
+            .monster_list
+            .push(bot_at(30., 0., tile_size)); // far out of it
+        let shot_at = glam::Vec2::new(0.5, 0.5) * tile_size;
+        let noise = hearing::Noise::new(hearing::NoiseKind::Gunshot, shot_at, 1.);
+
+        monster_manager.hear(
+            &[noise],
+            tile_size,
+            |_, _| false,
+            |_, to| physics::PathFindingResult::Ok(vec![to]),
+        );
+        assert_eq!(
+            wandering_path(&monster_manager.monster_list[0]),
+            &vec![shot_at]
+        );
+        assert!(wandering_path(&monster_manager.monster_list[1]).is_empty());
+        let Monster::TestBot(tb) = &monster_manager.monster_list[0];
+        assert_eq!(
+            tb.los.angle,
+            physics::two_points_angle(glam::Vec2::from(tb.hitbox.center()), shot_at)
+        );
+    }
+
+    #[test]
+    fn walls_can_muffle_a_noise_out_of_hearing() {
+        let tile_size = 60.;
+        let mut monster_manager = MonsterManager::new(0);
+        monster_manager.monster_list.push(bot_at(8., 0., tile_size));
+        let noise = hearing::Noise::new(
+            hearing::NoiseKind::Gunshot,
+            glam::Vec2::new(0.5, 0.5) * tile_size,
+            1.,
+        );
+
+        monster_manager.hear(
+            &[noise],
+            tile_size,
+            |x, _| x == 4,
+            |_, to| physics::PathFindingResult::Ok(vec![to]),
+        );
+        assert!(wandering_path(&monster_manager.monster_list[0]).is_empty());
+    }
+
+    #[test]
+    fn bots_go_to_the_last_noise_they_heard() {
+        let tile_size = 60.;
+        let mut monster_manager = MonsterManager::new(0);
+        monster_manager.monster_list.push(bot_at(5., 5., tile_size));
+        let first = glam::Vec2::new(2.5, 5.5) * tile_size;
+        let last = glam::Vec2::new(8.5, 5.5) * tile_size;
+        let noises = [
+            hearing::Noise::new(hearing::NoiseKind::Gunshot, first, 1.),
+            hearing::Noise::new(hearing::NoiseKind::Gunshot, last, 1.),
+        ];
+
+        monster_manager.hear(
+            &noises,
+            tile_size,
+            |_, _| false,
+            |_, to| physics::PathFindingResult::Ok(vec![to]),
+        );
+        assert_eq!(
+            wandering_path(&monster_manager.monster_list[0]),
+            &vec![last]
+        );
+    }
+
+    #[test]
+    fn bots_seeing_something_dont_get_distracted() {
+        let tile_size = 60.;
+        let mut monster_manager = MonsterManager::new(0);
+        let mut bot = bot_at(2., 0., tile_size);
+        let Monster::TestBot(tb) = &mut bot;
+        tb.brain.see_something = true;
+        monster_manager.monster_list.push(bot);
+        let noise = hearing::Noise::new(
+            hearing::NoiseKind::Explosion,
+            glam::Vec2::new(0.5, 0.5) * tile_size,
+            1.,
+        );
+
+        monster_manager.hear(
+            &[noise],
+            tile_size,
+            |_, _| false,
+            |_, to| physics::PathFindingResult::Ok(vec![to]),
+        );
+        assert!(wandering_path(&monster_manager.monster_list[0]).is_empty());
+    }
 }
@@ -3,7 +3,7 @@ use ggez::mint;
 use glam;
 use std;
 
-use crate::{bloc, id, monster, player};
+use crate::{bloc, id, monster, player, status};
 
 pub trait EntityTrait {
     fn get_hitbox(&self) -> ggez::graphics::Rect;
@@ -12,6 +12,23 @@ pub trait EntityTrait {
     fn rotated_hitbox(&self) -> Vec<glam::Vec2>;
     fn id(&self) -> i32;
     fn take_damage(&mut self, damage: i32);
+    fn status_effects(&mut self) -> Option<&mut status::StatusEffects> {
+        None
+    }
+    fn apply_status(&mut self, kind: status::StatusKind) {
+        if let Some(effects) = self.status_effects() {
+            effects.apply(kind);
+        }
+    }
+    fn update_status(&mut self, dt: f32) {
+        let damage = match self.status_effects() {
+            Some(effects) => effects.update(dt),
+            None => 0,
+        };
+        if damage > 0 {
+            self.take_damage(damage);
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -49,7 +66,10 @@ pub struct LOS {
     pub result: RayCastResult,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Ord)]
+const CLOSED_DOOR: i32 = 27;
+const DOOR_EXTRA_COST: i32 = 40; // opening a door takes a bit of time
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 struct PathFindingNode {
     id: i32,
     transparent: bool,
@@ -57,6 +77,7 @@ struct PathFindingNode {
     parent_position: (i32, i32),
     g_cost: i32,
     h_cost: i32,
+    extra_cost: i32,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -261,10 +282,11 @@ impl CheckCollision {
         // };
         collision_result
     }
-    pub fn world_collision(
+    /// Takes any list of blocs, so the open world can only give the chunks around the entity
+    pub fn world_collision<'a, I: IntoIterator<Item = &'a bloc::Bloc>>(
         entity_hitbox: ggez::graphics::Rect,
         delta_pos: glam::Vec2,
-        bloc_list: &Vec<bloc::Bloc>,
+        bloc_list: I,
     ) -> ggez::graphics::Rect {
         let mut next_pos = ggez::graphics::Rect::new(
             entity_hitbox.x + delta_pos.x,
@@ -273,14 +295,7 @@ impl CheckCollision {
             entity_hitbox.h,
         );
         for bloc in bloc_list {
-            let tile = match bloc {
-                bloc::Bloc::Air(a) => &a.tile,
-                bloc::Bloc::Wall(w) => &w.tile,
-                bloc::Bloc::Water(w) => &w.tile,
-                bloc::Bloc::Lava(l) => &l.tile,
-                bloc::Bloc::Spawn(s) => &s.tile,
-                bloc::Bloc::End(e) => &e.tile,
-            };
+            let tile = bloc.tile();
 
             if !tile.transparent {
                 let dx = ggez::graphics::Rect::new(
@@ -383,9 +398,9 @@ impl RayCasting {
         result
     }
 
-    pub fn ray_cast<E: EntityTrait>(
+    pub fn ray_cast<'a, E: EntityTrait + 'a, I: IntoIterator<Item = &'a E>>(
         line_of_sight: (glam::Vec2, glam::Vec2),
-        entity_list: &Vec<E>,
+        entity_list: I,
     ) -> ((glam::Vec2, glam::Vec2), Option<usize>, bool) {
         // let mut r_lists_index = None;
         let mut r_item_index = None;
@@ -394,7 +409,7 @@ impl RayCasting {
         let los_startpoint = glam::Vec2::new(line_of_sight.0.x, line_of_sight.0.y);
         let mut los_endpoint = glam::Vec2::new(line_of_sight.1.x, line_of_sight.1.y);
 
-        for (index, entity) in entity_list.iter().enumerate() {
+        for (index, entity) in entity_list.into_iter().enumerate() {
             if !entity.ray_cast_bypass() {
                 // let lines
                 let interaction_points0 = RayCasting::check_line_rect_intersection_points(
@@ -418,9 +433,38 @@ impl RayCasting {
         }
         ((los_startpoint, los_endpoint.clone()), r_item_index, is_hit)
     }
-    pub fn ray_cast_tile_monster(
+    pub fn ray_cast_all<E: EntityTrait>(
+        line_of_sight: (glam::Vec2, glam::Vec2),
+        entity_list: &[E],
+    ) -> Vec<(usize, f32)> {
+        // Every entity crossed by the line, with its distance to the start of the line
+        let mut hits: Vec<(usize, f32)> = Vec::new();
+
+        for (index, entity) in entity_list.iter().enumerate() {
+            if entity.ray_cast_bypass() {
+                continue;
+            }
+            let interaction_points = RayCasting::check_line_rect_intersection_points(
+                line_of_sight,
+                entity.rotated_hitbox(),
+            );
+            let closest = interaction_points
+                .iter()
+                .map(|pt| RayCasting::get_distance(line_of_sight.0, *pt))
+                .fold(None, |min: Option<f32>, d| match min {
+                    Some(m) if m < d => Some(m),
+                    _ => Some(d),
+                });
+            if let Some(dist) = closest {
+                hits.push((index, dist));
+            }
+        }
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        hits
+    }
+    pub fn ray_cast_tile_monster<'a, I: IntoIterator<Item = &'a bloc::Bloc>>(
         los: (glam::Vec2, glam::Vec2),
-        blocs: &Vec<bloc::Bloc>,
+        blocs: I,
         monsters: &Vec<monster::Monster>,
     ) -> RayCastResult {
         // let malist: Vec<RayCastBlocType> = vec![RayCastBlocType::Wall, RayCastBlocType::Other];
@@ -465,9 +509,9 @@ impl RayCasting {
             result
         }
     }
-    pub fn ray_cast_tile_player(
+    pub fn ray_cast_tile_player<'a, I: IntoIterator<Item = &'a bloc::Bloc>>(
         los: (glam::Vec2, glam::Vec2),
-        blocs: &Vec<bloc::Bloc>,
+        blocs: I,
         players: &Vec<player::Player>,
     ) -> RayCastResult {
         // let malist: Vec<RayCastBlocType> = vec![RayCastBlocType::Wall, RayCastBlocType::Other];
@@ -528,14 +572,11 @@ impl PathFinding {
         for (y, row) in raw_map.iter().enumerate() {
             let mut grid_new_row: Vec<PathFindingNode> = Vec::new();
             for (x, tile) in row.iter().enumerate() {
-                let transparent: bool;
-                if transparent_tiles.contains(&(*tile as f32)) {
-                    transparent = true;
-                } else {
-                    transparent = false;
-                }
+                // Closed doors can be walked through, they only take longer
+                let transparent =
+                    transparent_tiles.contains(&(*tile as f32)) || *tile == CLOSED_DOOR;
 
-                let new_node = PathFindingNode::new(
+                let mut new_node = PathFindingNode::new(
                     id_manager.get_new_id(),
                     transparent,
                     (x as i32, y as i32),
@@ -550,6 +591,9 @@ impl PathFinding {
                         (desired_position.x as i32, desired_position.y as i32),
                     ),
                 );
+                if *tile == CLOSED_DOOR {
+                    new_node.extra_cost = DOOR_EXTRA_COST;
+                }
 
                 grid_new_row.push(new_node);
             }
@@ -576,50 +620,51 @@ impl PathFinding {
         let mut grid =
             PathFinding::construct_pf_grid(map_infos.clone(), entity_position, desired_position);
 
-        let grid_size = glam::Vec2::new((grid.len() as i32) as f32, grid[0].len() as f32);
+        let grid_size = glam::Vec2::new(grid[0].len() as f32, grid.len() as f32);
 
         let start_node =
             grid[entity_position.y as usize - 1][entity_position.x as usize - 1].clone();
 
-        let mut target_node =
-            grid[desired_position.y as usize][desired_position.x as usize].clone();
+        let target_node = grid[desired_position.y as usize][desired_position.x as usize].clone();
 
         let mut found = false;
 
-        // actual pathfinding
+        // actual pathfinding, the heap holds every node reached so far ordered by g + h
         if target_node.transparent {
             let mut to_see_heap: std::collections::BinaryHeap<PathFindingNode> =
                 std::collections::BinaryHeap::new();
-            let mut to_see_id: Vec<i32> = Vec::new();
-
-            to_see_heap.push(start_node.clone());
-            to_see_id.push(start_node.id);
-
-            let mut seen_id: Vec<i32> = Vec::new();
+            let mut reached_id: std::collections::HashSet<i32> = std::collections::HashSet::new();
+            let mut seen_id: std::collections::HashSet<i32> = std::collections::HashSet::new();
+
+            {
+                let start =
+                    &mut grid[start_node.position.1 as usize][start_node.position.0 as usize];
+                start.g_cost = 0;
+                start.h_cost = PathFinding::get_distance(start.position, target_node.position);
+                to_see_heap.push(start.clone());
+                reached_id.insert(start.id);
+            }
 
             while let Some(current_node) = to_see_heap.pop() {
-                let current_index_in_to_see = to_see_id
-                    .iter()
-                    .position(|id| *id == current_node.id)
-                    .unwrap();
-                to_see_id.swap_remove(current_index_in_to_see);
-                seen_id.push(current_node.id);
+                // A node can be pushed again with a lower cost, the older entry is left behind
+                if !seen_id.insert(current_node.id) {
+                    continue;
+                }
 
                 if current_node.id == target_node.id {
-                    target_node.parent_position = current_node.position;
                     found = true;
                     break;
                 }
                 for neighbour in PathFinding::get_neighbours(current_node.position, grid_size) {
-                    let mut neighbour_node =
-                        grid[neighbour.1 as usize][neighbour.0 as usize].clone();
+                    let neighbour_node = &mut grid[neighbour.1 as usize][neighbour.0 as usize];
                     if !neighbour_node.transparent || seen_id.contains(&neighbour_node.id) {
                         continue;
                     }
-                    let new_move_cost =
-                        PathFinding::get_distance(start_node.position, neighbour_node.position);
+                    let new_move_cost = current_node.g_cost
+                        + PathFinding::get_distance(current_node.position, neighbour_node.position)
+                        + neighbour_node.extra_cost;
                     if new_move_cost < neighbour_node.g_cost
-                        || !to_see_id.contains(&neighbour_node.id)
+                        || !reached_id.contains(&neighbour_node.id)
                     {
                         neighbour_node.g_cost = new_move_cost;
                         neighbour_node.h_cost = PathFinding::get_distance(
@@ -627,13 +672,8 @@ impl PathFinding {
                             neighbour_node.position,
                         );
                         neighbour_node.parent_position = current_node.position;
-
-                        if !to_see_id.contains(&neighbour_node.id) {
-                            grid[neighbour_node.position.1 as usize]
-                                [neighbour_node.position.0 as usize] = current_node.clone();
-                            to_see_id.push(neighbour_node.id);
-                            to_see_heap.push(neighbour_node);
-                        }
+                        reached_id.insert(neighbour_node.id);
+                        to_see_heap.push(neighbour_node.clone());
                     }
                 }
             }
@@ -642,7 +682,8 @@ impl PathFinding {
         }
         if found {
             let mut backtrack: Vec<glam::Vec2> = Vec::new();
-            let mut current = target_node.clone();
+            let mut current =
+                grid[target_node.position.1 as usize][target_node.position.0 as usize].clone();
 
             while current.parent_position != (-1, -1) {
                 backtrack.push(glam::Vec2::new(
@@ -717,13 +758,24 @@ impl PathFindingNode {
             parent_position: (-1, -1),
             g_cost: g_cost,
             h_cost: h_cost,
+            extra_cost: 0,
         }
     }
 }
 
+// Reversed so the BinaryHeap pops the cheapest node first
+impl Ord for PathFindingNode {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (other.g_cost + other.h_cost)
+            .cmp(&(self.g_cost + self.h_cost))
+            .then_with(|| other.h_cost.cmp(&self.h_cost))
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
 impl PartialOrd for PathFindingNode {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        other.h_cost.partial_cmp(&self.h_cost)
+        Some(self.cmp(other))
     }
 }
 
@@ -735,3 +787,59 @@ impl Circle {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The start is given one tile down and right of where the search begins
+    fn path(grid: Vec<Vec<i32>>, start: (f32, f32), end: (f32, f32)) -> Vec<(usize, usize)> {
+        match PathFinding::astar(
+            glam::Vec2::new(start.0 + 1., start.1 + 1.),
+            glam::Vec2::new(end.0, end.1),
+            (grid, vec![-1.], 1.),
+        ) {
+            PathFindingResult::Ok(path) => {
+                path.iter().map(|p| (p.x as usize, p.y as usize)).collect()
+            }
+            PathFindingResult::Fail => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn finds_the_shortest_way_around_a_wall() {
+        let grid = vec![
+            vec![-1, -1, -1, -1, -1],
+            vec![-1, 4, 4, 4, -1],
+            vec![-1, -1, -1, 4, -1],
+        ];
+        let found = path(grid, (0., 2.), (4., 2.));
+        assert_eq!(found.len(), 8);
+        assert_eq!(found.last(), Some(&(4, 2)));
+    }
+
+    #[test]
+    fn a_closed_door_costs_more_than_a_short_detour() {
+        let grid = vec![
+            vec![-1, -1, -1],
+            vec![-1, CLOSED_DOOR, -1],
+            vec![-1, -1, -1],
+        ];
+        assert!(!path(grid, (1., 0.), (1., 2.)).contains(&(1, 1)));
+    }
+
+    #[test]
+    fn a_closed_door_is_cheaper_than_a_long_detour() {
+        let mut grid = vec![vec![-1; 9]; 4];
+        grid[1] = vec![4; 9];
+        grid[1][0] = -1;
+        grid[1][4] = CLOSED_DOOR;
+        assert!(path(grid, (4., 0.), (4., 2.)).contains(&(4, 1)));
+    }
+
+    #[test]
+    fn fails_when_walled_off() {
+        let grid = vec![vec![-1, 4, -1]];
+        assert!(path(grid, (0., 0.), (2., 0.)).is_empty());
+    }
+}
@@ -1,10 +1,15 @@
 use ggez;
-use ggez::audio::SoundSource;
 
-use crate::{bloc, id, input, monster, physics, weapon};
+use crate::{bloc, hearing, id, input, item, map, monster, physics, sprite, status, weapon};
 
 const PLAYER_SPEED: f32 = 400.;
-const PLAYER_BASE_HP: i32 = 100;
+pub const PLAYER_BASE_HP: i32 = 100;
+const SNEAK_SPEED_MULT: f32 = 0.4;
+const STRIDE_LENGTH: f32 = 50.; // in pixels, walked between two footsteps
+const STICK_AIM_DISTANCE: f32 = 300.; // in pixels, how far the aim stick points
+const AIM_ASSIST_RANGE: f32 = 800.;
+const AIM_ASSIST_ANGLE: f32 = 0.26; // in radians, about 15 degrees on each side of the aim
+const AIM_ASSIST_STRENGTH: f32 = 0.6; // part of the gap to the monster that's closed
 
 pub struct Player {
     pub id: i32,
@@ -15,19 +20,17 @@ pub struct Player {
     pub speed: f32,
     pub los: physics::LOS,
     pub inventory: weapon::WeaponInventory,
-    pub shot_sound: ggez::audio::Source,
+    pub items: item::ItemInventory,
+    pub keys: Vec<i32>,
+    pub status: status::StatusEffects,
+    pub animator: sprite::Animator,
+    pub sounds: Vec<(&'static str, glam::Vec2)>, // played since the last frame, and where
+    pub noises: Vec<hearing::Noise>, // made since the last frame, for the monsters to hear
+    stride: f32,
 }
 
 impl Player {
-    pub fn new(
-        x: f32,
-        y: f32,
-        w: f32,
-        h: f32,
-        id_manager: &mut id::IdManager,
-        ctx: &mut ggez::Context,
-    ) -> Self {
-        let shot_sound = ggez::audio::Source::new(ctx, "/sounds/pistol.wav").unwrap();
+    pub fn new(x: f32, y: f32, w: f32, h: f32, id_manager: &mut id::IdManager) -> Self {
         Player {
             id: id_manager.get_new_id(),
             hp: PLAYER_BASE_HP,
@@ -37,71 +40,67 @@ impl Player {
             speed: PLAYER_SPEED,
             los: physics::LOS::default(),
             inventory: weapon::WeaponInventory::new(id_manager),
-            shot_sound,
+            items: item::ItemInventory::new(),
+            keys: Vec::new(),
+            status: status::StatusEffects::default(),
+            animator: sprite::Animator::new(),
+            sounds: Vec::new(),
+            noises: Vec::new(),
+            stride: 0.,
         }
     }
-    pub fn update_movements(
+    pub fn update_movements<'a, I: IntoIterator<Item = &'a bloc::Bloc> + Clone>(
         &mut self,
-        bloclist: &mut Vec<bloc::Bloc>,
+        bloclist: I,
         dt: f32,
         id_manager: &mut id::IdManager,
         monster_manager: &mut monster::MonsterManager,
-        ctx: &mut ggez::Context,
+        difficulty: u32,
     ) {
-        let mut dir = glam::Vec2::ZERO;
         let mut delta_pos = glam::Vec2::ZERO;
-        if self.inputs.up {
-            dir.y -= 1.;
-        }
-        if self.inputs.down {
-            dir.y += 1.;
-        }
-        if self.inputs.left {
-            dir.x -= 1.;
+        let dir = self.inputs.movement(); // shorter when the stick is barely tilted
+        let mut speed = self.speed * self.status.speed_mult();
+        if self.inputs.held(input::Action::Sneak) {
+            speed *= SNEAK_SPEED_MULT;
         }
-        if self.inputs.right {
-            dir.x += 1.;
+        delta_pos.x += dir.x * (speed * dt);
+        delta_pos.y += dir.y * (speed * dt);
+        let new_hitbox =
+            physics::CheckCollision::world_collision(self.hitbox, delta_pos, bloclist.clone());
+        // The faster you go, the louder the steps
+        self.stride +=
+            glam::Vec2::from(new_hitbox.point()).distance(glam::Vec2::from(self.hitbox.point()));
+        if self.stride >= STRIDE_LENGTH {
+            self.stride = 0.;
+            self.noises.push(hearing::Noise::new(
+                hearing::NoiseKind::Footstep,
+                glam::Vec2::from(new_hitbox.center()),
+                speed * dir.length() / PLAYER_SPEED,
+            ));
         }
-        dir = physics::normalize_point(dir);
-        delta_pos.x += dir.x * (self.speed * dt);
-        delta_pos.y += dir.y * (self.speed * dt);
-        self.hitbox = physics::CheckCollision::world_collision(self.hitbox, delta_pos, bloclist);
+        let state = if new_hitbox != self.hitbox {
+            sprite::AnimationKind::Walk
+        } else {
+            sprite::AnimationKind::Idle
+        };
+        self.hitbox = new_hitbox;
+        self.animator.update(dt, state);
 
-        if self.inputs.mouse_left || self.inputs.controler_south {
-            self.shoot(id_manager, monster_manager, ctx);
+        if self.inputs.held(input::Action::Fire) && !self.status.is_stunned() {
+            for drop in self.shoot(bloclist, id_manager, monster_manager, difficulty) {
+                self.pick_up(drop);
+            }
         }
     }
-    pub fn update_los(
+    pub fn update_los<'a, I: IntoIterator<Item = &'a bloc::Bloc>>(
         &mut self,
-        camera_scroll: glam::Vec2,
-        bloclist: &mut Vec<bloc::Bloc>,
+        aim: glam::Vec2, // in the world
+        bloclist: I,
         monster_list: &mut Vec<monster::Monster>,
     ) {
         // Line of sight
-        let hitbox_center = (
-            -camera_scroll.x + self.hitbox.center().x,
-            -camera_scroll.y + self.hitbox.center().y,
-        );
-        self.los.angle = physics::two_points_angle(
-            glam::Vec2::from(hitbox_center),
-            glam::Vec2::from(self.inputs.pointing),
-        );
-        let weapon_range = weapon::WeaponTrait::get_range(
-            &self.inventory.weapon_list[self.inventory.selected_index],
-        );
-
-        let player_center = glam::Vec2::new(self.hitbox.center().x, self.hitbox.center().y);
-
-        let rotated_line_end_point: glam::Vec2 = physics::rotate_line(
-            player_center,
-            glam::Vec2::new(
-                self.hitbox.x + weapon_range + self.hitbox.w / 2. + self.hitbox.w,
-                self.hitbox.y + self.hitbox.h / 2.,
-            ),
-            self.los.angle,
-        );
-
-        let line_of_sight: (glam::Vec2, glam::Vec2) = (player_center, rotated_line_end_point);
+        self.los.angle = physics::two_points_angle(glam::Vec2::from(self.hitbox.center()), aim);
+        let line_of_sight = self.aim_line();
 
         let result =
             physics::RayCasting::ray_cast_tile_monster(line_of_sight, bloclist, monster_list);
@@ -117,35 +116,119 @@ impl Player {
         }
         self.los.result = result;
     }
-    pub fn draw(&self, ctx: &mut ggez::Context, draw_offset: glam::Vec2) -> ggez::GameResult {
-        let player_center =
-            glam::Vec2::new(self.hitbox.center().x, self.hitbox.center().y) + draw_offset;
+    /// Where the aim stick points in the world, straight from the player. The aim stays where it
+    /// was when the stick is let go.
+    pub fn stick_aim<F: Fn(i32, i32) -> bool>(
+        &self,
+        monster_list: &[monster::Monster],
+        tile_size: f32,
+        blocks_sight: F,
+    ) -> glam::Vec2 {
+        let center = glam::Vec2::from(self.hitbox.center());
+        let stick = self.inputs.aim();
+        let mut angle = if stick == glam::Vec2::ZERO {
+            self.los.angle
+        } else {
+            stick.y.atan2(stick.x)
+        };
+        if self.inputs.sticks.aim_assist && stick != glam::Vec2::ZERO {
+            angle += AIM_ASSIST_STRENGTH
+                * Self::assist_gap(center, angle, monster_list, tile_size, blocks_sight);
+        }
+        center + glam::Vec2::new(angle.cos(), angle.sin()) * STICK_AIM_DISTANCE
+    }
+    /// The angle to the monster in sight the closest to the aim, 0 when there's none
+    fn assist_gap<F: Fn(i32, i32) -> bool>(
+        center: glam::Vec2,
+        angle: f32,
+        monster_list: &[monster::Monster],
+        tile_size: f32,
+        blocks_sight: F,
+    ) -> f32 {
+        let mut best: Option<f32> = None;
+        for monster in monster_list {
+            let target = glam::Vec2::from(physics::EntityTrait::get_hitbox(monster).center());
+            let distance = center.distance(target);
+            if distance > AIM_ASSIST_RANGE {
+                continue;
+            }
+            let gap = (physics::two_points_angle(center, target) - angle + std::f32::consts::PI)
+                .rem_euclid(std::f32::consts::PI * 2.)
+                - std::f32::consts::PI;
+            if gap.abs() > AIM_ASSIST_ANGLE || best.is_some_and(|best| best.abs() <= gap.abs()) {
+                continue;
+            }
+            // Half a tile at a time, a wall on the way hides it
+            let steps = (distance / tile_size * 2.).ceil().max(1.) as usize;
+            let hidden = (1..steps).any(|step| {
+                let tile = center.lerp(target, step as f32 / steps as f32) / tile_size;
+                blocks_sight(tile.x.floor() as i32, tile.y.floor() as i32)
+            });
+            if !hidden {
+                best = Some(gap);
+            }
+        }
+        best.unwrap_or(0.)
+    }
+    pub fn aim_line(&self) -> (glam::Vec2, glam::Vec2) {
+        let weapon_range = match self.inventory.selected() {
+            Some(w) => weapon::WeaponTrait::get_range(w),
+            None => 0.,
+        };
 
-        // Used to see the orientation, code from from Heto's game
-        let mut hitbox_mesh = ggez::graphics::MeshBuilder::new();
-        let mut los_mesh = ggez::graphics::MeshBuilder::new();
+        let player_center = glam::Vec2::new(self.hitbox.center().x, self.hitbox.center().y);
 
-        let gun_rect =
-            ggez::graphics::Rect::new(self.hitbox.w / 2.0 - 10., self.hitbox.h / 2.0, 30.0, 10.0);
+        let rotated_line_end_point: glam::Vec2 = physics::rotate_line(
+            player_center,
+            glam::Vec2::new(
+                self.hitbox.x + weapon_range + self.hitbox.w / 2. + self.hitbox.w,
+                self.hitbox.y + self.hitbox.h / 2.,
+            ),
+            self.los.angle,
+        );
 
-        hitbox_mesh.rectangle(
-            ggez::graphics::DrawMode::stroke(1.0),
-            gun_rect,
-            ggez::graphics::Color::WHITE,
-        )?;
+        (player_center, rotated_line_end_point)
+    }
+    pub fn draw(
+        &self,
+        ctx: &mut ggez::Context,
+        draw_offset: glam::Vec2,
+        sprites: &mut sprite::SpriteAtlas,
+    ) -> ggez::GameResult {
+        let center = glam::Vec2::from(self.hitbox.center());
+        let size = self.hitbox.w * 1.4; // the sprites have some room around the body
 
-        // End of debug code
+        sprites.add(
+            &self.animator.sprite("player"),
+            self.animator.time,
+            center,
+            size,
+            self.los.angle,
+            ggez::graphics::Color::WHITE,
+        );
+        // The weapon is held in front, and fires along with the player
+        if let Some(weapon) = self.inventory.selected() {
+            let hand = physics::rotate_line(
+                center,
+                center + glam::Vec2::new(self.hitbox.w / 2., 0.),
+                self.los.angle,
+            );
+            let animation = match self.animator.kind {
+                sprite::AnimationKind::Attack => "attack",
+                _ => "idle",
+            };
+            sprites.add(
+                &format!("{}.{}", weapon.archetype.sprite(), animation),
+                self.animator.time,
+                hand,
+                size,
+                self.los.angle,
+                ggez::graphics::Color::WHITE,
+            );
+        }
+        sprites.draw(ctx, draw_offset)?;
 
-        hitbox_mesh.rectangle(
-            ggez::graphics::DrawMode::stroke(1.0),
-            ggez::graphics::Rect::new(
-                -self.hitbox.w / 2.0,
-                -self.hitbox.h / 2.0,
-                self.hitbox.w,
-                self.hitbox.h,
-            ),
-            ggez::graphics::Color::RED,
-        )?;
+        let mut los_mesh = ggez::graphics::MeshBuilder::new();
         los_mesh.circle(
             ggez::graphics::DrawMode::stroke(10.),
             self.los.end_point,
@@ -153,14 +236,6 @@ impl Player {
             0.1,
             ggez::graphics::Color::from_rgb(0, 100, 100),
         )?;
-
-        let builded_hitbox_mesh = hitbox_mesh.build(ctx)?;
-        ggez::graphics::draw(
-            ctx,
-            &builded_hitbox_mesh,
-            (player_center, self.los.angle, ggez::graphics::Color::WHITE),
-        )?;
-
         let builded_los_mesh = los_mesh.build(ctx)?;
         ggez::graphics::draw(
             ctx,
@@ -175,41 +250,136 @@ impl Player {
         println!("Player's hp: {}", self.hp);
         if self.hp < 1 {
             println!("Player is supposed to be dead");
+            self.animator.die();
             true
         } else {
+            self.animator.hurt();
             false
         }
     }
-    pub fn shoot(
+    pub fn pick_up(&mut self, drop: weapon::ObjectDrop) {
+        match drop {
+            // A full inventory leaves the new weapon behind
+            weapon::ObjectDrop::Ok(new_weapon) => {
+                self.inventory.add(new_weapon);
+            }
+            weapon::ObjectDrop::Consumable(consumable, count) => self.items.add(consumable, count),
+            weapon::ObjectDrop::Key(key) => self.keys.push(key),
+            weapon::ObjectDrop::None => {}
+        }
+    }
+    /// Uses the consumable bound to the given hotbar slot, returns true if one was consumed.
+    /// Without a map only the potions can be used.
+    pub fn use_hotbar(&mut self, slot: usize, map: Option<&mut map::Map>) -> bool {
+        let consumable = match self.items.hotbar.get(slot) {
+            Some(Some(c)) => *c,
+            _ => return false,
+        };
+        if self.items.count(consumable) == 0 {
+            return false;
+        }
+        let used = match consumable {
+            item::Consumable::HealthPotion => {
+                if self.hp < PLAYER_BASE_HP {
+                    self.heal(item::HEALTH_POTION_HEAL);
+                    true
+                } else {
+                    false
+                }
+            }
+            item::Consumable::Torch => match map {
+                Some(map) => map.place_torch(glam::Vec2::from(self.hitbox.center())),
+                None => false,
+            },
+            item::Consumable::Bomb => match map {
+                Some(map) => {
+                    map.bombs.push(item::Bomb::new(self.los.end_point));
+                    true
+                }
+                None => false,
+            },
+        };
+        if used {
+            self.items.take(consumable);
+        }
+        used
+    }
+    pub fn heal(&mut self, amount: i32) {
+        self.hp = std::cmp::min(PLAYER_BASE_HP, self.hp + amount);
+    }
+    pub fn shoot<'a, I: IntoIterator<Item = &'a bloc::Bloc>>(
         &mut self,
+        bloclist: I,
         id_manager: &mut id::IdManager,
         monster_manager: &mut monster::MonsterManager,
-        ctx: &mut ggez::Context,
-    ) -> weapon::ObjectDrop {
-        let mut dropped_item = weapon::ObjectDrop::None;
-
-        if weapon::WeaponTrait::can_shoot(
-            &mut self.inventory.weapon_list[self.inventory.selected_index],
-        ) {
-            self.shot_sound.play(ctx).unwrap();
-            match self.los.result.clone() {
-                physics::RayCastResult::Ok(_line, object, _dist) => match object {
-                    physics::RayCastBlocType::Monster(monster_index) => {
-                        dropped_item = monster_manager.damage_monster_isdead(
-                            monster_index,
-                            weapon::WeaponTrait::get_damage(
-                                &self.inventory.weapon_list[self.inventory.selected_index],
-                            ),
-                            id_manager,
-                        );
-                    }
-                    _ => {}
-                },
-                physics::RayCastResult::Fail => {}
+        difficulty: u32,
+    ) -> Vec<weapon::ObjectDrop> {
+        let mut dropped_items: Vec<weapon::ObjectDrop> = Vec::new();
+
+        let (damage, piercing, lifesteal, burning, archetype) = match self.inventory.selected_mut()
+        {
+            Some(w) => {
+                if !weapon::WeaponTrait::can_shoot(w) {
+                    return dropped_items;
+                }
+                (
+                    weapon::WeaponTrait::get_damage(w),
+                    w.is_piercing(),
+                    w.get_lifesteal(),
+                    w.is_burning(),
+                    w.archetype,
+                )
+            }
+            None => return dropped_items,
+        };
+        if let Some(sound) = archetype.sound() {
+            self.sounds
+                .push((sound, glam::Vec2::from(self.hitbox.center())));
+        }
+        self.noises.push(hearing::Noise::new(
+            hearing::NoiseKind::Gunshot,
+            glam::Vec2::from(self.hitbox.center()),
+            archetype.loudness(),
+        ));
+        self.animator.attack();
+
+        let mut targets: Vec<usize> = Vec::new();
+        if piercing {
+            // Stop at the first wall, but hit every monster before it
+            let (wall_shot, _, _) = physics::RayCasting::ray_cast(self.aim_line(), bloclist);
+            for (monster_index, _dist) in
+                physics::RayCasting::ray_cast_all(wall_shot, &monster_manager.monster_list)
+            {
+                targets.push(monster_index);
             }
+        } else if let physics::RayCastResult::Ok(
+            _line,
+            physics::RayCastBlocType::Monster(index),
+            _dist,
+        ) = self.los.result.clone()
+        {
+            targets.push(index);
         }
 
-        dropped_item
+        // Highest index first, so the swap_remove of a dead monster doesn't move the next targets
+        targets.sort_unstable_by(|a, b| b.cmp(a));
+        for monster_index in targets {
+            if burning {
+                physics::EntityTrait::apply_status(
+                    &mut monster_manager.monster_list[monster_index],
+                    status::StatusKind::Burning,
+                );
+            }
+            dropped_items.push(monster_manager.damage_monster_isdead(
+                monster_index,
+                damage,
+                difficulty,
+                id_manager,
+            ));
+            self.heal(damage * lifesteal / 100);
+        }
+
+        dropped_items
     }
 }
 
@@ -232,4 +402,7 @@ impl physics::EntityTrait for Player {
     fn take_damage(&mut self, damage: i32) {
         self.take_damages(damage);
     }
+    fn status_effects(&mut self) -> Option<&mut status::StatusEffects> {
+        Some(&mut self.status)
+    }
 }
@@ -0,0 +1,268 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::{input, item};
+
+const VERSION: u32 = 1;
+pub const SPEEDS: [usize; 4] = [1, 2, 4, 8]; // updates of the game per frame when fast-forwarding
+
+/// Something done in a menu that changes the game, the inputs don't see it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    OpenWorld,
+    SelectWeapon(usize),
+    BindHotbar(usize, item::Consumable),
+}
+
+/// One update of the game
+#[derive(Clone, Debug)]
+pub struct Tick {
+    pub dt: f32,
+    pub input: input::Snapshot,
+    pub events: Vec<Event>, // applied before the inputs are read
+}
+
+/// A run, from its seed and the updates it went through. The menus aren't in it, only what
+/// they changed in the game.
+pub struct Replay {
+    pub seed: u64,
+    pub sticks: input::StickTuning,
+    pub ticks: Vec<Tick>,
+}
+
+/// How it's saved, a tick per line
+#[derive(Serialize, Deserialize)]
+struct ReplayFile {
+    version: u32,
+    seed: i64, // toml has no unsigned integers, these are the bits of the u64
+    dead_zone: f32,
+    curve: String,
+    aim_assist: bool,
+    ticks: Vec<String>,
+}
+
+/// Records the game as it's played, saved when the game closes
+pub struct Recorder {
+    pub path: PathBuf,
+    pub replay: Replay,
+    pending: Vec<Event>, // done since the last update, they go with the next one
+}
+
+/// A replay being played back, and what the viewer asks for
+pub struct Playback {
+    pub replay: Replay,
+    pub position: usize, // ticks already played
+    pub paused: bool,
+    pub speed: usize,        // ticks per frame
+    pub seek: Option<usize>, // the tick to jump to, going back starts the run over
+    pub stop: bool,          // gives the game back to the player from where it is
+}
+
+const CONSUMABLES: [item::Consumable; 3] = [
+    item::Consumable::HealthPotion,
+    item::Consumable::Torch,
+    item::Consumable::Bomb,
+];
+
+impl Event {
+    fn name(&self) -> String {
+        match self {
+            Event::OpenWorld => "open_world".to_string(),
+            Event::SelectWeapon(index) => format!("weapon:{}", index),
+            Event::BindHotbar(slot, consumable) => format!("hotbar:{}:{:?}", slot, consumable),
+        }
+    }
+    fn from_name(name: &str) -> Option<Self> {
+        let parts: Vec<&str> = name.split(':').collect();
+        match parts.as_slice() {
+            ["open_world"] => Some(Event::OpenWorld),
+            ["weapon", index] => index.parse().ok().map(Event::SelectWeapon),
+            ["hotbar", slot, consumable] => {
+                let slot: usize = slot.parse().ok()?;
+                if slot >= item::HOTBAR_SIZE {
+                    return None;
+                }
+                CONSUMABLES
+                    .iter()
+                    .find(|c| format!("{:?}", c) == *consumable)
+                    .map(|c| Event::BindHotbar(slot, *c))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn list<T, F: Fn(&T) -> String>(items: &[T], name: F) -> String {
+    if items.is_empty() {
+        "-".to_string()
+    } else {
+        items.iter().map(name).collect::<Vec<String>>().join(",")
+    }
+}
+
+fn parse_list<T, F: Fn(&str) -> Option<T>>(field: &str, parse: F) -> Option<Vec<T>> {
+    if field == "-" {
+        return Some(Vec::new());
+    }
+    field.split(',').map(parse).collect()
+}
+
+fn parse_numbers(field: &str) -> Option<Vec<f32>> {
+    field.split(',').map(|n| n.parse::<f32>().ok()).collect()
+}
+
+impl Tick {
+    /// dt, held, pressed, released, axes, mouse aim, gamepad and events, separated by spaces.
+    /// Floats are written in full so they read back the same.
+    fn to_line(&self) -> String {
+        let input = &self.input;
+        format!(
+            "{} {} {} {} {},{},{},{} {},{} {} {}",
+            self.dt,
+            list(&input.held, |a| a.name()),
+            list(&input.pressed, |a| a.name()),
+            list(&input.released, |a| a.name()),
+            input.axes[0],
+            input.axes[1],
+            input.axes[2],
+            input.axes[3],
+            input.mouse_aim.x,
+            input.mouse_aim.y,
+            input.gamepad as u8,
+            list(&self.events, |e| e.name()),
+        )
+    }
+    fn from_line(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.len() != 8 {
+            return None;
+        }
+        let axes = parse_numbers(fields[4])?;
+        let mouse_aim = parse_numbers(fields[5])?;
+        if axes.len() != 4 || mouse_aim.len() != 2 {
+            return None;
+        }
+        Some(Tick {
+            dt: fields[0].parse().ok()?,
+            input: input::Snapshot {
+                held: parse_list(fields[1], input::Action::from_name)?,
+                pressed: parse_list(fields[2], input::Action::from_name)?,
+                released: parse_list(fields[3], input::Action::from_name)?,
+                axes: [axes[0], axes[1], axes[2], axes[3]],
+                mouse_aim: Vec2::new(mouse_aim[0], mouse_aim[1]),
+                gamepad: match fields[6] {
+                    "0" => false,
+                    "1" => true,
+                    _ => return None,
+                },
+            },
+            events: parse_list(fields[7], Event::from_name)?,
+        })
+    }
+}
+
+impl Replay {
+    pub fn save(&self, path: &PathBuf) -> Result<(), String> {
+        let file = ReplayFile {
+            version: VERSION,
+            seed: self.seed as i64,
+            dead_zone: self.sticks.dead_zone,
+            curve: self.sticks.curve.name().to_string(),
+            aim_assist: self.sticks.aim_assist,
+            ticks: self.ticks.iter().map(|tick| tick.to_line()).collect(),
+        };
+        let content = toml::to_string_pretty(&file).map_err(|e| e.to_string())?;
+        std::fs::write(path, content).map_err(|e| e.to_string())
+    }
+    pub fn load(path: &PathBuf) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let file: ReplayFile = toml::from_str(&content).map_err(|e| e.to_string())?;
+        if file.version != VERSION {
+            return Err(format!(
+                "made by version {} of the recorder, this is version {}",
+                file.version, VERSION
+            ));
+        }
+        let curve = input::ResponseCurve::from_name(&file.curve)
+            .ok_or_else(|| format!("unknown response curve '{}'", file.curve))?;
+        let mut ticks = Vec::with_capacity(file.ticks.len());
+        for (index, line) in file.ticks.iter().enumerate() {
+            match Tick::from_line(line) {
+                Some(tick) => ticks.push(tick),
+                None => return Err(format!("tick {} can't be read: '{}'", index, line)),
+            }
+        }
+        Ok(Replay {
+            seed: file.seed as u64,
+            sticks: input::StickTuning {
+                dead_zone: file.dead_zone,
+                curve,
+                aim_assist: file.aim_assist,
+            },
+            ticks,
+        })
+    }
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf, seed: u64, sticks: input::StickTuning) -> Self {
+        Recorder {
+            path,
+            replay: Replay {
+                seed,
+                sticks,
+                ticks: Vec::new(),
+            },
+            pending: Vec::new(),
+        }
+    }
+    pub fn push_event(&mut self, event: Event) {
+        self.pending.push(event);
+    }
+    /// What the next update of the game is going to read
+    pub fn record(&mut self, dt: f32, input: input::Snapshot) {
+        self.replay.ticks.push(Tick {
+            dt,
+            input,
+            events: std::mem::take(&mut self.pending),
+        });
+    }
+    pub fn save(&self) {
+        match self.replay.save(&self.path) {
+            Ok(()) => println!(
+                "Saved a replay of {} updates to '{}'",
+                self.replay.ticks.len(),
+                self.path.display()
+            ),
+            Err(e) => eprintln!(
+                "[WARNING] Could not save the replay to '{}': {}",
+                self.path.display(),
+                e
+            ),
+        }
+    }
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            replay,
+            position: 0,
+            paused: false,
+            speed: 1,
+            seek: None,
+            stop: false,
+        }
+    }
+    pub fn is_over(&self) -> bool {
+        self.position >= self.replay.ticks.len()
+    }
+    /// In seconds of the game
+    pub fn time_at(&self, position: usize) -> f32 {
+        self.replay.ticks[..position.min(self.replay.ticks.len())]
+            .iter()
+            .map(|tick| tick.dt)
+            .sum()
+    }
+}
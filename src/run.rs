@@ -0,0 +1,565 @@
+use glam::Vec2;
+
+use crate::{
+    bloc, clock, hearing, id, input, item, map, mapgen, monster, physics, player, replay, status,
+    view, world,
+};
+
+const TILE_SIZE: f32 = 60.;
+
+/// Everything a run is made of. It's updated without the window, the sounds or the camera,
+/// so a replay goes through it the same way whatever is shown.
+pub struct Run {
+    pub map: map::Map,
+    pub player: player::Player,
+    pub monster_manager: monster::MonsterManager,
+    pub id_manager: id::IdManager,
+    pub world: Option<world::ChunkedWorld>,
+    outcome: Outcome, // since the last update
+}
+
+/// What the run went through in an update, for the game to show and play
+#[derive(Default)]
+pub struct Outcome {
+    pub noises: Vec<hearing::Noise>, // the loud ones shake the camera
+    pub sounds: Vec<(&'static str, Vec2)>, // and where they're played
+    pub hp_lost: i32,
+    pub new_floor: bool, // its music starts
+    pub moved: bool,     // the player was taken somewhere else, the camera jumps there
+}
+
+impl Run {
+    /// Everything in a run comes from its seed, so it can be played again
+    pub fn new(generation: mapgen::GenerationConfig, seed: u64) -> ggez::GameResult<Self> {
+        clock::reset();
+        let mut id_manager = id::IdManager::new();
+
+        // load the map
+        let mut map = map::Map::new(TILE_SIZE, generation, seed);
+        map.gen_new_map(&mut id_manager)?;
+
+        // Create the player
+        let player_spawn_pos = Vec2::new(
+            TILE_SIZE * map.spawn.x + TILE_SIZE / 2. - 30. / 2.,
+            TILE_SIZE * map.spawn.y + TILE_SIZE / 2. - 30. / 2.,
+        );
+        let player = player::Player::new(
+            player_spawn_pos.x,
+            player_spawn_pos.y,
+            30.,
+            30.,
+            &mut id_manager,
+        );
+
+        // Create the monsters
+        let mut monster_manager = monster::MonsterManager::new(seed);
+
+        for _ in 0..1 {
+            let monster_type = map.biome.pick_monster(&mut monster_manager.rng);
+            monster_manager.new_bot(
+                monster_type,
+                &mut id_manager,
+                (
+                    map.map_file_content.clone(),
+                    map.ghost_tiles.clone(),
+                    map.tile_size,
+                ),
+            );
+        }
+        Ok(Run {
+            map,
+            player,
+            monster_manager,
+            id_manager,
+            world: None,
+            outcome: Outcome::default(),
+        })
+    }
+    /// Takes the stairs to another floor, the player arrives on the stairs going back.
+    /// The player stays where they are if the floor can't be generated.
+    pub fn change_floor(&mut self, depth: u32) {
+        let going_down = depth > self.map.difficulty;
+        if let Err(e) = self.map.change_floor(
+            depth,
+            &mut self.monster_manager.monster_list,
+            &mut self.id_manager,
+        ) {
+            eprintln!("[WARNING] Could not go to stage '{}': {}", depth, e);
+            return;
+        }
+        let arrival = if going_down {
+            self.map.spawn
+        } else {
+            self.map.end
+        };
+        self.player.hitbox.x = arrival.x * self.map.tile_size;
+        self.player.hitbox.y = arrival.y * self.map.tile_size;
+        // The monster the player was aiming at stayed on the other floor
+        self.player.los.result = physics::RayCastResult::Fail;
+        self.outcome.new_floor = true;
+        self.outcome.moved = true;
+    }
+    /// Leaves the floors for the endless cave, generated around the player as they go
+    pub fn start_open_world(&mut self) {
+        let mut world =
+            world::ChunkedWorld::new(self.map.tile_size, self.map.run_seed, &self.map.generation);
+        let spawn = world.spawn_point(&mut self.id_manager);
+        self.player.hitbox.x = spawn.x - self.player.hitbox.w / 2.;
+        self.player.hitbox.y = spawn.y - self.player.hitbox.h / 2.;
+        self.monster_manager.monster_list.clear();
+        self.player.los.result = physics::RayCastResult::Fail;
+        self.world = Some(world);
+        self.outcome.moved = true;
+        println!("Entering the open world");
+    }
+    /// One update of the game, a replay goes through them again with the same inputs and dt
+    pub fn tick(&mut self, dt: f32) -> Outcome {
+        let hp_before = self.player.hp;
+        clock::advance(dt);
+        self.handle_game_actions();
+        if self.world.is_some() {
+            self.update_open_world(dt);
+        } else {
+            self.update_floor(dt);
+        }
+        self.outcome.sounds.append(&mut self.player.sounds);
+        self.outcome.hp_lost = (hp_before - self.player.hp).max(0);
+        std::mem::take(&mut self.outcome)
+    }
+    /// An update of a replay, with what the menus did before it
+    pub fn replay_tick(&mut self, tick: &replay::Tick) -> Outcome {
+        for event in tick.events.iter() {
+            match *event {
+                replay::Event::OpenWorld => self.start_open_world(),
+                replay::Event::SelectWeapon(index) => self.player.inventory.selected_index = index,
+                replay::Event::BindHotbar(slot, consumable) => {
+                    self.player.items.hotbar[slot] = Some(consumable)
+                }
+            }
+        }
+        self.player.inputs.restore(&tick.input);
+        self.tick(tick.dt)
+    }
+    fn update_floor(&mut self, dt: f32) {
+        // Update player
+        self.player.update_movements(
+            self.map.bloc_list.iter(),
+            dt,
+            &mut self.id_manager,
+            &mut self.monster_manager,
+            self.map.difficulty,
+        );
+        let aim = if self.player.inputs.gamepad {
+            let map = &self.map;
+            self.player
+                .stick_aim(&self.monster_manager.monster_list, map.tile_size, |x, y| {
+                    map.blocks_sound(x, y)
+                })
+        } else {
+            self.player.inputs.mouse_aim
+        };
+        self.player.update_los(
+            aim,
+            self.map.bloc_list.iter(),
+            &mut self.monster_manager.monster_list,
+        );
+        // let player_vec = Vec::new().push(self.player);
+        self.map.update_traps(&mut self.id_manager);
+        let mut bloc_events = self.map.bloc_effects(&mut self.player);
+        self.map.reveal_traps(
+            Vec2::from(self.player.hitbox.center()),
+            self.player.inputs.held(input::Action::Sneak),
+        );
+        self.map.explore(Vec2::from(self.player.hitbox.center()));
+        for drop in self.map.collect_pickups(self.player.hitbox) {
+            self.player.pick_up(drop);
+        }
+
+        // What the monsters can hear this frame
+        let map = &self.map;
+        let mut noises: Vec<hearing::Noise> = self
+            .player
+            .noises
+            .drain(..)
+            .map(|noise| map.surface_noise(noise))
+            .collect();
+
+        // Update the bombs
+        for blast_center in self.map.update_bombs(dt) {
+            let blast_radius = item::BOMB_RADIUS * self.map.tile_size;
+            noises.push(self.map.explode(blast_center, &mut self.id_manager));
+            for drop in self.monster_manager.damage_in_radius(
+                blast_center,
+                blast_radius,
+                item::BOMB_DAMAGE,
+                Some(status::StatusKind::Stun),
+                self.map.difficulty,
+                &mut self.id_manager,
+            ) {
+                self.player.pick_up(drop);
+            }
+            if physics::RayCasting::get_distance(
+                blast_center,
+                Vec2::from(self.player.hitbox.center()),
+            ) <= blast_radius
+            {
+                self.player.take_damages(item::BOMB_DAMAGE);
+            }
+        }
+
+        // Update the monsters
+        while self.monster_manager.monster_list.len() < self.map.biome.max_monsters() {
+            let monster_type = self.map.biome.pick_monster(&mut self.monster_manager.rng);
+            self.monster_manager.new_bot(
+                monster_type,
+                &mut self.id_manager,
+                (
+                    self.map.map_file_content.clone(),
+                    self.map.ghost_tiles.clone(),
+                    self.map.tile_size,
+                ),
+            );
+        }
+        self.monster_manager
+            .update(Vec2::from(self.player.hitbox.center()));
+        self.monster_manager.open_doors(&mut self.map);
+        self.monster_manager.update_movements(
+            dt,
+            &self.map.bloc_list,
+            (
+                self.map.map_file_content.clone(),
+                self.map.ghost_tiles.clone(),
+                self.map.tile_size,
+            ),
+            (self.map.total_rows, self.map.total_cols),
+        );
+        let map = &self.map;
+        self.monster_manager.hear(
+            &noises,
+            map.tile_size,
+            |x, y| map.blocks_sound(x, y),
+            |from, to| {
+                physics::PathFinding::astar(
+                    from / map.tile_size,
+                    (to / map.tile_size).floor(),
+                    (
+                        map.map_file_content.clone(),
+                        map.ghost_tiles.clone(),
+                        map.tile_size,
+                    ),
+                )
+            },
+        );
+        for index in 0..self.monster_manager.monster_list.len() {
+            bloc_events.extend(
+                self.map
+                    .bloc_effects(&mut self.monster_manager.monster_list[index]),
+            );
+        }
+        physics::EntityTrait::update_status(&mut self.player, dt);
+        for drop in
+            self.monster_manager
+                .update_status(dt, self.map.difficulty, &mut self.id_manager)
+        {
+            self.player.pick_up(drop);
+        }
+
+        let mut fell_down = false;
+        for event in bloc_events {
+            match event {
+                bloc::BlocEvent::Fall(id) => {
+                    if id == self.player.id {
+                        fell_down = true;
+                    } else {
+                        self.monster_manager.remove(id);
+                    }
+                }
+                bloc::BlocEvent::Alert(pos) => self.monster_manager.alert(
+                    pos,
+                    (
+                        self.map.map_file_content.clone(),
+                        self.map.ghost_tiles.clone(),
+                        self.map.tile_size,
+                    ),
+                ),
+            }
+        }
+        if fell_down {
+            println!("The floor gave way");
+            self.change_floor(self.map.difficulty + 1);
+        }
+        self.outcome.noises.extend(noises);
+    }
+    fn update_open_world(&mut self, dt: f32) {
+        let world = match self.world.as_mut() {
+            Some(world) => world,
+            None => return,
+        };
+        let player_center = Vec2::from(self.player.hitbox.center());
+        world.update(player_center, &mut self.id_manager);
+
+        // Everything in reach of the player can be hit, the rest of the world is ignored.
+        // It never gets smaller than twice the screen, and doesn't depend on the zoom.
+        let reach = Vec2::splat(self.player.inventory.longest_range());
+        let half_size = view::VIRTUAL_SIZE.max(reach);
+        let area = ggez::graphics::Rect::new(
+            player_center.x - half_size.x,
+            player_center.y - half_size.y,
+            half_size.x * 2.,
+            half_size.y * 2.,
+        );
+        self.player.update_movements(
+            world.blocs_in(area),
+            dt,
+            &mut self.id_manager,
+            &mut self.monster_manager,
+            0,
+        );
+        let aim = if self.player.inputs.gamepad {
+            self.player.stick_aim(
+                &self.monster_manager.monster_list,
+                world.tile_size,
+                |x, y| world.blocks_sound(x, y),
+            )
+        } else {
+            self.player.inputs.mouse_aim
+        };
+        self.player.update_los(
+            aim,
+            world.blocs_in(area),
+            &mut self.monster_manager.monster_list,
+        );
+        world.bloc_effects(&mut self.player);
+
+        // The monsters live around the player only
+        if self.monster_manager.monster_list.len() < world.biome.max_monsters() {
+            let spawn_distance = (world::CHUNK_SIZE / 2, world::CHUNK_SIZE * 2);
+            if let Some(pos) = world.random_open_position(
+                player_center,
+                spawn_distance.0,
+                spawn_distance.1,
+                &mut self.monster_manager.rng,
+            ) {
+                let monster_type = world.biome.pick_monster(&mut self.monster_manager.rng);
+                self.monster_manager
+                    .spawn(monster_type, pos, &mut self.id_manager);
+            }
+        }
+        self.monster_manager.update(player_center);
+        self.monster_manager.update_movements_in_world(dt, world);
+        for monster in self.monster_manager.monster_list.iter_mut() {
+            world.bloc_effects(monster);
+        }
+        let noises: Vec<hearing::Noise> = self.player.noises.drain(..).collect();
+        self.monster_manager.hear(
+            &noises,
+            world.tile_size,
+            |x, y| world.blocks_sound(x, y),
+            |from, to| world.find_path(from, to),
+        );
+        physics::EntityTrait::update_status(&mut self.player, dt);
+        for drop in self
+            .monster_manager
+            .update_status(dt, 0, &mut self.id_manager)
+        {
+            self.player.pick_up(drop);
+        }
+        self.outcome.noises.extend(noises);
+    }
+    /// Opens or closes the door next to the player
+    fn interact(&mut self) {
+        let player_center = Vec2::from(self.player.hitbox.center());
+        let (x, y) = match self.map.door_near(player_center, self.map.tile_size * 1.2) {
+            Some(coords) => coords,
+            None => return,
+        };
+        // Don't shut a door on someone standing in it
+        let door_rect = self.map.bloc_list[y * self.map.total_cols as usize + x]
+            .tile()
+            .hitbox;
+        if self.player.hitbox.overlaps(&door_rect)
+            || self
+                .monster_manager
+                .monster_list
+                .iter()
+                .any(|m| physics::EntityTrait::get_hitbox(m).overlaps(&door_rect))
+        {
+            return;
+        }
+        match self.map.toggle_door(x, y, &self.player.keys) {
+            map::DoorResult::Unlocked => println!("The vault is open"),
+            map::DoorResult::Locked => {
+                println!("This door is locked, there must be a key somewhere")
+            }
+            _ => {}
+        }
+    }
+    /// The one shot actions of the game, the held ones are read where they're used
+    fn handle_game_actions(&mut self) {
+        let actions = &self.player.inputs;
+        let (next_map, interact, next_weapon) = (
+            actions.pressed(input::Action::NextMap),
+            actions.pressed(input::Action::Interact),
+            actions.pressed(input::Action::NextWeapon),
+        );
+        // Items are used when let go, a bomb can be aimed while its key is held
+        let hotbar_slot =
+            (0..item::HOTBAR_SIZE).find(|slot| actions.released(input::Action::Hotbar(*slot)));
+
+        if next_map && self.world.is_none() {
+            let player_center = Vec2::from(self.player.hitbox.center());
+            let on_end = physics::RayCasting::get_distance(
+                player_center,
+                self.map
+                    .tile_center(self.map.end.x as usize, self.map.end.y as usize),
+            ) < self.map.tile_size;
+            let on_spawn = physics::RayCasting::get_distance(
+                player_center,
+                self.map
+                    .tile_center(self.map.spawn.x as usize, self.map.spawn.y as usize),
+            ) < self.map.tile_size;
+            match map::stairs_destination(self.map.difficulty, on_end, on_spawn) {
+                Some(depth) => self.change_floor(depth),
+                None if on_spawn => {
+                    println!("These stairs lead back to the surface, the crabs are down there")
+                }
+                None => {}
+            }
+        }
+        if interact && self.world.is_none() {
+            self.interact();
+        }
+        if next_weapon {
+            self.player.inventory.select_next();
+        }
+        if let Some(slot) = hotbar_slot {
+            // Torches and bombs go on the floors, the open world has nowhere to keep them
+            let map = match self.world {
+                Some(_) => None,
+                None => Some(&mut self.map),
+            };
+            self.player.use_hotbar(slot, map);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    const SEEDS: [u64; 2] = [0, 0xDEAD_BEEF];
+    const TICKS: usize = 900;
+
+    /// What a run is left with, to tell two of them apart
+    #[derive(Debug, PartialEq)]
+    struct State {
+        player: (ggez::graphics::Rect, i32, f32, usize),
+        monsters: Vec<(i32, ggez::graphics::Rect, i32)>,
+        grid: Vec<Vec<i32>>,
+        depth: u32,
+        bombs: Vec<(Vec2, f32)>,
+        pickups: Vec<Vec2>,
+        open_world: bool,
+        next_roll: u64, // where the monsters' rng is at
+    }
+
+    fn state(run: &Run) -> State {
+        State {
+            player: (
+                run.player.hitbox,
+                run.player.hp,
+                run.player.los.angle,
+                run.player.inventory.selected_index,
+            ),
+            monsters: run
+                .monster_manager
+                .monster_list
+                .iter()
+                .map(|monster::Monster::TestBot(bot)| (bot.id, bot.hitbox, bot.hp))
+                .collect(),
+            grid: run.map.map_file_content.clone(),
+            depth: run.map.difficulty,
+            bombs: run.map.bombs.iter().map(|b| (b.pos, b.fuse)).collect(),
+            pickups: run.map.pickups.iter().map(|p| p.pos).collect(),
+            open_world: run.world.is_some(),
+            next_roll: run.monster_manager.rng.clone().gen(),
+        }
+    }
+
+    /// Walks around in circles shooting, changes weapon, tries the stairs and ends in the
+    /// open world, the same way for every seed
+    fn input_log() -> Vec<replay::Tick> {
+        let directions = [
+            input::Action::Right,
+            input::Action::Down,
+            input::Action::Left,
+            input::Action::Up,
+        ];
+        (0..TICKS)
+            .map(|i| {
+                let mut held = vec![directions[i / 45 % directions.len()]];
+                if i / 30 % 2 == 1 {
+                    held.push(input::Action::Fire);
+                }
+                let pressed = match i {
+                    200 => vec![input::Action::NextWeapon],
+                    400 => vec![input::Action::NextMap],
+                    _ => Vec::new(),
+                };
+                let angle = i as f32 * 0.05;
+                replay::Tick {
+                    dt: 1. / 60.,
+                    input: input::Snapshot {
+                        held,
+                        pressed,
+                        mouse_aim: Vec2::new(angle.cos(), angle.sin()) * 1000.,
+                        ..Default::default()
+                    },
+                    events: if i == TICKS / 2 {
+                        vec![replay::Event::OpenWorld]
+                    } else {
+                        Vec::new()
+                    },
+                }
+            })
+            .collect()
+    }
+
+    fn play(seed: u64, log: &[replay::Tick]) -> State {
+        let mut run = Run::new(mapgen::GenerationConfig::default(), seed).unwrap();
+        for tick in log.iter() {
+            run.replay_tick(tick);
+        }
+        state(&run)
+    }
+
+    #[test]
+    fn same_seed_and_inputs_play_the_same() {
+        let log = input_log();
+        for &seed in SEEDS.iter() {
+            let first = play(seed, &log);
+            let second = play(seed, &log);
+            assert!(
+                first.open_world,
+                "seed {} never reached the open world",
+                seed
+            );
+            assert_eq!(first, second, "seed {} played differently", seed);
+        }
+    }
+
+    #[test]
+    fn the_inputs_change_the_run() {
+        let log = input_log();
+        let idle: Vec<replay::Tick> = log
+            .iter()
+            .map(|tick| replay::Tick {
+                dt: tick.dt,
+                input: input::Snapshot::default(),
+                events: Vec::new(),
+            })
+            .collect();
+        assert_ne!(play(42, &log).player, play(42, &idle).player);
+    }
+}
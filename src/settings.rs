@@ -0,0 +1,573 @@
+use crate::{audio, input};
+
+const SETTINGS_FILE: &str = "settings.toml"; // in the user config dir
+pub const WINDOW_TITLE: &str = "The game title";
+pub const RESOLUTIONS: [(f32, f32); 4] =
+    [(1280., 720.), (1600., 900.), (1920., 1080.), (2560., 1440.)];
+const GUI_SCALE_RANGE: (f32, f32) = (0.5, 4.);
+const MIN_WINDOW_SIZE: f32 = 320.;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowKind {
+    Windowed,
+    Borderless, // fullscreen at the desktop resolution
+    Fullscreen,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WindowSettings {
+    pub kind: WindowKind,
+    pub width: f32,
+    pub height: f32,
+    pub vsync: bool, // only read when the window is created, applies on the next start
+}
+
+/// What the settings menu changes, kept between runs
+pub struct Settings {
+    pub keymap: input::KeyMap,
+    pub padmap: input::PadMap,
+    pub gui_scale: f32,
+    pub window: WindowSettings,
+    pub volumes: audio::Volumes,
+}
+
+impl WindowKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WindowKind::Windowed => "windowed",
+            WindowKind::Borderless => "borderless",
+            WindowKind::Fullscreen => "fullscreen",
+        }
+    }
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            WindowKind::Windowed,
+            WindowKind::Borderless,
+            WindowKind::Fullscreen,
+        ]
+        .iter()
+        .copied()
+        .find(|kind| kind.name() == name)
+    }
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            kind: WindowKind::Borderless,
+            width: 1920.,
+            height: 1080.,
+            vsync: false,
+        }
+    }
+}
+
+impl WindowSettings {
+    pub fn mode(&self) -> ggez::conf::WindowMode {
+        ggez::conf::WindowMode::default()
+            .dimensions(self.width, self.height)
+            .fullscreen_type(match self.kind {
+                WindowKind::Windowed => ggez::conf::FullscreenType::Windowed,
+                WindowKind::Borderless => ggez::conf::FullscreenType::Desktop,
+                WindowKind::Fullscreen => ggez::conf::FullscreenType::True,
+            })
+            .resizable(true)
+    }
+    pub fn setup(&self) -> ggez::conf::WindowSetup {
+        ggez::conf::WindowSetup::default()
+            .title(WINDOW_TITLE)
+            .vsync(self.vsync)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            keymap: input::KeyMap::default(),
+            padmap: input::PadMap::default(),
+            gui_scale: 2.,
+            window: WindowSettings::default(),
+            volumes: audio::Volumes::default(),
+        }
+    }
+}
+
+fn number(value: &toml::Value) -> Option<f32> {
+    value
+        .as_float()
+        .map(|f| f as f32)
+        .or_else(|| value.as_integer().map(|i| i as f32))
+}
+
+/// A single name, or a list of up to two names for a primary and a secondary binding
+fn parse_bind(value: &toml::Value) -> Option<input::Bind> {
+    let names: Vec<&toml::Value> = match value.as_array() {
+        Some(names) => names.iter().collect(),
+        None => vec![value],
+    };
+    if names.len() > 2 {
+        return None;
+    }
+    let mut bind = input::Bind([None, None]);
+    for (slot, name) in names.iter().enumerate() {
+        bind.0[slot] = Some(input::Binding::from_name(name.as_str()?)?);
+    }
+    Some(bind)
+}
+
+fn parse_button(value: &toml::Value) -> Option<ggez::event::Button> {
+    value.as_str().and_then(input::button_from_name)
+}
+
+fn parse_axis(value: &toml::Value) -> Option<ggez::event::Axis> {
+    value.as_str().and_then(input::axis_from_name)
+}
+
+/// Reads a [section] of action = binding, what can't be read keeps its default
+fn read_bindings<T: Copy>(
+    section: &str,
+    value: &toml::Value,
+    mut bindings: Vec<(String, &mut T)>,
+    parse: fn(&toml::Value) -> Option<T>,
+    problems: &mut Vec<String>,
+) {
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => {
+            problems.push(format!("'{}' should be a [{}] section", section, section));
+            return;
+        }
+    };
+    for (name, value) in table.iter() {
+        match bindings
+            .iter_mut()
+            .find(|(binding_name, _)| binding_name == name)
+        {
+            Some((_, binding)) => match parse(value) {
+                Some(parsed) => **binding = parsed,
+                None => problems.push(format!(
+                    "{}.{} = {} is not a known input",
+                    section, name, value
+                )),
+            },
+            None => problems.push(format!("unknown setting '{}.{}'", section, name)),
+        }
+    }
+}
+
+impl Settings {
+    /// The defaults when there is no file yet. Anything that can't be read keeps its default
+    /// and is listed in the returned problems, the rest of the file still applies.
+    pub fn load(ctx: &ggez::Context) -> (Self, Vec<String>) {
+        let path = ggez::filesystem::user_config_dir(ctx).join(SETTINGS_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Settings::parse(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (Settings::default(), Vec::new()),
+            Err(e) => (
+                Settings::default(),
+                vec![format!("could not read '{}': {}", path.display(), e)],
+            ),
+        }
+    }
+    /// The content of a settings file, over the defaults
+    fn parse(content: &str) -> (Self, Vec<String>) {
+        let mut settings = Settings::default();
+        let mut problems: Vec<String> = Vec::new();
+        let table: toml::value::Table = match toml::from_str(content) {
+            Ok(table) => table,
+            Err(e) => {
+                problems.push(format!("could not parse '{}': {}", SETTINGS_FILE, e));
+                return (settings, problems);
+            }
+        };
+
+        for (name, value) in table.iter() {
+            match name.as_str() {
+                "gui_scale" => match number(value) {
+                    Some(scale) if scale >= GUI_SCALE_RANGE.0 && scale <= GUI_SCALE_RANGE.1 => {
+                        settings.gui_scale = scale
+                    }
+                    _ => problems.push(format!(
+                        "gui_scale = {} should be between {} and {}",
+                        value, GUI_SCALE_RANGE.0, GUI_SCALE_RANGE.1
+                    )),
+                },
+                "keys" => read_bindings(
+                    "keys",
+                    value,
+                    settings.keymap.bindings_mut(),
+                    parse_bind,
+                    &mut problems,
+                ),
+                "gamepad" => read_bindings(
+                    "gamepad",
+                    value,
+                    settings.padmap.bindings_mut(),
+                    parse_button,
+                    &mut problems,
+                ),
+                "gamepad_axes" => read_bindings(
+                    "gamepad_axes",
+                    value,
+                    settings.padmap.axes_mut(),
+                    parse_axis,
+                    &mut problems,
+                ),
+                "gamepad_sticks" => settings.read_sticks(value, &mut problems),
+                "window" => settings.read_window(value, &mut problems),
+                "volume" => settings.read_volumes(value, &mut problems),
+                _ => problems.push(format!("unknown setting '{}'", name)),
+            }
+        }
+        (settings, problems)
+    }
+    fn read_window(&mut self, value: &toml::Value, problems: &mut Vec<String>) {
+        let table = match value.as_table() {
+            Some(table) => table,
+            None => {
+                problems.push("'window' should be a [window] section".to_string());
+                return;
+            }
+        };
+        for (name, value) in table.iter() {
+            match name.as_str() {
+                "mode" => match value.as_str().and_then(WindowKind::from_name) {
+                    Some(kind) => self.window.kind = kind,
+                    None => problems.push(format!(
+                        "window.mode = {} should be \"windowed\", \"borderless\" or \"fullscreen\"",
+                        value
+                    )),
+                },
+                "width" | "height" => match number(value) {
+                    Some(size) if size >= MIN_WINDOW_SIZE => {
+                        if name == "width" {
+                            self.window.width = size;
+                        } else {
+                            self.window.height = size;
+                        }
+                    }
+                    _ => problems.push(format!(
+                        "window.{} = {} should be a size of at least {}",
+                        name, value, MIN_WINDOW_SIZE
+                    )),
+                },
+                "vsync" => match value.as_bool() {
+                    Some(vsync) => self.window.vsync = vsync,
+                    None => {
+                        problems.push(format!("window.vsync = {} should be true or false", value))
+                    }
+                },
+                _ => problems.push(format!("unknown setting 'window.{}'", name)),
+            }
+        }
+    }
+    fn read_sticks(&mut self, value: &toml::Value, problems: &mut Vec<String>) {
+        let table = match value.as_table() {
+            Some(table) => table,
+            None => {
+                problems.push("'gamepad_sticks' should be a [gamepad_sticks] section".to_string());
+                return;
+            }
+        };
+        let (min, max) = input::DEAD_ZONE_RANGE;
+        for (name, value) in table.iter() {
+            match name.as_str() {
+                "dead_zone" => match number(value) {
+                    Some(dead_zone) if dead_zone >= min && dead_zone <= max => {
+                        self.padmap.sticks.dead_zone = dead_zone
+                    }
+                    _ => problems.push(format!(
+                        "gamepad_sticks.dead_zone = {} should be between {} and {}",
+                        value, min, max
+                    )),
+                },
+                "curve" => match value.as_str().and_then(input::ResponseCurve::from_name) {
+                    Some(curve) => self.padmap.sticks.curve = curve,
+                    None => problems.push(format!(
+                        "gamepad_sticks.curve = {} should be \"linear\", \"quadratic\" or \"cubic\"",
+                        value
+                    )),
+                },
+                "aim_assist" => match value.as_bool() {
+                    Some(aim_assist) => self.padmap.sticks.aim_assist = aim_assist,
+                    None => problems.push(format!(
+                        "gamepad_sticks.aim_assist = {} should be true or false",
+                        value
+                    )),
+                },
+                _ => problems.push(format!("unknown setting 'gamepad_sticks.{}'", name)),
+            }
+        }
+    }
+    fn read_volumes(&mut self, value: &toml::Value, problems: &mut Vec<String>) {
+        let table = match value.as_table() {
+            Some(table) => table,
+            None => {
+                problems.push("'volume' should be a [volume] section".to_string());
+                return;
+            }
+        };
+        for (name, value) in table.iter() {
+            let volume = match name.as_str() {
+                "master" => &mut self.volumes.master,
+                "sfx" => &mut self.volumes.sfx,
+                "music" => &mut self.volumes.music,
+                "ui" => &mut self.volumes.ui,
+                _ => {
+                    problems.push(format!("unknown setting 'volume.{}'", name));
+                    continue;
+                }
+            };
+            match number(value) {
+                Some(level) if (0. ..=1.).contains(&level) => *volume = level,
+                _ => problems.push(format!(
+                    "volume.{} = {} should be between 0 and 1",
+                    name, value
+                )),
+            }
+        }
+    }
+
+    /// Overwrites the settings file. The window setup is also given to ggez, which only reads it
+    /// when the window is created.
+    pub fn save(&mut self, ctx: &mut ggez::Context) {
+        let mut keys = toml::value::Table::new();
+        for (name, bind) in self.keymap.bindings_mut() {
+            let names = bind
+                .0
+                .iter()
+                .flatten()
+                .map(|binding| toml::Value::String(binding.name()))
+                .collect();
+            keys.insert(name, toml::Value::Array(names));
+        }
+        let mut gamepad = toml::value::Table::new();
+        for (name, button) in self.padmap.bindings_mut() {
+            gamepad.insert(name, toml::Value::String(format!("{:?}", button)));
+        }
+        let mut gamepad_axes = toml::value::Table::new();
+        for (name, axis) in self.padmap.axes_mut() {
+            gamepad_axes.insert(name, toml::Value::String(format!("{:?}", axis)));
+        }
+        let mut gamepad_sticks = toml::value::Table::new();
+        gamepad_sticks.insert(
+            "dead_zone".to_string(),
+            toml::Value::Float(self.padmap.sticks.dead_zone as f64),
+        );
+        gamepad_sticks.insert(
+            "curve".to_string(),
+            toml::Value::String(self.padmap.sticks.curve.name().to_string()),
+        );
+        gamepad_sticks.insert(
+            "aim_assist".to_string(),
+            toml::Value::Boolean(self.padmap.sticks.aim_assist),
+        );
+        let mut window = toml::value::Table::new();
+        window.insert(
+            "mode".to_string(),
+            toml::Value::String(self.window.kind.name().to_string()),
+        );
+        window.insert(
+            "width".to_string(),
+            toml::Value::Float(self.window.width as f64),
+        );
+        window.insert(
+            "height".to_string(),
+            toml::Value::Float(self.window.height as f64),
+        );
+        window.insert("vsync".to_string(), toml::Value::Boolean(self.window.vsync));
+        let mut volume = toml::value::Table::new();
+        for (name, level) in [
+            ("master", self.volumes.master),
+            ("sfx", self.volumes.sfx),
+            ("music", self.volumes.music),
+            ("ui", self.volumes.ui),
+        ]
+        .iter()
+        {
+            volume.insert(name.to_string(), toml::Value::Float(*level as f64));
+        }
+        let mut table = toml::value::Table::new();
+        table.insert(
+            "gui_scale".to_string(),
+            toml::Value::Float(self.gui_scale as f64),
+        );
+        table.insert("keys".to_string(), toml::Value::Table(keys));
+        table.insert("gamepad".to_string(), toml::Value::Table(gamepad));
+        table.insert("gamepad_axes".to_string(), toml::Value::Table(gamepad_axes));
+        table.insert(
+            "gamepad_sticks".to_string(),
+            toml::Value::Table(gamepad_sticks),
+        );
+        table.insert("window".to_string(), toml::Value::Table(window));
+        table.insert("volume".to_string(), toml::Value::Table(volume));
+
+        let dir = ggez::filesystem::user_config_dir(ctx).to_path_buf();
+        let path = dir.join(SETTINGS_FILE);
+        let written = toml::to_string(&toml::Value::Table(table))
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                std::fs::create_dir_all(&dir)
+                    .and_then(|_| std::fs::write(&path, content))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = written {
+            eprintln!(
+                "[WARNING] Could not save the settings to '{}': {}",
+                path.display(),
+                e
+            );
+        }
+
+        let conf = ggez::conf::Conf {
+            window_mode: self.window.mode(),
+            window_setup: self.window.setup(),
+            ..ggez::conf::Conf::default()
+        };
+        if let Err(e) = ggez::filesystem::write_config(ctx, &conf) {
+            eprintln!("[WARNING] Could not save the window setup: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Action, Binding};
+    use ggez::event::{Button, KeyCode, MouseButton};
+
+    fn fire_button(padmap: &input::PadMap) -> Button {
+        padmap
+            .buttons
+            .iter()
+            .find(|(action, _)| *action == Action::Fire)
+            .map(|(_, button)| *button)
+            .unwrap()
+    }
+
+    #[test]
+    fn a_valid_file_is_read_whole() {
+        let (settings, problems) = Settings::parse(
+            "gui_scale = 3
+            [keys]
+            up = \"Up\"
+            fire = [\"MouseRight\", \"Space\"]
+            hotbar_2 = \"Q\"
+            [gamepad]
+            fire = \"RightTrigger2\"
+            [window]
+            mode = \"windowed\"
+            width = 1280
+            height = 720.0
+            vsync = true
+            [volume]
+            master = 0.5
+            music = 0",
+        );
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(settings.gui_scale, 3.);
+        assert_eq!(
+            settings.keymap.get(Action::Up),
+            input::Bind([Some(Binding::Key(KeyCode::Up)), None])
+        );
+        assert_eq!(
+            settings.keymap.get(Action::Fire),
+            input::Bind([
+                Some(Binding::Mouse(MouseButton::Right)),
+                Some(Binding::Key(KeyCode::Space))
+            ])
+        );
+        assert!(settings
+            .keymap
+            .get(Action::Hotbar(1))
+            .matches(Binding::Key(KeyCode::Q)));
+        assert_eq!(fire_button(&settings.padmap), Button::RightTrigger2);
+        assert_eq!(settings.window.kind, WindowKind::Windowed);
+        assert_eq!(
+            (settings.window.width, settings.window.height),
+            (1280., 720.)
+        );
+        assert!(settings.window.vsync);
+        assert_eq!(settings.volumes.master, 0.5);
+        assert_eq!(settings.volumes.music, 0.);
+    }
+
+    #[test]
+    fn unknown_entries_are_reported_and_the_rest_applies() {
+        let (settings, problems) = Settings::parse(
+            "shadows = true
+            gui_scale = 1.5
+            [keys]
+            jump = \"Space\"
+            down = \"Down\"
+            [window]
+            fps = 60
+            [volume]
+            voices = 0.2",
+        );
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert_eq!(settings.gui_scale, 1.5);
+        assert!(settings
+            .keymap
+            .get(Action::Down)
+            .matches(Binding::Key(KeyCode::Down)));
+    }
+
+    #[test]
+    fn invalid_values_keep_their_default() {
+        let (settings, problems) = Settings::parse(
+            "gui_scale = 12
+            [keys]
+            up = \"NotAKey\"
+            left = 4
+            right = [\"A\", \"B\", \"C\"]
+            [gamepad]
+            fire = \"Trigger\"
+            [window]
+            mode = \"maximized\"
+            width = 10
+            vsync = \"yes\"
+            [volume]
+            sfx = 1.5
+            ui = \"loud\"",
+        );
+        assert_eq!(problems.len(), 10, "{:?}", problems);
+        let defaults = Settings::default();
+        assert_eq!(settings.gui_scale, defaults.gui_scale);
+        assert_eq!(
+            settings.keymap.get(Action::Up),
+            defaults.keymap.get(Action::Up)
+        );
+        assert_eq!(
+            settings.keymap.get(Action::Left),
+            defaults.keymap.get(Action::Left)
+        );
+        assert_eq!(
+            settings.keymap.get(Action::Right),
+            defaults.keymap.get(Action::Right)
+        );
+        assert_eq!(fire_button(&settings.padmap), fire_button(&defaults.padmap));
+        assert_eq!(settings.window.kind, defaults.window.kind);
+        assert_eq!(settings.window.width, defaults.window.width);
+        assert_eq!(settings.window.vsync, defaults.window.vsync);
+        assert_eq!(settings.volumes.sfx, defaults.volumes.sfx);
+        assert_eq!(settings.volumes.ui, defaults.volumes.ui);
+    }
+
+    #[test]
+    fn sections_of_the_wrong_type_are_reported() {
+        let (settings, problems) = Settings::parse("keys = 3\nwindow = \"big\"\nvolume = []");
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert_eq!(
+            settings.keymap.get(Action::Up),
+            Settings::default().keymap.get(Action::Up)
+        );
+    }
+
+    #[test]
+    fn a_broken_file_gives_the_defaults() {
+        let (settings, problems) = Settings::parse("gui_scale = = 3\n[keys");
+        assert_eq!(problems.len(), 1);
+        assert_eq!(settings.gui_scale, Settings::default().gui_scale);
+    }
+}
@@ -0,0 +1,244 @@
+use ggez::graphics::spritebatch::SpriteBatch;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+
+pub const SPRITE_SIZE: f32 = 32.; // in pixels, size of a cell of the atlas
+const MANIFEST_PATH: &str = "/sprites/atlas.toml";
+const HURT_TIME: f32 = 0.25; // in seconds
+const ATTACK_TIME: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnimationKind {
+    Idle,
+    Walk,
+    Attack,
+    Hurt,
+    Die,
+}
+
+/// The layout of the atlas, read from its manifest
+#[derive(Deserialize)]
+struct Manifest {
+    columns: u32,
+    rows: u32,
+    sprites: Vec<SpriteEntry>,
+}
+
+#[derive(Deserialize)]
+struct SpriteEntry {
+    name: String,
+    row: u32,
+    #[serde(default)]
+    column: u32,
+    #[serde(default = "one_frame")]
+    frames: u32,
+    #[serde(default = "default_fps")]
+    fps: f32,
+    #[serde(default = "looping")]
+    looping: bool,
+}
+
+fn one_frame() -> u32 {
+    1
+}
+fn default_fps() -> f32 {
+    8.
+}
+fn looping() -> bool {
+    true
+}
+
+pub struct Animation {
+    pub frames: Vec<ggez::graphics::Rect>, // in texture coordinates
+    pub fps: f32,
+    pub looping: bool,
+}
+
+/// Named sprites of the entities and items, all in one image so they share a sprite batch
+pub struct SpriteAtlas {
+    pub batch: SpriteBatch,
+    animations: HashMap<String, Animation>,
+}
+
+/// Which animation an entity plays, and since when
+pub struct Animator {
+    pub kind: AnimationKind,
+    pub time: f32,
+    lock: f32, // hurt and attack play until the end before going back to the state
+}
+
+impl AnimationKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnimationKind::Idle => "idle",
+            AnimationKind::Walk => "walk",
+            AnimationKind::Attack => "attack",
+            AnimationKind::Hurt => "hurt",
+            AnimationKind::Die => "die",
+        }
+    }
+}
+
+impl SpriteAtlas {
+    pub fn load(ctx: &mut ggez::Context, image: ggez::graphics::Image) -> Self {
+        let mut content = String::new();
+        let read = ggez::filesystem::open(ctx, MANIFEST_PATH).and_then(|mut file| {
+            file.read_to_string(&mut content)
+                .map_err(ggez::GameError::from)
+        });
+        let manifest = match read.map(|_| toml::from_str::<Manifest>(&content)) {
+            Ok(Ok(manifest)) => manifest,
+            Ok(Err(e)) => {
+                eprintln!("Could not parse '{}', no sprites: {}", MANIFEST_PATH, e);
+                Manifest::empty()
+            }
+            Err(e) => {
+                eprintln!("Could not read '{}', no sprites: {}", MANIFEST_PATH, e);
+                Manifest::empty()
+            }
+        };
+
+        let (cell_w, cell_h) = (1. / manifest.columns as f32, 1. / manifest.rows as f32);
+        let mut animations = HashMap::new();
+        for entry in manifest.sprites.iter() {
+            let frames = (0..entry.frames)
+                .map(|frame| {
+                    ggez::graphics::Rect::new(
+                        (entry.column + frame) as f32 * cell_w,
+                        entry.row as f32 * cell_h,
+                        cell_w,
+                        cell_h,
+                    )
+                })
+                .collect();
+            animations.insert(
+                entry.name.clone(),
+                Animation {
+                    frames,
+                    fps: entry.fps,
+                    looping: entry.looping,
+                },
+            );
+        }
+
+        SpriteAtlas {
+            batch: SpriteBatch::new(image),
+            animations,
+        }
+    }
+    /// The frame shown after playing the animation for the given time
+    pub fn frame(&self, name: &str, time: f32) -> Option<ggez::graphics::Rect> {
+        let animation = self.animations.get(name)?;
+        let mut index = (time.max(0.) * animation.fps) as usize;
+        if animation.looping {
+            index %= animation.frames.len();
+        } else {
+            index = index.min(animation.frames.len() - 1);
+        }
+        Some(animation.frames[index])
+    }
+    /// Queues the sprite centered on the position, scaled to the given width.
+    /// Falls back on the idle animation of the same sprite when it doesn't have this one.
+    pub fn add(
+        &mut self,
+        name: &str,
+        time: f32,
+        center: glam::Vec2,
+        width: f32,
+        angle: f32,
+        color: ggez::graphics::Color,
+    ) {
+        let src = match self.frame(name, time) {
+            Some(src) => src,
+            None => match name.rsplit_once('.') {
+                Some((prefix, _)) => match self.frame(&format!("{}.idle", prefix), time) {
+                    Some(src) => src,
+                    None => return,
+                },
+                None => return,
+            },
+        };
+        let scale = width / SPRITE_SIZE;
+        self.batch.add(
+            ggez::graphics::DrawParam::new()
+                .src(src)
+                .dest(center)
+                .scale(glam::Vec2::new(scale, scale))
+                .offset(glam::Vec2::new(0.5, 0.5))
+                .rotation(angle)
+                .color(color),
+        );
+    }
+    /// Draws everything queued since the last call
+    pub fn draw(&mut self, ctx: &mut ggez::Context, draw_offset: glam::Vec2) -> ggez::GameResult {
+        ggez::graphics::draw(
+            ctx,
+            &self.batch,
+            (draw_offset, 0., ggez::graphics::Color::WHITE),
+        )?;
+        self.batch.clear();
+        Ok(())
+    }
+}
+
+impl Manifest {
+    fn empty() -> Self {
+        Manifest {
+            columns: 1,
+            rows: 1,
+            sprites: Vec::new(),
+        }
+    }
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Animator {
+            kind: AnimationKind::Idle,
+            time: 0.,
+            lock: 0.,
+        }
+    }
+    /// Moves the animation forward, switching to the given state once hurt or attack are done
+    pub fn update(&mut self, dt: f32, state: AnimationKind) {
+        self.time += dt;
+        self.lock -= dt;
+        if self.kind == AnimationKind::Die || self.lock > 0. {
+            return;
+        }
+        if self.kind != state {
+            self.kind = state;
+            self.time = 0.;
+        }
+    }
+    pub fn hurt(&mut self) {
+        self.play_once(AnimationKind::Hurt, HURT_TIME);
+    }
+    pub fn attack(&mut self) {
+        // Getting hit shows over shooting
+        if self.kind != AnimationKind::Hurt || self.lock <= 0. {
+            self.play_once(AnimationKind::Attack, ATTACK_TIME);
+        }
+    }
+    pub fn die(&mut self) {
+        if self.kind != AnimationKind::Die {
+            self.kind = AnimationKind::Die;
+            self.time = 0.;
+        }
+    }
+    fn play_once(&mut self, kind: AnimationKind, duration: f32) {
+        if self.kind == AnimationKind::Die {
+            return;
+        }
+        if self.kind != kind {
+            self.time = 0.;
+        }
+        self.kind = kind;
+        self.lock = duration;
+    }
+    /// Name of the sprite to draw, for an entity using the given prefix in the atlas
+    pub fn sprite(&self, prefix: &str) -> String {
+        format!("{}.{}", prefix, self.kind.name())
+    }
+}
@@ -0,0 +1,175 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatusKind {
+    Burning,
+    Poison,
+    Slow,
+    Bleed,
+    Stun,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub remaining: f32, // seconds
+    pub stacks: u32,
+    pub tick_timer: f32,
+}
+
+#[derive(Default)]
+pub struct StatusEffects {
+    pub list: Vec<StatusEffect>,
+}
+
+impl StatusKind {
+    pub fn duration(&self) -> f32 {
+        match self {
+            StatusKind::Burning => 3.,
+            StatusKind::Poison => 5.,
+            StatusKind::Slow => 0.5,
+            StatusKind::Bleed => 4.,
+            StatusKind::Stun => 0.8,
+        }
+    }
+    /// Seconds between two damage ticks, None if the effect doesn't deal damage
+    pub fn tick_interval(&self) -> Option<f32> {
+        match self {
+            StatusKind::Burning => Some(0.5),
+            StatusKind::Poison => Some(1.),
+            StatusKind::Bleed => Some(0.8),
+            StatusKind::Slow | StatusKind::Stun => None,
+        }
+    }
+    /// Damage of a single tick for a single stack
+    pub fn tick_damage(&self) -> i32 {
+        match self {
+            StatusKind::Burning => 3,
+            StatusKind::Poison => 2,
+            StatusKind::Bleed => 2,
+            StatusKind::Slow | StatusKind::Stun => 0,
+        }
+    }
+    /// Applying an effect again either adds a stack (up to this) or only refreshes the duration
+    pub fn max_stacks(&self) -> u32 {
+        match self {
+            StatusKind::Poison => 5,
+            StatusKind::Bleed => 3,
+            StatusKind::Burning | StatusKind::Slow | StatusKind::Stun => 1,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatusKind::Burning => "Burning",
+            StatusKind::Poison => "Poison",
+            StatusKind::Slow => "Slow",
+            StatusKind::Bleed => "Bleed",
+            StatusKind::Stun => "Stun",
+        }
+    }
+    pub fn color(&self) -> ggez::graphics::Color {
+        match self {
+            StatusKind::Burning => ggez::graphics::Color::from_rgb(255, 120, 0),
+            StatusKind::Poison => ggez::graphics::Color::from_rgb(80, 200, 40),
+            StatusKind::Slow => ggez::graphics::Color::from_rgb(60, 120, 255),
+            StatusKind::Bleed => ggez::graphics::Color::from_rgb(180, 0, 0),
+            StatusKind::Stun => ggez::graphics::Color::from_rgb(255, 255, 80),
+        }
+    }
+}
+
+impl StatusEffects {
+    pub fn apply(&mut self, kind: StatusKind) {
+        match self.list.iter_mut().find(|e| e.kind == kind) {
+            Some(effect) => {
+                effect.remaining = kind.duration();
+                effect.stacks = std::cmp::min(effect.stacks + 1, kind.max_stacks());
+            }
+            None => self.list.push(StatusEffect {
+                kind,
+                remaining: kind.duration(),
+                stacks: 1,
+                tick_timer: 0.,
+            }),
+        }
+    }
+    /// Advances every effect, returns the damage dealt during this update
+    pub fn update(&mut self, dt: f32) -> i32 {
+        let mut damage = 0;
+        for effect in self.list.iter_mut() {
+            effect.remaining -= dt;
+            if let Some(interval) = effect.kind.tick_interval() {
+                effect.tick_timer += dt;
+                while effect.tick_timer >= interval {
+                    effect.tick_timer -= interval;
+                    damage += effect.kind.tick_damage() * effect.stacks as i32;
+                }
+            }
+        }
+        self.list.retain(|e| e.remaining > 0.);
+        damage
+    }
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.list.iter().any(|e| e.kind == kind)
+    }
+    pub fn is_stunned(&self) -> bool {
+        self.has(StatusKind::Stun)
+    }
+    pub fn speed_mult(&self) -> f32 {
+        if self.is_stunned() {
+            0.
+        } else if self.has(StatusKind::Slow) {
+            0.5
+        } else {
+            1.
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applying_again_stacks_up_to_the_max_and_refreshes() {
+        let mut effects = StatusEffects::default();
+        for _ in 0..10 {
+            effects.apply(StatusKind::Poison);
+        }
+        assert_eq!(effects.list.len(), 1);
+        assert_eq!(effects.list[0].stacks, StatusKind::Poison.max_stacks());
+
+        effects.update(2.);
+        effects.apply(StatusKind::Poison);
+        assert_eq!(effects.list[0].remaining, StatusKind::Poison.duration());
+    }
+
+    #[test]
+    fn ticks_deal_damage_for_every_stack() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusKind::Bleed);
+        effects.apply(StatusKind::Bleed);
+        // 0.8s per tick, 2 damage per stack
+        assert_eq!(effects.update(0.5), 0);
+        assert_eq!(effects.update(0.5), 4);
+        assert_eq!(effects.update(1.6), 8);
+    }
+
+    #[test]
+    fn effects_expire() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusKind::Slow);
+        assert!(effects.has(StatusKind::Slow));
+        effects.update(StatusKind::Slow.duration() + 0.1);
+        assert!(effects.list.is_empty());
+    }
+
+    #[test]
+    fn stun_wins_over_slow() {
+        let mut effects = StatusEffects::default();
+        assert_eq!(effects.speed_mult(), 1.);
+        effects.apply(StatusKind::Slow);
+        assert_eq!(effects.speed_mult(), 0.5);
+        effects.apply(StatusKind::Stun);
+        assert!(effects.is_stunned());
+        assert_eq!(effects.speed_mult(), 0.);
+    }
+}
@@ -0,0 +1,178 @@
+use ggez::graphics::spritebatch::SpriteBatch;
+use std::collections::HashMap;
+
+use crate::{biome, mapgen};
+
+pub const CELL_SIZE: f32 = 32.; // in pixels, every sprite of the atlas has this size
+const ATLAS_COLUMNS: f32 = 8.;
+
+/// Rows of the atlas, top to bottom, with how many variants each one has
+const ATLAS_ROWS: [(&str, usize); 21] = [
+    ("ground", 4),
+    ("ground_moss", 4),
+    ("ground_ash", 4),
+    ("ground_sand", 4),
+    ("wall", 8),
+    ("wall_moss", 8),
+    ("wall_crystal", 8),
+    ("wall_basalt", 8),
+    ("wall_rust", 8),
+    ("water", 4),
+    ("crate", 1),
+    ("lava", 4),
+    ("end", 1),
+    ("spawn", 1),
+    ("spikes", 1),
+    ("collapsing_floor", 1),
+    ("gas_vent", 1),
+    ("pressure_plate", 1),
+    ("door_open", 1),
+    ("door_closed", 1),
+    ("vault_door", 1),
+];
+
+/// Rows whose columns are the frames of an animation, with its speed in frames per second
+const ANIMATED_ROWS: [(&str, f32); 2] = [("water", 4.), ("lava", 3.)];
+
+// Columns of the wall rows, drawn with the open sides on top and rotated from there
+const WALL_SOLID: usize = 0; // and 1 for the other look
+const WALL_EDGE: usize = 2; // open on top
+const WALL_CORNER: usize = 3; // open on top and left
+const WALL_INNER_CORNER: usize = 4; // only the top left diagonal is open
+const WALL_THIN: usize = 5; // open on top and bottom
+const WALL_END: usize = 6; // only closed at the bottom
+const WALL_PILLAR: usize = 7; // open everywhere
+const GROUND_VARIANTS: usize = 4;
+
+/// The tile atlas, with one sprite batch for the whole map. The floors and the open world
+/// share it, each one sets its biome before drawing.
+pub struct Tileset {
+    pub batch: SpriteBatch,
+    biome: biome::Biome,
+    rows: HashMap<i32, (usize, usize)>, // material, (row, variant count)
+    animated: HashMap<i32, f32>,        // material, frames per second
+}
+
+impl Tileset {
+    pub fn new(image: ggez::graphics::Image, biome: biome::Biome) -> Self {
+        let mut tileset = Tileset {
+            batch: SpriteBatch::new(image),
+            biome,
+            rows: HashMap::new(),
+            animated: HashMap::new(),
+        };
+        tileset.load_biome();
+        tileset
+    }
+    /// Uses the textures of the biome in place of the default ones, the atlas stays the same
+    pub fn set_biome(&mut self, biome: biome::Biome) {
+        if biome != self.biome {
+            self.biome = biome;
+            self.load_biome();
+        }
+    }
+    fn load_biome(&mut self) {
+        let biome = self.biome;
+        let names: HashMap<i32, &str> = vec![
+            (-1, "ground"),
+            (4, "wall"),
+            (9, "water"),
+            (12, "crate"),
+            (18, "lava"),
+            (21, "end"),
+            (20, "spawn"),
+            (22, "spikes"),
+            (23, "collapsing_floor"),
+            (24, "gas_vent"),
+            (25, "pressure_plate"),
+            (26, "door_open"),
+            (27, "door_closed"),
+            (28, "vault_door"),
+        ]
+        .into_iter()
+        .chain(biome.textures())
+        .collect();
+
+        self.rows.clear();
+        self.animated.clear();
+        for (material, name) in names.iter() {
+            if let Some((_, fps)) = ANIMATED_ROWS.iter().find(|(row_name, _)| row_name == name) {
+                self.animated.insert(*material, *fps);
+            }
+            match ATLAS_ROWS.iter().position(|(row_name, _)| row_name == name) {
+                Some(row) => {
+                    self.rows.insert(*material, (row, ATLAS_ROWS[row].1));
+                }
+                None => println!("[WARNING] No '{}' row in the tileset", name),
+            }
+        }
+    }
+    /// Animated tiles change over time, they can't be cached
+    pub fn is_animated(&self, material: i32) -> bool {
+        self.animated.contains_key(&material)
+    }
+    /// Same as src, but animated tiles show the frame matching the time instead of the variant
+    pub fn src_at(&self, material: i32, variant: usize, time: f32) -> Option<ggez::graphics::Rect> {
+        match self.animated.get(&material) {
+            Some(fps) => {
+                let frames = self.rows.get(&material)?.1;
+                self.src(material, (time * fps) as usize % frames)
+            }
+            None => self.src(material, variant),
+        }
+    }
+    /// Part of the atlas to draw, in texture coordinates
+    pub fn src(&self, material: i32, variant: usize) -> Option<ggez::graphics::Rect> {
+        let (row, count) = self.rows.get(&material)?;
+        let column = if variant < *count { variant } else { 0 };
+        Some(ggez::graphics::Rect::new(
+            column as f32 / ATLAS_COLUMNS,
+            *row as f32 / ATLAS_ROWS.len() as f32,
+            1. / ATLAS_COLUMNS,
+            1. / ATLAS_ROWS.len() as f32,
+        ))
+    }
+}
+
+/// Picks the sprite of a tile from its 8 neighbours, returns the variant and the rotation.
+/// Anything that isn't a wall counts as open.
+pub fn autotile<F: Fn(i32, i32) -> i32>(x: i32, y: i32, material_at: F) -> (usize, f32) {
+    let quarter = std::f32::consts::FRAC_PI_2;
+    if material_at(x, y) != mapgen::WALL {
+        return (noise(x, y) % GROUND_VARIANTS, 0.);
+    }
+    let open = |dx: i32, dy: i32| material_at(x + dx, y + dy) != mapgen::WALL;
+    // Clockwise from the top, so a quarter turn moves a side to the next one
+    let sides = [open(0, -1), open(1, 0), open(0, 1), open(-1, 0)];
+    let diagonals = [open(-1, -1), open(1, -1), open(1, 1), open(-1, 1)];
+    let turns = |first: usize| first as f32 * quarter;
+
+    match sides.iter().filter(|s| **s).count() {
+        0 => match diagonals.iter().position(|d| *d) {
+            Some(corner) => (WALL_INNER_CORNER, turns(corner)),
+            None => (WALL_SOLID + noise(x, y) % 2, 0.),
+        },
+        1 => (WALL_EDGE, turns(sides.iter().position(|s| *s).unwrap())),
+        2 => {
+            if sides[0] == sides[2] {
+                // Top and bottom, or left and right
+                (WALL_THIN, if sides[0] { 0. } else { quarter })
+            } else {
+                // The corner starts on the open side followed by the other open one
+                let first = (0..4).find(|i| sides[*i] && sides[(i + 1) % 4]).unwrap();
+                (WALL_CORNER, turns((first + 1) % 4))
+            }
+        }
+        3 => {
+            let closed = sides.iter().position(|s| !*s).unwrap();
+            (WALL_END, turns((closed + 2) % 4))
+        }
+        _ => (WALL_PILLAR, 0.),
+    }
+}
+
+/// Same result for the same tile, so the variants don't change when a map is built again
+fn noise(x: i32, y: i32) -> usize {
+    let hash = (x.wrapping_mul(73856093) ^ y.wrapping_mul(19349663)) as u32;
+    (hash.wrapping_mul(2654435761) >> 16) as usize
+}
@@ -0,0 +1,106 @@
+use glam::Vec2;
+
+/// Everything is laid out for this size, then scaled to fit the window
+pub const VIRTUAL_SIZE: Vec2 = glam::const_vec2!([1920., 1080.]);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Bottom,
+}
+
+/// Virtual pixels to window pixels. The virtual screen keeps its aspect ratio,
+/// what's left of the window on the sides or on top is letterboxed.
+pub struct ViewTransform {
+    pub window_size: Vec2, // in window pixels
+    pub scale: f32,
+    pub offset: Vec2, // size of the letterbox bars, left and top
+}
+
+impl ViewTransform {
+    pub fn new(window_size: Vec2) -> Self {
+        let scale = (window_size.x / VIRTUAL_SIZE.x).min(window_size.y / VIRTUAL_SIZE.y);
+        ViewTransform {
+            window_size,
+            scale,
+            offset: (window_size - VIRTUAL_SIZE * scale) / 2.,
+        }
+    }
+    pub fn to_virtual(&self, pos: Vec2) -> Vec2 {
+        (pos - self.offset) / self.scale
+    }
+    /// What to give to graphics::set_screen_coordinates so the area lands on the virtual screen.
+    /// The area is in virtual pixels, or is the view of a camera that has been zoomed.
+    pub fn screen_coordinates(&self, area: ggez::graphics::Rect) -> ggez::graphics::Rect {
+        let ratio = area.w / VIRTUAL_SIZE.x / self.scale; // area units per window pixel
+        ggez::graphics::Rect::new(
+            area.x - self.offset.x * ratio,
+            area.y - self.offset.y * ratio,
+            self.window_size.x * ratio,
+            self.window_size.y * ratio,
+        )
+    }
+    /// Draws in virtual pixels from now on
+    pub fn apply(&self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        ggez::graphics::set_screen_coordinates(
+            ctx,
+            self.screen_coordinates(ggez::graphics::Rect::new(
+                0.,
+                0.,
+                VIRTUAL_SIZE.x,
+                VIRTUAL_SIZE.y,
+            )),
+        )
+    }
+    /// Draws in window pixels from now on, for the menus
+    pub fn reset(&self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        ggez::graphics::set_screen_coordinates(
+            ctx,
+            ggez::graphics::Rect::new(0., 0., self.window_size.x, self.window_size.y),
+        )
+    }
+    /// Position in virtual pixels, relative to a corner or an edge of the screen.
+    /// The margin goes toward the inside.
+    pub fn anchor(anchor: Anchor, margin: Vec2) -> Vec2 {
+        let (x, y) = match anchor {
+            Anchor::TopLeft => (margin.x, margin.y),
+            Anchor::TopRight => (VIRTUAL_SIZE.x - margin.x, margin.y),
+            Anchor::BottomLeft => (margin.x, VIRTUAL_SIZE.y - margin.y),
+            Anchor::BottomRight => (VIRTUAL_SIZE.x - margin.x, VIRTUAL_SIZE.y - margin.y),
+            Anchor::Bottom => (VIRTUAL_SIZE.x / 2. + margin.x, VIRTUAL_SIZE.y - margin.y),
+        };
+        Vec2::new(x, y)
+    }
+    /// Black bars over what's drawn outside of the virtual screen
+    pub fn draw_letterbox(&self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        if self.offset.x < 1. && self.offset.y < 1. {
+            return Ok(());
+        }
+        self.reset(ctx)?;
+        let mut bars = ggez::graphics::MeshBuilder::new();
+        let (w, h) = (self.window_size.x, self.window_size.y);
+        let rects = if self.offset.x >= 1. {
+            [
+                ggez::graphics::Rect::new(0., 0., self.offset.x, h),
+                ggez::graphics::Rect::new(w - self.offset.x, 0., self.offset.x, h),
+            ]
+        } else {
+            [
+                ggez::graphics::Rect::new(0., 0., w, self.offset.y),
+                ggez::graphics::Rect::new(0., h - self.offset.y, w, self.offset.y),
+            ]
+        };
+        for rect in rects.iter() {
+            bars.rectangle(
+                ggez::graphics::DrawMode::fill(),
+                *rect,
+                ggez::graphics::Color::BLACK,
+            )?;
+        }
+        let bars = bars.build(ctx)?;
+        ggez::graphics::draw(ctx, &bars, ggez::graphics::DrawParam::new())
+    }
+}
@@ -1,12 +1,15 @@
+use serde::{Deserialize, Serialize};
 use std;
 
-use crate::id;
+use crate::{clock, id, item};
 use rand::Rng;
 
 const INVENTORY_MAX_LENGHT: usize = 10;
 
 pub enum ObjectDrop {
     Ok(Weapon),
+    Consumable(item::Consumable, u32),
+    Key(i32), // id of the door it opens
     None,
 }
 
@@ -17,199 +20,411 @@ pub trait WeaponTrait {
 }
 
 pub struct WeaponInventory {
-    pub weapon_list: [Weapon; INVENTORY_MAX_LENGHT],
+    pub weapon_list: [Option<Weapon>; INVENTORY_MAX_LENGHT],
     pub selected_index: usize,
 }
 
-#[derive(Clone, Copy)]
-pub enum Weapon {
-    Pistol(Pistol),
-    Knife(Knife),
-    Minigun(Minigun),
-    None,
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Archetype {
+    Pistol,
+    Knife,
+    Minigun,
 }
 
-#[derive(Clone, Copy)]
-pub struct Pistol {
-    pub id: i32,
-    pub damage: i32,
-    pub attack_speed: i32, // delay between two shots
-    pub last_shot_time: std::time::SystemTime,
-    pub range: f32,
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+    Legendary,
 }
 
-#[derive(Clone, Copy)]
-pub struct Knife {
-    pub id: i32,
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Modifier {
+    Damage(i32),      // percent
+    AttackSpeed(i32), // percent, lowers the delay between two shots
+    Range(i32),       // percent
+    Piercing,         // goes through every monster until it hits a wall
+    Lifesteal(i32),   // percent of the damage dealt given back to the shooter
+    Burning,          // sets the target on fire
+}
+
+pub struct BaseStats {
     pub damage: i32,
     pub attack_speed: i32, // delay between two shots
-    pub last_shot_time: std::time::SystemTime,
     pub range: f32,
 }
-#[derive(Clone, Copy)]
-pub struct Minigun {
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Weapon {
     pub id: i32,
-    pub damage: i32,
-    pub attack_speed: i32, // delay between two shots
-    pub last_shot_time: std::time::SystemTime,
-    pub range: f32,
+    pub archetype: Archetype,
+    pub rarity: Rarity,
+    pub modifiers: Vec<Modifier>,
+    #[serde(skip, default = "never_shot")]
+    pub last_shot_time: clock::GameTime,
 }
 
-pub fn generate_drop(id_manager: &mut id::IdManager) -> ObjectDrop {
-    let prcentage = rand::thread_rng().gen_range(0..100);
+fn never_shot() -> clock::GameTime {
+    clock::GameTime::NEVER
+}
 
-    let unique_weapon = 2; // the number of weapons
+pub fn generate_drop<R: Rng>(
+    id_manager: &mut id::IdManager,
+    difficulty: u32,
+    rng: &mut R,
+) -> ObjectDrop {
+    let prcentage = rng.gen_range(0..100);
 
-    if prcentage < 90 {
-        if unique_weapon > 1 {
-            let index = rand::thread_rng().gen_range(0..unique_weapon);
-            match index {
-                1 => ObjectDrop::Ok(Weapon::Pistol(Pistol::new(id_manager))),
-                0 => ObjectDrop::Ok(Weapon::Knife(Knife::new(id_manager))),
-                _ => ObjectDrop::None,
-            }
-        } else {
-            ObjectDrop::Ok(Weapon::Pistol(Pistol::new(id_manager)))
-        }
+    if prcentage < 40 {
+        let archetype = match rng.gen_range(0..3) {
+            0 => Archetype::Knife,
+            1 => Archetype::Pistol,
+            _ => Archetype::Minigun,
+        };
+        ObjectDrop::Ok(Weapon::roll(archetype, difficulty, id_manager, rng))
+    } else if prcentage < 90 {
+        let consumable = match rng.gen_range(0..3) {
+            0 => item::Consumable::HealthPotion,
+            1 => item::Consumable::Torch,
+            _ => item::Consumable::Bomb,
+        };
+        ObjectDrop::Consumable(consumable, 1)
     } else {
         ObjectDrop::None
     }
 }
 
+/// Vaults always have something in them, and it's better than what monsters carry
+pub fn generate_vault_loot<R: Rng>(
+    id_manager: &mut id::IdManager,
+    difficulty: u32,
+    rng: &mut R,
+) -> ObjectDrop {
+    match generate_drop(id_manager, difficulty + 30, rng) {
+        ObjectDrop::None => ObjectDrop::Consumable(item::Consumable::HealthPotion, 2),
+        ObjectDrop::Consumable(consumable, count) => ObjectDrop::Consumable(consumable, count + 1),
+        drop => drop,
+    }
+}
+
 impl WeaponInventory {
     pub fn new(id_manager: &mut id::IdManager) -> Self {
-        let mut weapon_list = [Weapon::None; INVENTORY_MAX_LENGHT];
-        weapon_list[0] = Weapon::Minigun(Minigun::new(id_manager));
-        // weapon_list[0] = Weapon::Knife(Knife::new(id_manager));
+        let mut weapon_list: [Option<Weapon>; INVENTORY_MAX_LENGHT] = Default::default();
+        weapon_list[0] = Some(Weapon::new(Archetype::Minigun, id_manager));
+        // weapon_list[0] = Some(Weapon::new(Archetype::Knife, id_manager));
         WeaponInventory {
             weapon_list: weapon_list,
             selected_index: 0,
         }
     }
     pub fn index_is_weapon(&self) -> bool {
-        match self.weapon_list[self.selected_index] {
-            Weapon::None => false,
-            _ => true,
+        self.weapon_list[self.selected_index].is_some()
+    }
+    pub fn selected(&self) -> Option<&Weapon> {
+        self.weapon_list[self.selected_index].as_ref()
+    }
+    /// Range of the weapon that shoots the furthest, 0 without any weapon
+    pub fn longest_range(&self) -> f32 {
+        self.weapon_list
+            .iter()
+            .flatten()
+            .map(|w| w.get_range())
+            .fold(0., f32::max)
+    }
+    /// Selects the next weapon, the empty slots are skipped
+    pub fn select_next(&mut self) {
+        for offset in 1..=INVENTORY_MAX_LENGHT {
+            let index = (self.selected_index + offset) % INVENTORY_MAX_LENGHT;
+            if self.weapon_list[index].is_some() {
+                self.selected_index = index;
+                return;
+            }
+        }
+    }
+    pub fn selected_mut(&mut self) -> Option<&mut Weapon> {
+        self.weapon_list[self.selected_index].as_mut()
+    }
+    /// Puts the weapon in the first free slot, gives it back if the inventory is full
+    pub fn add(&mut self, weapon: Weapon) -> Option<Weapon> {
+        match self.weapon_list.iter().position(|slot| slot.is_none()) {
+            Some(index) => {
+                self.weapon_list[index] = Some(weapon);
+                None
+            }
+            None => Some(weapon),
         }
     }
 }
 
-impl Pistol {
-    pub fn new(id_manager: &mut id::IdManager) -> Self {
-        Pistol {
-            id: id_manager.get_new_id(),
-            damage: 6,
-            attack_speed: 200,
-            last_shot_time: std::time::SystemTime::UNIX_EPOCH,
-            range: 500.,
+impl Archetype {
+    pub fn base_stats(&self) -> BaseStats {
+        match self {
+            Archetype::Pistol => BaseStats {
+                damage: 6,
+                attack_speed: 200,
+                range: 500.,
+            },
+            Archetype::Knife => BaseStats {
+                damage: 26,
+                attack_speed: 120,
+                range: 80.,
+            },
+            Archetype::Minigun => BaseStats {
+                damage: 30,
+                attack_speed: 30,
+                range: 700.,
+            },
+        }
+    }
+    /// Prefix of its sprites in the atlas
+    pub fn sprite(&self) -> &'static str {
+        match self {
+            Archetype::Pistol => "pistol",
+            Archetype::Knife => "knife",
+            Archetype::Minigun => "minigun",
+        }
+    }
+    /// Sound pool of a shot, the knife doesn't make any
+    pub fn sound(&self) -> Option<&'static str> {
+        match self {
+            Archetype::Pistol => Some("pistol"),
+            Archetype::Knife => None,
+            Archetype::Minigun => Some("little_pistol"),
+        }
+    }
+    /// How far the monsters hear it, the knife is almost silent
+    pub fn loudness(&self) -> f32 {
+        match self {
+            Archetype::Pistol => 1.,
+            Archetype::Knife => 0.1,
+            Archetype::Minigun => 2.,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Archetype::Pistol => "Pistol",
+            Archetype::Knife => "Knife",
+            Archetype::Minigun => "Minigun",
         }
     }
 }
-impl Knife {
-    pub fn new(id_manager: &mut id::IdManager) -> Self {
-        Knife {
-            id: id_manager.get_new_id(),
-            damage: 26,
-            attack_speed: 120,
-            last_shot_time: std::time::SystemTime::UNIX_EPOCH,
-            range: 80.,
+
+impl Rarity {
+    /// The deeper you are, the better the odds
+    pub fn roll<R: Rng>(difficulty: u32, rng: &mut R) -> Self {
+        let roll = rng.gen_range(0..100) + std::cmp::min(difficulty, 40) as i32;
+        if roll >= 125 {
+            Rarity::Legendary
+        } else if roll >= 95 {
+            Rarity::Rare
+        } else if roll >= 65 {
+            Rarity::Uncommon
+        } else {
+            Rarity::Common
+        }
+    }
+    pub fn modifier_count(&self) -> usize {
+        match self {
+            Rarity::Common => 0,
+            Rarity::Uncommon => 1,
+            Rarity::Rare => 2,
+            Rarity::Legendary => 3,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rarity::Common => "Common",
+            Rarity::Uncommon => "Uncommon",
+            Rarity::Rare => "Rare",
+            Rarity::Legendary => "Legendary",
         }
     }
 }
-impl Minigun {
-    pub fn new(id_manager: &mut id::IdManager) -> Self {
-        Minigun {
+
+impl Modifier {
+    pub fn roll<R: Rng>(rarity: Rarity, rng: &mut R) -> Self {
+        // Better rarities roll stronger numbers
+        let tier = rarity as i32;
+        match rng.gen_range(0..6) {
+            0 => Modifier::Damage(rng.gen_range(10..=20) * tier),
+            1 => Modifier::AttackSpeed(rng.gen_range(5..=10) * tier),
+            2 => Modifier::Range(rng.gen_range(10..=15) * tier),
+            3 => Modifier::Piercing,
+            4 => Modifier::Lifesteal(rng.gen_range(2..=5) * tier),
+            _ => Modifier::Burning,
+        }
+    }
+    pub fn description(&self) -> String {
+        match self {
+            Modifier::Damage(p) => format!("+{}% damage", p),
+            Modifier::AttackSpeed(p) => format!("+{}% attack speed", p),
+            Modifier::Range(p) => format!("+{}% range", p),
+            Modifier::Piercing => "Piercing".to_string(),
+            Modifier::Lifesteal(p) => format!("{}% lifesteal", p),
+            Modifier::Burning => "Sets targets on fire".to_string(),
+        }
+    }
+}
+
+impl Weapon {
+    pub fn new(archetype: Archetype, id_manager: &mut id::IdManager) -> Self {
+        Weapon {
             id: id_manager.get_new_id(),
-            damage: 30,
-            attack_speed: 30,
-            last_shot_time: std::time::SystemTime::UNIX_EPOCH,
-            range: 700.,
+            archetype,
+            rarity: Rarity::Common,
+            modifiers: Vec::new(),
+            last_shot_time: never_shot(),
         }
     }
+    pub fn roll<R: Rng>(
+        archetype: Archetype,
+        difficulty: u32,
+        id_manager: &mut id::IdManager,
+        rng: &mut R,
+    ) -> Self {
+        let mut weapon = Weapon::new(archetype, id_manager);
+        weapon.rarity = Rarity::roll(difficulty, rng);
+
+        while weapon.modifiers.len() < weapon.rarity.modifier_count() {
+            let modifier = Modifier::roll(weapon.rarity, rng);
+            // Flags can't stack, there is no point having two of them
+            if (modifier == Modifier::Piercing || modifier == Modifier::Burning)
+                && weapon.modifiers.contains(&modifier)
+            {
+                continue;
+            }
+            weapon.modifiers.push(modifier);
+        }
+        weapon
+    }
+    pub fn name(&self) -> String {
+        format!("{} {}", self.rarity.name(), self.archetype.name())
+    }
+    fn sum_percent<F: Fn(&Modifier) -> Option<i32>>(&self, f: F) -> i32 {
+        self.modifiers.iter().filter_map(f).sum()
+    }
+    pub fn get_attack_speed(&self) -> i32 {
+        let bonus = self.sum_percent(|m| match m {
+            Modifier::AttackSpeed(p) => Some(*p),
+            _ => None,
+        });
+        self.archetype.base_stats().attack_speed * 100 / (100 + bonus)
+    }
+    /// What's left to wait before the next shot, from 1 right after shooting to 0
+    pub fn cooldown(&self) -> f32 {
+        let elapsed = self.last_shot_time.elapsed();
+        (1. - elapsed.as_millis() as f32 / self.get_attack_speed().max(1) as f32).max(0.)
+    }
+    pub fn is_piercing(&self) -> bool {
+        self.modifiers.contains(&Modifier::Piercing)
+    }
+    pub fn get_lifesteal(&self) -> i32 {
+        self.sum_percent(|m| match m {
+            Modifier::Lifesteal(p) => Some(*p),
+            _ => None,
+        })
+    }
+    pub fn is_burning(&self) -> bool {
+        self.modifiers.contains(&Modifier::Burning)
+    }
+    pub fn tooltip(&self) -> String {
+        let mut tooltip = format!(
+            "{}\nDamage: {}\nAttack delay: {} ms\nRange: {}",
+            self.name(),
+            self.get_damage(),
+            self.get_attack_speed(),
+            self.get_range()
+        );
+        for modifier in self.modifiers.iter() {
+            tooltip.push('\n');
+            tooltip.push_str(&modifier.description());
+        }
+        tooltip
+    }
 }
 
 impl WeaponTrait for Weapon {
     fn can_shoot(&mut self) -> bool {
-        match self {
-            Weapon::Pistol(p) => {
-                match p.last_shot_time.elapsed() {
-                    Ok(elapsed) => {
-                        if elapsed.as_millis() > p.attack_speed as u128 {
-                            p.last_shot_time = std::time::SystemTime::now();
-                            // println!("elapsed: {}", elapsed.as_millis());
-                            true
-                        } else {
-                            // println!("Can't shoot yet");
-                            false
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!(
-                            "There has been an error with the system clock, err: {:?}",
-                            e
-                        );
-                        false
-                    }
-                }
-            }
-            Weapon::Knife(k) => {
-                match k.last_shot_time.elapsed() {
-                    Ok(elapsed) => {
-                        if elapsed.as_millis() > k.attack_speed as u128 {
-                            k.last_shot_time = std::time::SystemTime::now();
-                            // println!("elapsed: {}", elapsed.as_millis());
-                            true
-                        } else {
-                            // println!("Can't shoot yet");
-                            false
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!(
-                            "There has been an error with the system clock, err: {:?}",
-                            e
-                        );
-                        false
-                    }
-                }
-            }
-            Weapon::Minigun(mg) => {
-                match mg.last_shot_time.elapsed() {
-                    Ok(elapsed) => {
-                        if elapsed.as_millis() > mg.attack_speed as u128 {
-                            mg.last_shot_time = std::time::SystemTime::now();
-                            // println!("elapsed: {}", elapsed.as_millis());
-                            true
-                        } else {
-                            // println!("Can't shoot yet");
-                            false
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!(
-                            "There has been an error with the system clock, err: {:?}",
-                            e
-                        );
-                        false
-                    }
-                }
-            }
-            _ => false,
+        if self.last_shot_time.elapsed().as_millis() > self.get_attack_speed() as u128 {
+            self.last_shot_time = clock::GameTime::now();
+            true
+        } else {
+            false
         }
     }
     fn get_damage(&self) -> i32 {
-        match self {
-            Weapon::Pistol(p) => p.damage,
-            Weapon::Knife(k) => k.damage,
-            Weapon::Minigun(mg) => mg.damage,
-            _ => 0,
-        }
+        let bonus = self.sum_percent(|m| match m {
+            Modifier::Damage(p) => Some(*p),
+            _ => None,
+        });
+        self.archetype.base_stats().damage * (100 + bonus) / 100
     }
     fn get_range(&self) -> f32 {
-        match self {
-            Weapon::Pistol(p) => p.range,
-            Weapon::Minigun(mg) => mg.range,
-            _ => 0.,
+        let bonus = self.sum_percent(|m| match m {
+            Modifier::Range(p) => Some(*p),
+            _ => None,
+        });
+        self.archetype.base_stats().range * (100 + bonus) as f32 / 100.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn weapon_with(modifiers: Vec<Modifier>) -> Weapon {
+        let mut weapon = Weapon::new(Archetype::Pistol, &mut id::IdManager::new());
+        weapon.modifiers = modifiers;
+        weapon
+    }
+
+    #[test]
+    fn modifiers_change_the_final_stats() {
+        let base = Archetype::Pistol.base_stats();
+        let weapon = weapon_with(vec![
+            Modifier::Damage(50),
+            Modifier::AttackSpeed(100),
+            Modifier::Range(20),
+            Modifier::Burning,
+        ]);
+        assert_eq!(weapon.get_damage(), base.damage * 150 / 100);
+        assert_eq!(weapon.get_attack_speed(), base.attack_speed / 2);
+        assert_eq!(weapon.get_range(), base.range * 1.2);
+        assert!(weapon.is_burning());
+    }
+
+    #[test]
+    fn percent_modifiers_stack() {
+        let weapon = weapon_with(vec![Modifier::Lifesteal(3), Modifier::Lifesteal(4)]);
+        assert_eq!(weapon.get_lifesteal(), 7);
+        assert!(!weapon.is_piercing());
+    }
+
+    #[test]
+    fn rolls_get_the_modifiers_of_their_rarity() {
+        let mut id_manager = id::IdManager::new();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        for difficulty in [0, 20, 40, 100].iter() {
+            for _ in 0..200 {
+                let weapon =
+                    Weapon::roll(Archetype::Minigun, *difficulty, &mut id_manager, &mut rng);
+                assert_eq!(weapon.modifiers.len(), weapon.rarity.modifier_count());
+                for flag in [Modifier::Piercing, Modifier::Burning].iter() {
+                    assert!(weapon.modifiers.iter().filter(|m| *m == flag).count() <= 1);
+                }
+            }
         }
     }
+
+    #[test]
+    fn tooltip_shows_the_final_stats() {
+        let weapon = weapon_with(vec![Modifier::Damage(50), Modifier::Piercing]);
+        let tooltip = weapon.tooltip();
+        assert!(tooltip.starts_with("Common Pistol\n"));
+        assert!(tooltip.contains(&format!("Damage: {}", weapon.get_damage())));
+        assert!(tooltip.contains("+50% damage"));
+        assert!(tooltip.contains("Piercing"));
+    }
 }
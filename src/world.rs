@@ -0,0 +1,319 @@
+use glam::Vec2;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+use crate::{biome, bloc, id, map, mapgen, physics, status, tileset};
+
+pub const CHUNK_SIZE: i32 = 16; // in tiles
+const LOAD_RADIUS: i32 = 2; // in chunks, around the player
+const UNLOAD_RADIUS: i32 = 4; // further chunks are dropped
+const PATHFINDING_RADIUS: i32 = 1; // chunks around a monster it can plan a path through
+
+pub struct Chunk {
+    pub coords: (i32, i32),
+    pub grid: Vec<Vec<i32>>, // CHUNK_SIZE x CHUNK_SIZE
+    pub bloc_list: Vec<bloc::Bloc>,
+}
+
+/// Open world mode, a cave with no end generated chunk by chunk around the player
+pub struct ChunkedWorld {
+    pub tile_size: f32,
+    pub chunks: HashMap<(i32, i32), Chunk>,
+    pub ghost_tiles: Vec<f32>,
+    pub biome: biome::Biome,
+    config: mapgen::GenerationConfig,
+    noise: mapgen::LayeredNoise,
+    frequency: f64, // noise units per tile
+}
+
+impl ChunkedWorld {
+    pub fn new(tile_size: f32, seed: u64, config: &mapgen::GenerationConfig) -> Self {
+        let biome = biome::Biome::from_depth(0);
+        let mut config = config.for_depth(0);
+        config.liquid.get_or_insert(mapgen::Liquid::Water);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        ChunkedWorld {
+            tile_size,
+            chunks: HashMap::new(),
+            ghost_tiles: map::ghost_tiles(),
+            biome,
+            noise: mapgen::LayeredNoise::new(config.noise_octaves, &mut rng),
+            // Same cave size as on the floors
+            frequency: config.noise_frequency * 2. / config.width as f64,
+            config,
+        }
+    }
+    pub fn tile_of(&self, pos: Vec2) -> (i32, i32) {
+        (
+            (pos.x / self.tile_size).floor() as i32,
+            (pos.y / self.tile_size).floor() as i32,
+        )
+    }
+    pub fn chunk_of(&self, pos: Vec2) -> (i32, i32) {
+        let (x, y) = self.tile_of(pos);
+        (x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE))
+    }
+    pub fn tile_center(&self, x: i32, y: i32) -> Vec2 {
+        Vec2::new(
+            x as f32 * self.tile_size + self.tile_size / 2.,
+            y as f32 * self.tile_size + self.tile_size / 2.,
+        )
+    }
+    /// None if the chunk isn't loaded
+    pub fn material_at(&self, x: i32, y: i32) -> Option<i32> {
+        let coords = (x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE));
+        self.chunks.get(&coords).map(|chunk| {
+            chunk.grid[y.rem_euclid(CHUNK_SIZE) as usize][x.rem_euclid(CHUNK_SIZE) as usize]
+        })
+    }
+    /// Like map::Map::blocks_sound, tiles that aren't loaded count as walls
+    pub fn blocks_sound(&self, x: i32, y: i32) -> bool {
+        match self.material_at(x, y) {
+            Some(material) => !self.ghost_tiles.contains(&(material as f32)),
+            None => true,
+        }
+    }
+    /// Water and lava under the entity, like map::Map::bloc_effects. There are no traps here.
+    pub fn bloc_effects<E: physics::EntityTrait>(&mut self, entity: &mut E) {
+        let center = Vec2::from(physics::EntityTrait::get_hitbox(entity).center());
+        let (x, y) = self.tile_of(center);
+        let coords = self.chunk_of(center);
+        let chunk = match self.chunks.get_mut(&coords) {
+            Some(chunk) => chunk,
+            None => return,
+        };
+        let index = (y.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE + x.rem_euclid(CHUNK_SIZE)) as usize;
+        match &mut chunk.bloc_list[index] {
+            bloc::Bloc::Water(_w) => entity.apply_status(status::StatusKind::Slow),
+            bloc::Bloc::Lava(l) => l.damage(entity),
+            _ => {}
+        }
+    }
+    pub fn is_loaded(&self, pos: Vec2) -> bool {
+        self.chunks.contains_key(&self.chunk_of(pos))
+    }
+
+    /// Generates the chunks around the center and forgets the ones too far from it
+    pub fn update(&mut self, center: Vec2, id_manager: &mut id::IdManager) {
+        let (cx, cy) = self.chunk_of(center);
+        for y in cy - LOAD_RADIUS..=cy + LOAD_RADIUS {
+            for x in cx - LOAD_RADIUS..=cx + LOAD_RADIUS {
+                if !self.chunks.contains_key(&(x, y)) {
+                    let chunk = self.generate_chunk((x, y), id_manager);
+                    self.chunks.insert((x, y), chunk);
+                }
+            }
+        }
+        self.chunks.retain(|&(x, y), _| {
+            (x - cx).abs() <= UNLOAD_RADIUS && (y - cy).abs() <= UNLOAD_RADIUS
+        });
+    }
+    fn generate_chunk(&self, coords: (i32, i32), id_manager: &mut id::IdManager) -> Chunk {
+        let mut grid = vec![vec![0; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+        let mut bloc_list: Vec<bloc::Bloc> = Vec::new();
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let (tx, ty) = (coords.0 * CHUNK_SIZE + x, coords.1 * CHUNK_SIZE + y);
+                let material = self.generated_material(tx, ty);
+                grid[y as usize][x as usize] = material;
+
+                let mut tile = map::Tile::new(
+                    tx as f32 * self.tile_size,
+                    ty as f32 * self.tile_size,
+                    self.tile_size,
+                    material,
+                    self.ghost_tiles.contains(&(material as f32)),
+                    0.,
+                );
+                // The neighbours come from the noise too, loaded or not
+                let (variant, angle) =
+                    tileset::autotile(tx, ty, |x, y| self.generated_material(x, y));
+                tile.variant = variant;
+                tile.angle = angle;
+                let id = id_manager.get_new_id();
+                bloc_list.push(match material {
+                    4 => bloc::Bloc::Wall(bloc::Wall::new(id, tile)),
+                    9 => bloc::Bloc::Water(bloc::Water::new(id, tile)),
+                    18 => bloc::Bloc::Lava(bloc::Lava::new(id, tile)),
+                    _ => bloc::Bloc::Air(bloc::Air::new(id, tile)),
+                });
+            }
+        }
+        Chunk {
+            coords,
+            grid,
+            bloc_list,
+        }
+    }
+
+    /// The noise is sampled in world tiles, so the chunks fit together
+    fn generated_material(&self, x: i32, y: i32) -> i32 {
+        self.config.material_for(
+            self.noise
+                .get(x as f64 * self.frequency, y as f64 * self.frequency),
+        )
+    }
+
+    /// Blocs of the chunks touching the area, for collisions and ray casts
+    pub fn blocs_in(
+        &self,
+        area: ggez::graphics::Rect,
+    ) -> impl Iterator<Item = &bloc::Bloc> + Clone {
+        let min = self.chunk_of(Vec2::new(area.x, area.y));
+        let max = self.chunk_of(Vec2::new(area.right(), area.bottom()));
+        (min.1..=max.1)
+            .flat_map(move |y| (min.0..=max.0).map(move |x| (x, y)))
+            .filter_map(move |coords| self.chunks.get(&coords))
+            .flat_map(|chunk| chunk.bloc_list.iter())
+    }
+
+    /// Astar on the chunks around the start only, the target has to be in them
+    pub fn find_path(&self, from: Vec2, to: Vec2) -> physics::PathFindingResult {
+        let (cx, cy) = self.chunk_of(from);
+        let origin = (
+            (cx - PATHFINDING_RADIUS) * CHUNK_SIZE,
+            (cy - PATHFINDING_RADIUS) * CHUNK_SIZE,
+        );
+        let size = (PATHFINDING_RADIUS * 2 + 1) * CHUNK_SIZE;
+
+        let (tx, ty) = self.tile_of(to);
+        if tx < origin.0 || ty < origin.1 || tx >= origin.0 + size || ty >= origin.1 + size {
+            return physics::PathFindingResult::Fail;
+        }
+
+        // Unloaded chunks are walls
+        let mut grid = vec![vec![4; size as usize]; size as usize];
+        for y in 0..size {
+            for x in 0..size {
+                if let Some(material) = self.material_at(origin.0 + x, origin.1 + y) {
+                    grid[y as usize][x as usize] = material;
+                }
+            }
+        }
+
+        let shift = Vec2::new(origin.0 as f32, origin.1 as f32);
+        match physics::PathFinding::astar(
+            from / self.tile_size - shift,
+            Vec2::new((tx - origin.0) as f32, (ty - origin.1) as f32),
+            (grid, self.ghost_tiles.clone(), self.tile_size),
+        ) {
+            physics::PathFindingResult::Ok(path) => physics::PathFindingResult::Ok(
+                path.iter()
+                    .map(|point| *point + shift * self.tile_size)
+                    .collect(),
+            ),
+            physics::PathFindingResult::Fail => physics::PathFindingResult::Fail,
+        }
+    }
+    /// A walkable tile close enough to be reached by find_path
+    pub fn wander_target<R: Rng>(&self, from: Vec2, rng: &mut R) -> Option<Vec2> {
+        let range = PATHFINDING_RADIUS * CHUNK_SIZE;
+        self.random_open_position(from, 0, range, rng)
+    }
+    /// Center of a random walkable tile between the two distances (in tiles)
+    pub fn random_open_position<R: Rng>(
+        &self,
+        center: Vec2,
+        min: i32,
+        max: i32,
+        rng: &mut R,
+    ) -> Option<Vec2> {
+        let (x, y) = self.tile_of(center);
+        for _ in 0..50 {
+            let (dx, dy) = (rng.gen_range(-max..=max), rng.gen_range(-max..=max));
+            if dx.abs().max(dy.abs()) < min {
+                continue;
+            }
+            if let Some(material) = self.material_at(x + dx, y + dy) {
+                if self.ghost_tiles.contains(&(material as f32)) {
+                    return Some(self.tile_center(x + dx, y + dy));
+                }
+            }
+        }
+        None
+    }
+    /// The closest ground tile to the origin of the world
+    pub fn spawn_point(&mut self, id_manager: &mut id::IdManager) -> Vec2 {
+        self.update(Vec2::ZERO, id_manager);
+        let range = LOAD_RADIUS * CHUNK_SIZE;
+        for distance in 0..range {
+            for y in -distance..=distance {
+                for x in -distance..=distance {
+                    if x.abs().max(y.abs()) == distance && self.material_at(x, y) == Some(-1) {
+                        return self.tile_center(x, y);
+                    }
+                }
+            }
+        }
+        Vec2::ZERO
+    }
+
+    /// Only the chunks inside the view go to the sprite batches
+    pub fn draw(
+        &self,
+        ctx: &mut ggez::Context,
+        tileset: &mut tileset::Tileset,
+        draw_offset: Vec2,
+        view: ggez::graphics::Rect,
+        light_center: Vec2,
+    ) -> ggez::GameResult {
+        let tile_size_mult = self.tile_size / tileset::CELL_SIZE;
+        let time = ggez::timer::time_since_start(ctx).as_secs_f32();
+        let tint = self.biome.tint();
+        let light_radius = map::PLAYER_LIGHT_RADIUS * self.tile_size;
+        let chunk_size = CHUNK_SIZE as f32 * self.tile_size;
+        tileset.set_biome(self.biome);
+
+        for chunk in self.chunks.values() {
+            let chunk_rect = ggez::graphics::Rect::new(
+                chunk.coords.0 as f32 * chunk_size,
+                chunk.coords.1 as f32 * chunk_size,
+                chunk_size,
+                chunk_size,
+            );
+            if !chunk_rect.overlaps(&view) {
+                continue;
+            }
+            for bloc in chunk.bloc_list.iter() {
+                let tile = bloc.tile();
+                let src = match tileset.src_at(tile.material, tile.variant, time) {
+                    Some(src) => src,
+                    None => continue,
+                };
+                let d = physics::RayCasting::get_distance(
+                    light_center,
+                    Vec2::from(tile.hitbox.center()),
+                );
+                let light = self.biome.ambient_light().max(1. - d / light_radius);
+                tileset.batch.add(
+                    ggez::graphics::DrawParam::new()
+                        .src(src)
+                        .dest(ggez::mint::Point2::from_slice(&[
+                            tile.hitbox.center().x,
+                            tile.hitbox.center().y,
+                        ]))
+                        .scale(ggez::mint::Vector2::from_slice(&[
+                            tile_size_mult,
+                            tile_size_mult,
+                        ]))
+                        .offset(Vec2::new(0.5, 0.5))
+                        .rotation(tile.angle)
+                        .color(ggez::graphics::Color::new(
+                            light * tint.0,
+                            light * tint.1,
+                            light * tint.2,
+                            1.,
+                        )),
+                );
+            }
+        }
+
+        ggez::graphics::draw(
+            ctx,
+            &tileset.batch,
+            (draw_offset, 0., ggez::graphics::Color::WHITE),
+        )?;
+        tileset.batch.clear();
+        Ok(())
+    }
+}